@@ -4,9 +4,12 @@
 use anyhow::{bail, Result};
 use diem_types::transaction::ScriptFunction;
 use include_dir::{include_dir, Dir};
-use move_binary_format::file_format::CompiledModule;
+use move_binary_format::{
+    errors::{PartialVMError, VMError},
+    file_format::CompiledModule,
+};
 use once_cell::sync::Lazy;
-use std::{convert::TryFrom, path::PathBuf};
+use std::{convert::TryFrom, fmt, path::PathBuf};
 
 use bytecode_verifier::verify_module; //////// 0L ////////
 
@@ -134,24 +137,79 @@ pub fn name_for_script(bytes: &[u8]) -> Result<String> {
 //////// 0L ////////
 // Update stdlib with a byte string, used as part of the upgrade oracle
 pub fn import_stdlib(lib_bytes: &Vec<u8>) -> Vec<CompiledModule> {
-    let modules : Vec<CompiledModule> = bcs::from_bytes::<Vec<Vec<u8>>>(lib_bytes)
-        .unwrap_or(vec![]) // set as empty array if err occurred
-        .into_iter()
-        .map(|bytes| CompiledModule::deserialize(&bytes).unwrap())
-        .collect();
+    let module_bytes = bcs::from_bytes::<Vec<Vec<u8>>>(lib_bytes)
+        .unwrap_or(vec![]); // set as empty array if err occurred
+    verify_stdlib(module_bytes).expect("stdlib module failed to verify")
+}
+
+/// Why `import_stdlib_bounded` rejected an upgrade payload.
+#[derive(Debug)]
+pub enum StdlibImportError {
+    /// The payload is not a BCS encoded list of module blobs.
+    Malformed(bcs::Error),
+    /// The payload holds more modules than the caller accepts.
+    TooManyModules { count: usize, max: usize },
+    /// The module at `index` does not deserialize.
+    ModuleMalformed { index: usize, error: PartialVMError },
+    /// The module at `index` fails bytecode verification.
+    ModuleUnverified { index: usize, error: VMError },
+}
 
-    // verify the compiled module
+impl fmt::Display for StdlibImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StdlibImportError::Malformed(e) => {
+                write!(f, "stdlib payload is not a list of modules: {}", e)
+            }
+            StdlibImportError::TooManyModules { count, max } => write!(
+                f,
+                "stdlib payload has {} modules, more than the maximum of {}",
+                count, max
+            ),
+            StdlibImportError::ModuleMalformed { index, error } => {
+                write!(f, "stdlib module {} does not deserialize: {:?}", index, error)
+            }
+            StdlibImportError::ModuleUnverified { index, error } => {
+                write!(f, "stdlib module {} failed to verify: {:?}", index, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StdlibImportError {}
+
+/// Like `import_stdlib`, but fails instead of panicking on a payload which does not decode,
+/// holds more than `max_modules` modules, or holds a module which does not deserialize or
+/// verify. The module count is checked before any module is deserialized.
+pub fn import_stdlib_bounded(
+    lib_bytes: &[u8],
+    max_modules: usize,
+) -> Result<Vec<CompiledModule>, StdlibImportError> {
+    let module_bytes =
+        bcs::from_bytes::<Vec<Vec<u8>>>(lib_bytes).map_err(StdlibImportError::Malformed)?;
+    if module_bytes.len() > max_modules {
+        return Err(StdlibImportError::TooManyModules {
+            count: module_bytes.len(),
+            max: max_modules,
+        });
+    }
+    verify_stdlib(module_bytes)
+}
+
+fn verify_stdlib(module_bytes: Vec<Vec<u8>>) -> Result<Vec<CompiledModule>, StdlibImportError> {
     let mut verified_modules = vec![];
-    for module in modules {
-        verify_module(&module).expect("stdlib module failed to verify");
+    for (index, bytes) in module_bytes.iter().enumerate() {
+        let module = CompiledModule::deserialize(bytes)
+            .map_err(|error| StdlibImportError::ModuleMalformed { index, error })?;
+        verify_module(&module)
+            .map_err(|error| StdlibImportError::ModuleUnverified { index, error })?;
         // DependencyChecker::verify_module(&module, &verified_modules)
         //     .expect("stdlib module dependency failed to verify");
         verified_modules.push(module)
     }
-    verified_modules
+    Ok(verified_modules)
 }
 
-
 // //////// 0L ////////
 // pub fn create_upgrade_payload() {
 //   let mut module_path = PathBuf::from(STAGED_OUTPUT_PATH);
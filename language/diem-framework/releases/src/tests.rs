@@ -42,3 +42,40 @@ fn can_deserialize_and_verify_modules_from_all_versions() {
 }
 
 // TODO: tests to ensure script abis and error_descriptions can be correctly read
+
+#[test]
+fn import_stdlib_bounded_rejects_too_many_modules() {
+    let payload = bcs::to_bytes(&vec![vec![0u8]; 3]).unwrap();
+    assert!(crate::import_stdlib_bounded(&payload, 2).is_err());
+    assert!(crate::import_stdlib_bounded(&bcs::to_bytes(&Vec::<Vec<u8>>::new()).unwrap(), 2)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn import_stdlib_bounded_rejects_bad_payloads_without_panicking() {
+    use crate::{import_stdlib_bounded, StdlibImportError};
+    use move_binary_format::file_format::{empty_module, self_module_name};
+
+    assert!(matches!(
+        import_stdlib_bounded(b"not a module list", 2),
+        Err(StdlibImportError::Malformed(_))
+    ));
+
+    let garbage = bcs::to_bytes(&vec![vec![0u8]]).unwrap();
+    assert!(matches!(
+        import_stdlib_bounded(&garbage, 2),
+        Err(StdlibImportError::ModuleMalformed { index: 0, .. })
+    ));
+
+    // deserializes, but the duplicate identifier fails verification
+    let mut module = empty_module();
+    module.identifiers.push(self_module_name().to_owned());
+    let mut bytes = vec![];
+    module.freeze().unwrap().serialize(&mut bytes).unwrap();
+    let unverified = bcs::to_bytes(&vec![bytes]).unwrap();
+    assert!(matches!(
+        import_stdlib_bounded(&unverified, 2),
+        Err(StdlibImportError::ModuleUnverified { index: 0, .. })
+    ));
+}
@@ -2,8 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use diem_metrics::{
-    register_histogram, register_int_counter, register_int_counter_vec, Histogram, IntCounter,
-    IntCounterVec,
+    register_histogram, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge, Histogram, HistogramVec, IntCounter, IntCounterVec, IntGauge,
 };
 use once_cell::sync::Lazy;
 
@@ -65,8 +65,116 @@ pub static TXN_VALIDATION_SECONDS: Lazy<Histogram> = Lazy::new(|| {
 pub static TXN_GAS_USAGE: Lazy<Histogram> =
     Lazy::new(|| register_histogram!("diem_vm_txn_gas_usage", "Gas used per transaction").unwrap());
 
+/// Count the number of times the VM could not start, with a "reason" label naming the
+/// missing config.
+pub static VM_STARTUP_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "diem_vm_startup_failures",
+        "Number of VM startup failures",
+        &["reason"]
+    )
+    .unwrap()
+});
+
 /// Count the number of critical errors. This is not intended for display
 /// on a dashboard but rather for triggering alerts.
 pub static CRITICAL_ERRORS: Lazy<IntCounter> = Lazy::new(|| {
     register_int_counter!("diem_vm_critical_errors", "Number of critical errors").unwrap()
 });
+
+//////// 0L ////////
+/// Count the number of stdlib upgrades whose payload was published.
+pub static STDLIB_UPGRADE_APPLIED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "diem_vm_stdlib_upgrade_applied",
+        "Number of stdlib upgrades applied"
+    )
+    .unwrap()
+});
+
+/// Count the number of upgrade rounds that found no payload to apply.
+pub static STDLIB_UPGRADE_SKIPPED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "diem_vm_stdlib_upgrade_skipped",
+        "Number of stdlib upgrade rounds skipped for lack of a payload"
+    )
+    .unwrap()
+});
+
+/// Count the number of stdlib upgrades that failed. Intended for alerting.
+pub static STDLIB_UPGRADE_FAILED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "diem_vm_stdlib_upgrade_failed",
+        "Number of stdlib upgrades that failed"
+    )
+    .unwrap()
+});
+
+/// Count the number of stdlib upgrade payloads rejected for containing a module twice.
+pub static STDLIB_UPGRADE_DUPLICATE_MODULE: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "diem_vm_stdlib_upgrade_duplicate_module",
+        "Number of stdlib upgrade payloads containing a module id more than once"
+    )
+    .unwrap()
+});
+
+/// Count the number of upgrade rounds that left the payload in place for a later round,
+/// because the block could not apply it.
+pub static STDLIB_UPGRADE_DEFERRED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "diem_vm_stdlib_upgrade_deferred",
+        "Number of stdlib upgrade rounds that left the payload for a later round"
+    )
+    .unwrap()
+});
+
+/// Count the number of elected stdlib upgrade payloads reset without being applied, with a
+/// "reason" label naming the check the payload failed. A "malformed_resource" payload cannot
+/// be reset, so it is only skipped.
+pub static STDLIB_UPGRADE_REJECTED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "diem_vm_stdlib_upgrade_rejected",
+        "Number of elected stdlib upgrade payloads reset without being applied",
+        &["reason"]
+    )
+    .unwrap()
+});
+
+/// Number of modules published by the most recent stdlib upgrade.
+pub static STDLIB_MODULES_PUBLISHED_LAST: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "diem_vm_stdlib_modules_published_last",
+        "Number of modules published by the last stdlib upgrade"
+    )
+    .unwrap()
+});
+
+/// Count the number of blocks where the oracle tick ran out of its gas budget and was skipped.
+pub static ORACLE_TICK_SKIPPED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "diem_vm_oracle_tick_skipped",
+        "Number of oracle ticks skipped for exceeding their gas budget"
+    )
+    .unwrap()
+});
+
+/// Count the number of blocks proposed by an account outside the on-chain validator set.
+pub static UNKNOWN_BLOCK_PROPOSER: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "diem_vm_unknown_block_proposer",
+        "Number of blocks whose proposer is not in the validator set"
+    )
+    .unwrap()
+});
+
+/// Execution time of the prologue and epilogue Move calls, with a "function" label naming
+/// the call.
+pub static PROLOGUE_EPILOGUE_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "diem_vm_prologue_epilogue_seconds",
+        "Execution time of prologue and epilogue Move calls",
+        &["function"]
+    )
+    .unwrap()
+});
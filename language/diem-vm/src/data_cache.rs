@@ -20,7 +20,7 @@ use move_core_types::{
     language_storage::{ModuleId, StructTag},
 };
 use move_vm_runtime::data_cache::MoveStorage;
-use std::collections::btree_map::BTreeMap;
+use std::{cell::RefCell, collections::btree_map::BTreeMap};
 
 /// A local cache for a given a `StateView`. The cache is private to the Diem layer
 /// but can be used as a one shot cache for systems that need a simple `RemoteCache`
@@ -164,3 +164,93 @@ impl<'a, S: StateView> ConfigStorage for RemoteStorage<'a, S> {
         self.get(&access_path).ok()?
     }
 }
+
+//////// 0L ////////
+/// Remembers `get_resource` reads from the underlying storage for one block, so the VM's
+/// block hooks read each resource at most once. It does not see writes made during the
+/// block, so build a new one per block and never keep it across blocks.
+pub struct BlockResourceCache<'a, S> {
+    storage: &'a S,
+    resources: RefCell<BTreeMap<(AccountAddress, StructTag), Option<Vec<u8>>>>,
+}
+
+impl<'a, S: MoveStorage> BlockResourceCache<'a, S> {
+    pub fn new(storage: &'a S) -> Self {
+        Self {
+            storage,
+            resources: RefCell::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl<'a, S: MoveStorage> MoveStorage for BlockResourceCache<'a, S> {
+    fn get_module(&self, module_id: &ModuleId) -> VMResult<Option<Vec<u8>>> {
+        self.storage.get_module(module_id)
+    }
+
+    fn get_resource(
+        &self,
+        address: &AccountAddress,
+        struct_tag: &StructTag,
+    ) -> PartialVMResult<Option<Vec<u8>>> {
+        let key = (*address, struct_tag.clone());
+        if let Some(blob) = self.resources.borrow().get(&key) {
+            return Ok(blob.clone());
+        }
+        // errors are not cached, so a transient storage failure is retried
+        let blob = self.storage.get_resource(address, struct_tag)?;
+        self.resources.borrow_mut().insert(key, blob.clone());
+        Ok(blob)
+    }
+}
+
+//////// 0L ////////
+/// One read made through a `TracingStorage`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StorageRead {
+    Resource(AccountAddress, StructTag),
+    Module(ModuleId),
+}
+
+/// Passes every read through to the underlying storage unchanged, recording what was read,
+/// so developers can see the read set of a transaction. Only for debugging; the plain
+/// storage types carry none of this overhead.
+pub struct TracingStorage<'a, S> {
+    storage: &'a S,
+    reads: RefCell<Vec<StorageRead>>,
+}
+
+impl<'a, S: MoveStorage> TracingStorage<'a, S> {
+    pub fn new(storage: &'a S) -> Self {
+        Self {
+            storage,
+            reads: RefCell::new(vec![]),
+        }
+    }
+
+    /// Every read so far, in the order it was made.
+    pub fn reads(&self) -> Vec<StorageRead> {
+        self.reads.borrow().clone()
+    }
+
+    fn record(&self, read: StorageRead) {
+        debug!("[VM] storage read: {:?}", read);
+        self.reads.borrow_mut().push(read);
+    }
+}
+
+impl<'a, S: MoveStorage> MoveStorage for TracingStorage<'a, S> {
+    fn get_module(&self, module_id: &ModuleId) -> VMResult<Option<Vec<u8>>> {
+        self.record(StorageRead::Module(module_id.clone()));
+        self.storage.get_module(module_id)
+    }
+
+    fn get_resource(
+        &self,
+        address: &AccountAddress,
+        struct_tag: &StructTag,
+    ) -> PartialVMResult<Option<Vec<u8>>> {
+        self.record(StorageRead::Resource(*address, struct_tag.clone()));
+        self.storage.get_resource(address, struct_tag)
+    }
+}
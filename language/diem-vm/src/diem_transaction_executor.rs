@@ -3,16 +3,14 @@
 
 use crate::{
     counters::*,
-    data_cache::StateViewCache,
+    data_cache::{BlockResourceCache, StateViewCache},
     diem_transaction_validator::validate_signature_checked_transaction,
     diem_vm::{
-        charge_global_write_gas_usage, convert_changeset_and_events, get_transaction_output,
-        DiemVMImpl, DiemVMInternals,
+        charge_global_write_gas_usage, convert_changeset_and_events, gas_breakdown, gas_used,
+        get_transaction_output, writeset_triggers_reconfig, DiemVMImpl, DiemVMInternals,
     },
-    errors::expect_only_successful_execution,
     logging::AdapterLogSchema,
     script_to_script_function,
-    system_module_names::*,
     transaction_metadata::TransactionMetadata,
     VMExecutor,
 };
@@ -22,7 +20,7 @@ use diem_types::{
     access_path::AccessPath,
     account_config,
     block_metadata::BlockMetadata,
-    on_chain_config::DIEM_VERSION_3,
+    on_chain_config::CurrencyGasPriceBounds,
     transaction::{
         ChangeSet, Module, SignatureCheckedTransaction, Transaction, TransactionArgument,
         TransactionOutput, TransactionPayload, TransactionStatus, WriteSetPayload,
@@ -31,12 +29,12 @@ use diem_types::{
     write_set::{WriteSet, WriteSetMut},
 };
 use fail::fail_point;
+use move_binary_format::{errors::Location, CompiledModule};
 use move_core_types::{
     account_address::AccountAddress,
-    gas_schedule::GasAlgebra,
+    gas_schedule::{CostTable, GasAlgebra},
     identifier::IdentStr,
     transaction_argument::convert_txn_args,
-    value::{serialize_values, MoveValue},
 };
 use move_vm_runtime::{data_cache::MoveStorage, logging::LogContext, session::Session};
 use move_vm_types::gas_schedule::GasStatus;
@@ -53,10 +51,47 @@ impl DiemVM {
         Self(DiemVMImpl::new(state))
     }
 
+    //////// 0L ////////
+    /// See [`DiemVMImpl::new_without_ol_hooks`].
+    pub fn new_without_ol_hooks<S: StateView>(state: &S) -> Self {
+        Self(DiemVMImpl::new_without_ol_hooks(state))
+    }
+
     pub fn internals(&self) -> DiemVMInternals {
         DiemVMInternals::new(&self.0)
     }
 
+    //////// 0L ////////
+    /// See [`DiemVMImpl::with_upgrade_payload_address`].
+    pub fn with_upgrade_payload_address(self, address: AccountAddress) -> Self {
+        Self(self.0.with_upgrade_payload_address(address))
+    }
+
+    /// See [`DiemVMImpl::with_non_core_upgrade_modules`].
+    pub fn with_non_core_upgrade_modules(self) -> Self {
+        Self(self.0.with_non_core_upgrade_modules())
+    }
+
+    /// See [`DiemVMImpl::with_overridden_gas_schedule`].
+    pub fn with_overridden_gas_schedule(self, table: CostTable) -> Self {
+        Self(self.0.with_overridden_gas_schedule(table))
+    }
+
+    /// See [`DiemVMImpl::with_gas_breakdown`].
+    pub fn with_gas_breakdown(self) -> Self {
+        Self(self.0.with_gas_breakdown())
+    }
+
+    /// See [`DiemVMImpl::with_currency_gas_price_bounds`].
+    pub fn with_currency_gas_price_bounds(self, bounds: CurrencyGasPriceBounds) -> Self {
+        Self(self.0.with_currency_gas_price_bounds(bounds))
+    }
+
+    /// See [`DiemVMImpl::with_proposer_validation`].
+    pub fn with_proposer_validation(self) -> Self {
+        Self(self.0.with_proposer_validation())
+    }
+
     /// Generates a transaction output for a transaction that encountered errors during the
     /// execution process. This is public for now only for tests.
     pub fn failed_transaction_cleanup<S: MoveStorage>(
@@ -88,7 +123,6 @@ impl DiemVM {
         account_currency_symbol: &IdentStr,
         log_context: &impl LogContext,
     ) -> (VMStatus, TransactionOutput) {
-        gas_status.set_metering(false);
         let mut session = self.0.new_session(storage);
         match TransactionStatus::from(error_code.clone()) {
             TransactionStatus::Keep(status) => {
@@ -141,6 +175,23 @@ impl DiemVM {
             log_context,
         )?;
 
+        //////// 0L ////////
+        if self.0.gas_breakdown_enabled() {
+            let breakdown = gas_breakdown(
+                &gas_status.cost_table().gas_constants,
+                txn_data.transaction_size(),
+                session.num_mutated_accounts(&txn_data.sender()),
+                gas_used(txn_data.max_gas_amount(), gas_status.remaining_gas())?,
+            );
+            info!(
+                *log_context,
+                intrinsic = breakdown.intrinsic,
+                execution = breakdown.execution,
+                writes = breakdown.writes,
+                "[VM] Gas breakdown"
+            );
+        }
+
         Ok((
             VMStatus::Executed,
             get_transaction_output(
@@ -174,62 +225,8 @@ impl DiemVM {
                 .charge_intrinsic_gas(txn_data.transaction_size())
                 .map_err(|e| e.into_vm_status())?;
 
-            match payload {
-                TransactionPayload::Script(script) => {
-                    let diem_version = self.0.get_diem_version()?;
-                    let remapped_script =
-                        if diem_version < diem_types::on_chain_config::DIEM_VERSION_2 {
-                            None
-                        } else {
-                            script_to_script_function::remapping(script.code())
-                        };
-                    let mut senders = vec![txn_data.sender()];
-                    if diem_version >= DIEM_VERSION_3 {
-                        senders.extend(txn_data.secondary_signers());
-                    }
-                    match remapped_script {
-                        // We are in this case before VERSION_2
-                        // or if there is no remapping for the script
-                        None => session.execute_script(
-                            script.code().to_vec(),
-                            script.ty_args().to_vec(),
-                            convert_txn_args(script.args()),
-                            senders,
-                            gas_status,
-                            log_context,
-                        ),
-                        Some((module, function)) => session.execute_script_function(
-                            module,
-                            function,
-                            script.ty_args().to_vec(),
-                            convert_txn_args(script.args()),
-                            senders,
-                            gas_status,
-                            log_context,
-                        ),
-                    }
-                }
-                TransactionPayload::ScriptFunction(script_fn) => {
-                    let diem_version = self.0.get_diem_version()?;
-                    let mut senders = vec![txn_data.sender()];
-                    if diem_version >= DIEM_VERSION_3 {
-                        senders.extend(txn_data.secondary_signers());
-                    }
-                    session.execute_script_function(
-                        script_fn.module(),
-                        script_fn.function(),
-                        script_fn.ty_args().to_vec(),
-                        script_fn.args().to_vec(),
-                        senders,
-                        gas_status,
-                        log_context,
-                    )
-                }
-                TransactionPayload::Module(_) | TransactionPayload::WriteSet(_) => {
-                    return Err(VMStatus::Error(StatusCode::UNREACHABLE));
-                }
-            }
-            .map_err(|e| e.into_vm_status())?;
+            self.0
+                .execute_script_payload(&mut session, gas_status, txn_data, payload, log_context)?;
 
             charge_global_write_gas_usage(gas_status, &session, &txn_data.sender())?;
 
@@ -258,6 +255,12 @@ impl DiemVM {
             ))
         });
 
+        // Checked against the address the module declares, before it is replaced by the
+        // address the publishing option publishes at.
+        let declared = CompiledModule::deserialize(module.code())
+            .map_err(|e| e.finish(Location::Undefined).into_vm_status())?;
+        self.0.check_publish_allowed(declared.self_id().address(), log_context)?;
+
         // Publish the module
         let module_address = if self.0.publishing_option(log_context)?.is_open_module() {
             txn_data.sender()
@@ -386,16 +389,15 @@ impl DiemVM {
             WriteSetPayload::Direct(change_set) => change_set.clone(),
             WriteSetPayload::Script { script, execute_as } => {
                 let mut tmp_session = self.0.new_session(storage);
-                let diem_version = self.0.get_diem_version().map_err(Err)?;
+                let capabilities = self.0.get_version_capabilities(log_context).map_err(Err)?;
                 let senders = match txn_sender {
                     None => vec![*execute_as],
                     Some(sender) => vec![sender, *execute_as],
                 };
-                let remapped_script = if diem_version < diem_types::on_chain_config::DIEM_VERSION_2
-                {
-                    None
-                } else {
+                let remapped_script = if capabilities.supports_script_functions() {
                     script_to_script_function::remapping(script.code())
+                } else {
+                    None
                 };
                 let execution_result = match remapped_script {
                     // We are in this case before VERSION_2
@@ -487,56 +489,57 @@ impl DiemVM {
         };
         let mut gas_status = GasStatus::new_unmetered();
         let mut session = self.0.new_session(storage);
+        //////// 0L ////////
+        // Resource reads made by the 0L hooks, for this block only.
+        let block_cache = BlockResourceCache::new(storage);
 
-        let (round, timestamp, previous_vote, proposer) = block_metadata.clone().into_inner();
+        let round = block_metadata.round();
         
         println!("====================================== {} ======================================", round);
         
-        let args = serialize_values(&vec![
-            MoveValue::Signer(txn_data.sender),
-            MoveValue::U64(round),
-            MoveValue::U64(timestamp),
-            MoveValue::Vector(previous_vote.into_iter().map(MoveValue::Address).collect()),
-            MoveValue::Address(proposer),
-        ]);
-
-        session
-            .execute_function(
-                &DIEM_BLOCK_MODULE,
-                &BLOCK_PROLOGUE,
-                vec![],
-                args,
-                &mut gas_status,
-                log_context,
-            )
-            .map(|_return_vals| ())
-            .or_else(|e| {
-                expect_only_successful_execution(e, BLOCK_PROLOGUE.as_str(), log_context)
-            })?;
-
         //////// 0L ////////
-        if round == 2 {
-            // Consensus checking for oracle outcome
-            self.0.tick_oracle_consensus(
-                &mut session,
-                block_metadata.clone(),
-                &txn_data,
-                &mut gas_status,
-                log_context,
-            )?;
+        self.0.validate_proposer(&block_cache, block_metadata.proposer(), log_context);
+        self.0.run_block_prologue(
+            &mut session,
+            block_metadata.clone(),
+            &mut gas_status,
+            log_context,
+        )?;
 
-            //////// 0L ////////
-            // Apply upgrade for Upgrade oracle
-            self.0.apply_stdlib_upgrade(
+        //////// 0L ////////
+        // Consensus checking for oracle outcome, at the configured `oracle_tick_round`
+        let tick_kept = self.0.tick_oracle_consensus(
+            &mut session,
+            &block_cache,
+            block_metadata.clone(),
+            &txn_data,
+            &mut gas_status,
+            log_context,
+        )?;
+        if !tick_kept {
+            // the tick ran out of its gas budget partway, so its writes are dropped with the
+            // session and the block goes on from the prologue alone
+            session = self.0.new_session(storage);
+            gas_status = GasStatus::new_unmetered();
+            self.0.run_block_prologue(
                 &mut session,
-                &storage,
                 block_metadata.clone(),
-                &txn_data,
                 &mut gas_status,
                 log_context,
             )?;
         }
 
+        //////// 0L ////////
+        // Apply upgrade for Upgrade oracle, at the configured apply round
+        self.0.apply_stdlib_upgrade(
+            &mut session,
+            &block_cache,
+            block_metadata.clone(),
+            &txn_data,
+            &mut gas_status,
+            log_context,
+        )?;
+
         SYSTEM_TRANSACTIONS_EXECUTED.inc();
 
         let output = get_transaction_output(
@@ -609,12 +612,13 @@ impl DiemVM {
             Err(e) => return e,
         };
 
-        // Run the epilogue function.
+        // Run the epilogue function. Whether to reconfigure is derived from what the write set
+        // actually touches rather than from the payload kind.
         let mut session = self.0.new_session(storage);
         self.0.run_writeset_epilogue(
             &mut session,
             &txn_data,
-            writeset_payload.should_trigger_reconfiguration_by_default(),
+            writeset_triggers_reconfig(change_set.write_set()),
             log_context,
         )?;
 
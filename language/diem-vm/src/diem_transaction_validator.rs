@@ -2,15 +2,19 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    counters::*, create_access_path, data_cache::StateViewCache, diem_vm::DiemVMImpl,
-    transaction_metadata::TransactionMetadata, VMValidator,
+    counters::*,
+    create_access_path,
+    data_cache::StateViewCache,
+    diem_vm::{default_gas_currency_symbol, DiemVMImpl},
+    transaction_metadata::TransactionMetadata,
+    VMValidator,
 };
 use diem_logger::prelude::*;
 use diem_state_view::StateView;
 use diem_types::{
     account_address::AccountAddress,
     account_config::{self, CurrencyInfoResource, RoleId},
-    on_chain_config::{DiemVersion, VMConfig, VMPublishingOption, DIEM_VERSION_2, DIEM_VERSION_3},
+    on_chain_config::{DiemVersion, VMConfig, VMPublishingOption},
     transaction::{
         GovernanceRole, SignatureCheckedTransaction, SignedTransaction, TransactionPayload,
         VMValidatorResult,
@@ -49,6 +53,25 @@ impl DiemVMValidator {
             publishing_option,
         ))
     }
+
+    //////// 0L ////////
+    /// See [`DiemVMImpl::validate_transaction_prologue`].
+    pub fn validate_transaction_prologue<S: StateView>(
+        &self,
+        state: &S,
+        payload: &TransactionPayload,
+        txn_data: &TransactionMetadata,
+        account_currency_symbol: &IdentStr,
+        log_context: &impl LogContext,
+    ) -> Result<(), VMStatus> {
+        self.0.validate_transaction_prologue(
+            state,
+            payload,
+            txn_data,
+            account_currency_symbol,
+            log_context,
+        )
+    }
 }
 
 // VMValidator external API
@@ -120,6 +143,19 @@ fn get_account_role(sender: AccountAddress, remote_cache: &StateViewCache) -> Go
     GovernanceRole::NonGovernanceRole
 }
 
+//////// 0L ////////
+/// The gas currency named by `currency_code_string`, or `default_gas_currency_symbol()` when
+/// the transaction leaves it empty.
+pub(crate) fn resolve_gas_currency_code(
+    currency_code_string: &str,
+) -> Result<Identifier, VMStatus> {
+    if currency_code_string.is_empty() {
+        return Ok(default_gas_currency_symbol().to_owned());
+    }
+    account_config::from_currency_code_string(currency_code_string)
+        .map_err(|_| VMStatus::Error(StatusCode::INVALID_GAS_SPECIFIER))
+}
+
 pub(crate) fn validate_signature_checked_transaction<S: MoveStorage>(
     vm: &DiemVMImpl,
     mut session: &mut Session<S>,
@@ -128,7 +164,7 @@ pub(crate) fn validate_signature_checked_transaction<S: MoveStorage>(
     allow_too_new: bool,
     log_context: &impl LogContext,
 ) -> Result<(u64, Identifier), VMStatus> {
-    if transaction.is_multi_agent() && vm.get_diem_version()? < DIEM_VERSION_3 {
+    if transaction.is_multi_agent() && !vm.get_version_capabilities(log_context)?.supports_multi_agent() {
         // Multi agent is not allowed under this version
         return Err(VMStatus::Error(StatusCode::FEATURE_UNDER_GATING));
     }
@@ -138,13 +174,7 @@ pub(crate) fn validate_signature_checked_transaction<S: MoveStorage>(
     }
 
     let gas_price = transaction.gas_unit_price();
-    let currency_code_string = transaction.gas_currency_code();
-    let currency_code = match account_config::from_currency_code_string(currency_code_string) {
-        Ok(code) => code,
-        Err(_) => {
-            return Err(VMStatus::Error(StatusCode::INVALID_GAS_SPECIFIER));
-        }
-    };
+    let currency_code = resolve_gas_currency_code(transaction.gas_currency_code())?;
 
     let normalized_gas_price = match get_currency_info(&currency_code, remote_cache) {
         Ok(info) => info.convert_to_xdx(gas_price),
@@ -156,20 +186,20 @@ pub(crate) fn validate_signature_checked_transaction<S: MoveStorage>(
     let txn_data = TransactionMetadata::new(transaction);
     let prologue_status = match transaction.payload() {
         TransactionPayload::Script(_) => {
-            vm.check_gas(&txn_data, log_context)?;
+            vm.check_gas_in_currency(&txn_data, &currency_code, log_context)?;
             vm.run_script_prologue(&mut session, &txn_data, &currency_code, log_context)
         }
         TransactionPayload::ScriptFunction(_) => {
             // gate the behavior until the Diem version is ready
-            if vm.get_diem_version()? < DIEM_VERSION_2 {
+            if !vm.get_version_capabilities(log_context)?.supports_script_functions() {
                 return Err(VMStatus::Error(StatusCode::FEATURE_UNDER_GATING));
             }
             // NOTE: Script and ScriptFunction shares the same prologue
-            vm.check_gas(&txn_data, log_context)?;
+            vm.check_gas_in_currency(&txn_data, &currency_code, log_context)?;
             vm.run_script_prologue(&mut session, &txn_data, &currency_code, log_context)
         }
         TransactionPayload::Module(_module) => {
-            vm.check_gas(&txn_data, log_context)?;
+            vm.check_gas_in_currency(&txn_data, &currency_code, log_context)?;
             vm.run_module_prologue(&mut session, &txn_data, &currency_code, log_context)
         }
         TransactionPayload::WriteSet(_cs) => {
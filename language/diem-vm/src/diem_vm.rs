@@ -4,35 +4,58 @@
 use crate::{
     access_path_cache::AccessPathCache,
     counters::*,
-    data_cache::RemoteStorage, 
-    errors::{convert_epilogue_error, convert_prologue_error, expect_only_successful_execution},
+    data_cache::{RemoteStorage, TracingStorage},
+    errors::{
+        convert_epilogue_error, convert_prologue_error, expect_only_successful_execution,
+        startup_failure, StartupFailure,
+    },
+    logging::AdapterLogSchema,
+    script_to_script_function,
     system_module_names::*,
-    transaction_metadata::TransactionMetadata,
+    transaction_metadata::{auth_key_from_preimage, TransactionMetadata},
 };
-use diem_crypto::HashValue;
+use diem_crypto::{ed25519::ED25519_PUBLIC_KEY_LENGTH, HashValue};
 use diem_logger::prelude::*;
 use diem_state_view::StateView;
 use diem_types::{
-    account_config, 
+    access_path::{AccessPath, Path},
+    account_config::{self, AccountResource, CurrencyInfoResource},
     block_metadata::BlockMetadata, 
     contract_event::ContractEvent, 
+    diem_timestamp::DiemTimestampResource,
     event::EventKey, 
     on_chain_config::{
-        ConfigStorage, DiemVersion, OnChainConfig, VMConfig, VMPublishingOption, DIEM_VERSION_3,
+        config_address, new_epoch_event_key, ConfigStorage, ConfigurationResource,
+        CurrencyGasPriceBounds, DiemVersion, OnChainConfig, RegisteredCurrencies,
+        TransactionLimits, UpgradeConfig, VMConfig, VMPublishingOption, ValidatorSet,
+        VersionCapabilities, ON_CHAIN_CONFIG_REGISTRY,
     }, 
-    transaction::{TransactionOutput, TransactionStatus}, 
+    transaction::{
+        authenticator::Scheme, SignedTransaction, TransactionOutput, TransactionPayload,
+        TransactionStatus,
+    }, 
+    ol_oracle_upgrade_state::{OracleResource, VoteCount},
     ol_upgrade_payload::UpgradePayloadResource, 
     vm_status::{KeptVMStatus, StatusCode, VMStatus}, 
     write_set::{WriteOp, WriteSet, WriteSetMut}
 };
 use fail::fail_point;
-use move_binary_format::errors::Location;
+use move_binary_format::{
+    access::ModuleAccess,
+    errors::{Location, VMError, VMResult},
+    CompiledModule,
+};
 use move_core_types::{
     account_address::AccountAddress,
     effects::{ChangeSet as MoveChangeSet, Event as MoveEvent},
-    gas_schedule::{CostTable, GasAlgebra, GasCarrier, GasUnits, InternalGasUnits},
-    identifier::IdentStr,
-    language_storage::ModuleId,
+    gas_schedule::{
+        AbstractMemorySize, CostTable, GasAlgebra, GasCarrier, GasConstants, GasPrice, GasUnits,
+        InternalGasUnits,
+    },
+    identifier::{IdentStr, Identifier},
+    language_storage::{ModuleId, StructTag, TypeTag},
+    move_resource::{MoveResource, MoveStructType},
+    transaction_argument::convert_txn_args,
     value::{serialize_values, MoveValue},
 };
 use move_vm_runtime::{
@@ -42,8 +65,20 @@ use move_vm_runtime::{
     session::Session,
 };
 use move_vm_types::{gas_schedule::{calculate_intrinsic_gas, GasStatus}, data_store::DataStore};
-use std::{convert::TryFrom, sync::Arc};
-use diem_framework_releases::import_stdlib;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::{
+    collections::HashSet,
+    convert::TryFrom,
+    sync::{Arc, Mutex},
+};
+use diem_framework_releases::{import_stdlib_bounded, StdlibImportError};
+
+//////// 0L ////////
+/// Largest write set, in bytes of access paths and values, a transaction may commit.
+/// Well above what a stdlib upgrade writes. Every validator must use the same limit, so it
+/// is a constant rather than a node setting.
+pub const MAX_WRITE_SET_BYTES: u64 = 10 * 1024 * 1024;
 
 #[derive(Clone)]
 /// A wrapper to make VMRuntime standalone and thread safe.
@@ -52,6 +87,23 @@ pub struct DiemVMImpl {
     on_chain_config: Option<VMConfig>,
     version: Option<DiemVersion>,
     publishing_option: Option<VMPublishingOption>,
+    //////// 0L ////////
+    /// Account holding the `UpgradePayloadResource` read during stdlib upgrades.
+    upgrade_payload_address: AccountAddress,
+    /// When the oracle ticks and an upgrade is applied, read from chain.
+    upgrade_config: UpgradeConfig,
+    /// Whether the oracle tick and stdlib upgrade run during block processing.
+    /// Disabled, the VM processes blocks like stock Diem.
+    ol_hooks_enabled: bool,
+    /// Whether a stdlib upgrade may publish modules outside `CORE_CODE_ADDRESS`.
+    /// Only test frameworks set this.
+    allow_non_core_upgrade_modules: bool,
+    /// Whether to log how the gas of each successful user transaction splits up.
+    gas_breakdown_enabled: bool,
+    /// Gas unit price bounds for currencies which should not use the gas schedule's.
+    currency_gas_price_bounds: Option<CurrencyGasPriceBounds>,
+    /// Whether the block prologue reports a proposer outside the on-chain validator set.
+    proposer_validation_enabled: bool,
 }
 
 impl DiemVMImpl {
@@ -63,6 +115,13 @@ impl DiemVMImpl {
             on_chain_config: None,
             version: None,
             publishing_option: None,
+            upgrade_payload_address: account_config::diem_root_address(),
+            upgrade_config: UpgradeConfig::default(),
+            ol_hooks_enabled: true,
+            allow_non_core_upgrade_modules: false,
+            gas_breakdown_enabled: false,
+            currency_gas_price_bounds: None,
+            proposer_validation_enabled: false,
         };
         vm.load_configs_impl(&RemoteStorage::new(state));
         vm
@@ -79,9 +138,86 @@ impl DiemVMImpl {
             on_chain_config: Some(on_chain_config),
             version: Some(version),
             publishing_option: Some(publishing_option),
+            upgrade_payload_address: account_config::diem_root_address(),
+            upgrade_config: UpgradeConfig::default(),
+            ol_hooks_enabled: true,
+            allow_non_core_upgrade_modules: false,
+            gas_breakdown_enabled: false,
+            currency_gas_price_bounds: None,
+            proposer_validation_enabled: false,
         }
     }
 
+    //////// 0L ////////
+    /// Same as `new`, but the oracle tick and stdlib upgrade are no-ops.
+    pub fn new_without_ol_hooks<S: StateView>(state: &S) -> Self {
+        Self::new(state).without_ol_hooks()
+    }
+
+    /// Turns the oracle tick and stdlib upgrade into no-ops.
+    pub fn without_ol_hooks(mut self) -> Self {
+        self.ol_hooks_enabled = false;
+        self
+    }
+
+    //////// 0L ////////
+    /// Reads the upgrade payload from `address` instead of the diem root account.
+    /// Lets tests stage a payload without touching the core address.
+    pub fn with_upgrade_payload_address(mut self, address: AccountAddress) -> Self {
+        self.upgrade_payload_address = address;
+        self
+    }
+
+    /// Lets a stdlib upgrade publish each module at its own address, not just
+    /// `CORE_CODE_ADDRESS`, so test frameworks can stage upgrades of their own modules.
+    /// Never set this for a production node.
+    pub fn with_non_core_upgrade_modules(mut self) -> Self {
+        self.allow_non_core_upgrade_modules = true;
+        self
+    }
+
+    /// Uses `config` instead of the `UpgradeConfig` on chain.
+    #[cfg(test)]
+    pub(crate) fn with_upgrade_config(mut self, config: UpgradeConfig) -> Self {
+        self.upgrade_config = config;
+        self
+    }
+
+    /// Replaces the gas schedule of this VM instance with `table`, e.g. to replay recorded
+    /// transactions under a different gas model. Only this instance is affected; on-chain
+    /// state is untouched.
+    pub fn with_overridden_gas_schedule(mut self, table: CostTable) -> Self {
+        self.on_chain_config = Some(VMConfig {
+            gas_schedule: table,
+        });
+        self
+    }
+
+    /// Logs a `GasBreakdown` for every successful user transaction, for debugging fees.
+    /// Off by default, so the normal path only computes the gas used.
+    pub fn with_gas_breakdown(mut self) -> Self {
+        self.gas_breakdown_enabled = true;
+        self
+    }
+
+    pub(crate) fn gas_breakdown_enabled(&self) -> bool {
+        self.gas_breakdown_enabled
+    }
+
+    /// Uses `bounds` as the per-currency gas price bounds instead of the on-chain ones.
+    /// Only this instance is affected; on-chain state is untouched.
+    pub fn with_currency_gas_price_bounds(mut self, bounds: CurrencyGasPriceBounds) -> Self {
+        self.currency_gas_price_bounds = Some(bounds);
+        self
+    }
+
+    /// Logs and counts blocks whose proposer is not in the on-chain validator set. The block
+    /// is still executed as usual, so this never changes its output.
+    pub fn with_proposer_validation(mut self) -> Self {
+        self.proposer_validation_enabled = true;
+        self
+    }
+
     /// Provides access to some internal APIs of the Diem VM.
     pub fn internals(&self) -> DiemVMInternals {
         DiemVMInternals(self)
@@ -93,18 +229,83 @@ impl DiemVMImpl {
     ) -> Result<&VMPublishingOption, VMStatus> {
         self.publishing_option.as_ref().ok_or_else(|| {
             log_context.alert();
+            let reason = StartupFailure::PublishingOptionNotFound;
             error!(
                 *log_context,
+                code = reason.as_str(),
                 "VM Startup Failed. PublishingOption Not Found"
             );
-            VMStatus::Error(StatusCode::VM_STARTUP_FAILURE)
+            startup_failure(reason)
         })
     }
 
+    //////// 0L ////////
+    /// Check that every module in `core_system_modules()` is published in `remote_cache`,
+    /// returning the ones which are missing. A module that cannot be read counts as missing.
+    pub fn verify_core_modules_present<S: MoveStorage>(
+        &self,
+        remote_cache: &S,
+    ) -> Result<(), Vec<ModuleId>> {
+        let missing: Vec<ModuleId> = core_system_modules()
+            .into_iter()
+            .filter(|id| !matches!(remote_cache.get_module(id), Ok(Some(_))))
+            .collect();
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+
+    //////// 0L ////////
+    /// Sanity check a genesis `state` before it is distributed: the configs `new` loads and
+    /// the modules in `core_system_modules()` must all be present. Returns a description of
+    /// each missing item.
+    pub fn validate_genesis<S: StateView>(state: &S) -> Result<(), Vec<String>> {
+        let vm = Self::new(state);
+        let mut missing = vec![];
+        if vm.on_chain_config.is_none() {
+            missing.push("gas schedule (VMConfig)".to_string());
+        }
+        if vm.version.is_none() {
+            missing.push("DiemVersion".to_string());
+        }
+        if vm.publishing_option.is_none() {
+            missing.push("VMPublishingOption".to_string());
+        }
+        if let Err(modules) = vm.verify_core_modules_present(&RemoteStorage::new(state)) {
+            missing.extend(modules.iter().map(|id| format!("module {}", id)));
+        }
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+
+    /// Check that a module may be published at `module_address` under the on-chain
+    /// publishing option. Publishing at `CORE_CODE_ADDRESS` (governance) is always allowed;
+    /// any other address requires open module publishing.
+    pub fn check_publish_allowed(
+        &self,
+        module_address: &AccountAddress,
+        log_context: &impl LogContext,
+    ) -> Result<(), VMStatus> {
+        if *module_address == account_config::CORE_CODE_ADDRESS
+            || self.publishing_option(log_context)?.is_open_module()
+        {
+            Ok(())
+        } else {
+            Err(VMStatus::Error(StatusCode::INVALID_MODULE_PUBLISHER))
+        }
+    }
+
     fn load_configs_impl<S: ConfigStorage>(&mut self, data_cache: &S) {
         self.on_chain_config = VMConfig::fetch_config(data_cache);
         self.version = DiemVersion::fetch_config(data_cache);
         self.publishing_option = VMPublishingOption::fetch_config(data_cache);
+        self.currency_gas_price_bounds = CurrencyGasPriceBounds::fetch_config(data_cache);
+        self.upgrade_config = UpgradeConfig::fetch_config(data_cache).unwrap_or_default();
     }
 
     pub fn get_gas_schedule(&self, log_context: &impl LogContext) -> Result<&CostTable, VMStatus> {
@@ -113,100 +314,198 @@ impl DiemVMImpl {
             .map(|config| &config.gas_schedule)
             .ok_or_else(|| {
                 log_context.alert();
-                error!(*log_context, "VM Startup Failed. Gas Schedule Not Found");
-                VMStatus::Error(StatusCode::VM_STARTUP_FAILURE)
+                let reason = StartupFailure::GasScheduleNotFound;
+                error!(
+                    *log_context,
+                    code = reason.as_str(),
+                    "VM Startup Failed. Gas Schedule Not Found"
+                );
+                startup_failure(reason)
             })
     }
 
-    pub fn get_diem_version(&self) -> Result<DiemVersion, VMStatus> {
+    //////// 0L ////////
+    /// Currency codes registered on chain, i.e. the ones a transaction may name as its gas
+    /// currency. Read from `state`, not the configs loaded at startup. No registry, or an
+    /// empty one, gives an empty list.
+    pub fn approved_gas_currencies<S: StateView>(
+        &self,
+        state: &S,
+    ) -> Result<Vec<Identifier>, VMStatus> {
+        let blob = RemoteStorage::new(state)
+            .get(&RegisteredCurrencies::CONFIG_ID.access_path())
+            .map_err(|e| e.finish(Location::Undefined).into_vm_status())?;
+        match blob {
+            Some(bytes) => RegisteredCurrencies::from_bytes(&bytes)
+                .map(|registry| registry.currency_codes().to_vec())
+                .map_err(|_| VMStatus::Error(StatusCode::FAILED_TO_DESERIALIZE_RESOURCE)),
+            None => Ok(vec![]),
+        }
+    }
+
+    //////// 0L ////////
+    /// The fee for `gas_units` at `gas_price`, converted from XDX into the currency `symbol`
+    /// at its on-chain exchange rate, so tooling can show a fee before submission.
+    /// Read from `state`; nothing is executed.
+    pub fn convert_gas_to_currency<S: StateView>(
+        &self,
+        state: &S,
+        symbol: &IdentStr,
+        gas_units: u64,
+        gas_price: u64,
+    ) -> Result<u64, VMStatus> {
+        let blob = RemoteStorage::new(state)
+            .get(&CurrencyInfoResource::resource_path_for(symbol.to_owned()))
+            .map_err(|e| e.finish(Location::Undefined).into_vm_status())?
+            .ok_or(VMStatus::Error(StatusCode::CURRENCY_INFO_DOES_NOT_EXIST))?;
+        let currency_info = CurrencyInfoResource::try_from_bytes(&blob)
+            .map_err(|_| VMStatus::Error(StatusCode::FAILED_TO_DESERIALIZE_RESOURCE))?;
+        fee_in_currency(gas_units, gas_price, currency_info.to_xdx_exchange_rate())
+    }
+
+    //////// 0L ////////
+    /// The sequence number of the account at `address`, read from its `AccountResource`
+    /// in `state`, e.g. to build the account's next transaction.
+    pub fn account_sequence_number<S: StateView>(
+        &self,
+        state: &S,
+        address: AccountAddress,
+    ) -> Result<u64, VMStatus> {
+        let blob = RemoteStorage::new(state)
+            .get_resource(&address, &AccountResource::struct_tag())
+            .map_err(|e| e.finish(Location::Undefined).into_vm_status())?
+            .ok_or(VMStatus::Error(StatusCode::RESOURCE_DOES_NOT_EXIST))?;
+        bcs::from_bytes::<AccountResource>(&blob)
+            .map(|account| account.sequence_number())
+            .map_err(|_| VMStatus::Error(StatusCode::FAILED_TO_DESERIALIZE_RESOURCE))
+    }
+
+    /// The transaction size and gas bounds `check_gas` enforces, read from the loaded
+    /// gas schedule.
+    pub fn transaction_limits(
+        &self,
+        log_context: &impl LogContext,
+    ) -> Result<TransactionLimits, VMStatus> {
+        self.get_gas_schedule(log_context)
+            .map(|gas_schedule| TransactionLimits::from(&gas_schedule.gas_constants))
+    }
+
+    pub fn get_diem_version(&self, log_context: &impl LogContext) -> Result<DiemVersion, VMStatus> {
         self.version.clone().ok_or_else(|| {
-            CRITICAL_ERRORS.inc();
-            error!("VM Startup Failed. Diem Version Not Found");
-            VMStatus::Error(StatusCode::VM_STARTUP_FAILURE)
+            // counts towards CRITICAL_ERRORS, like the other config getters
+            log_context.alert();
+            let reason = StartupFailure::DiemVersionNotFound;
+            error!(
+                *log_context,
+                code = reason.as_str(),
+                "VM Startup Failed. Diem Version Not Found"
+            );
+            startup_failure(reason)
         })
     }
 
+    //////// 0L ////////
+    /// What the loaded `DiemVersion` supports.
+    pub fn get_version_capabilities(
+        &self,
+        log_context: &impl LogContext,
+    ) -> Result<VersionCapabilities, VMStatus> {
+        self.get_diem_version(log_context)
+            .map(|version| VersionCapabilities::new(&version))
+    }
+
+    //////// 0L ////////
+    /// The major number of the loaded `DiemVersion`, e.g. for display.
+    pub fn diem_version_number(&self, log_context: &impl LogContext) -> Result<u64, VMStatus> {
+        self.get_diem_version(log_context).map(|version| version.major)
+    }
+
+    //////// 0L ////////
+    /// Whether the loaded `DiemVersion` is `version` or later.
+    pub fn diem_version_at_least(
+        &self,
+        version: &DiemVersion,
+        log_context: &impl LogContext,
+    ) -> Result<bool, VMStatus> {
+        self.get_diem_version(log_context)
+            .map(|loaded| loaded >= *version)
+    }
+
     pub fn check_gas(
         &self,
         txn_data: &TransactionMetadata,
         log_context: &impl LogContext,
     ) -> Result<(), VMStatus> {
-        let gas_constants = &self.get_gas_schedule(log_context)?.gas_constants;
-        let raw_bytes_len = txn_data.transaction_size;
-        // The transaction is too large.
-        if txn_data.transaction_size.get() > gas_constants.max_transaction_size_in_bytes {
-            warn!(
-                *log_context,
-                "[VM] Transaction size too big {} (max {})",
-                raw_bytes_len.get(),
-                gas_constants.max_transaction_size_in_bytes,
-            );
-            return Err(VMStatus::Error(StatusCode::EXCEEDED_MAX_TRANSACTION_SIZE));
-        }
+        self.check_gas_with_options(txn_data, &CheckGasOptions::default(), log_context)
+    }
 
-        // Check is performed on `txn.raw_txn_bytes_len()` which is the same as
-        // `raw_bytes_len`
-        assume!(raw_bytes_len.get() <= gas_constants.max_transaction_size_in_bytes);
+    //////// 0L ////////
+    /// Same as `check_gas`, enforcing only the checks selected in `options`.
+    pub fn check_gas_with_options(
+        &self,
+        txn_data: &TransactionMetadata,
+        options: &CheckGasOptions,
+        log_context: &impl LogContext,
+    ) -> Result<(), VMStatus> {
+        let gas_constants = &self.get_gas_schedule(log_context)?.gas_constants;
+        check_gas_with_constants(gas_constants, txn_data, options, log_context)
+    }
 
-        // The submitted max gas units that the transaction can consume is greater than the
-        // maximum number of gas units bound that we have set for any
-        // transaction.
-        if txn_data.max_gas_amount().get() > gas_constants.maximum_number_of_gas_units.get() {
-            warn!(
-                *log_context,
-                "[VM] Gas unit error; max {}, submitted {}",
-                gas_constants.maximum_number_of_gas_units.get(),
-                txn_data.max_gas_amount().get(),
-            );
-            return Err(VMStatus::Error(
-                StatusCode::MAX_GAS_UNITS_EXCEEDS_MAX_GAS_UNITS_BOUND,
-            ));
-        }
+    //////// 0L ////////
+    /// The limits `check_gas` holds `txn_data` to, so a rejected sender can be told e.g. the
+    /// least `max_gas_amount` which would have been accepted.
+    pub fn compute_gas_thresholds(
+        &self,
+        txn_data: &TransactionMetadata,
+        log_context: &impl LogContext,
+    ) -> Result<GasThresholds, VMStatus> {
+        let gas_constants = &self.get_gas_schedule(log_context)?.gas_constants;
+        Ok(gas_thresholds(gas_constants, txn_data))
+    }
 
-        // The submitted transactions max gas units needs to be at least enough to cover the
-        // intrinsic cost of the transaction as calculated against the size of the
-        // underlying `RawTransaction`
-        let min_txn_fee =
-            gas_constants.to_external_units(calculate_intrinsic_gas(raw_bytes_len, gas_constants));
-        if txn_data.max_gas_amount().get() < min_txn_fee.get() {
-            warn!(
-                *log_context,
-                "[VM] Gas unit error; min {}, submitted {}",
-                min_txn_fee.get(),
-                txn_data.max_gas_amount().get(),
-            );
-            return Err(VMStatus::Error(
-                StatusCode::MAX_GAS_UNITS_BELOW_MIN_TRANSACTION_GAS_UNITS,
-            ));
-        }
-
-        // The submitted gas price is less than the minimum gas unit price set by the VM.
-        // NB: MIN_PRICE_PER_GAS_UNIT may equal zero, but need not in the future. Hence why
-        // we turn off the clippy warning.
-        #[allow(clippy::absurd_extreme_comparisons)]
-        let below_min_bound =
-            txn_data.gas_unit_price().get() < gas_constants.min_price_per_gas_unit.get();
-        if below_min_bound {
-            warn!(
-                *log_context,
-                "[VM] Gas unit error; min {}, submitted {}",
-                gas_constants.min_price_per_gas_unit.get(),
-                txn_data.gas_unit_price().get(),
-            );
-            return Err(VMStatus::Error(StatusCode::GAS_UNIT_PRICE_BELOW_MIN_BOUND));
+    //////// 0L ////////
+    /// Same as `check_gas`, but the gas unit price is checked against the bounds configured
+    /// on chain for `account_currency_symbol`, if there are any, instead of the gas
+    /// schedule's.
+    pub fn check_gas_in_currency(
+        &self,
+        txn_data: &TransactionMetadata,
+        account_currency_symbol: &IdentStr,
+        log_context: &impl LogContext,
+    ) -> Result<(), VMStatus> {
+        let mut gas_constants = self.get_gas_schedule(log_context)?.gas_constants.clone();
+        if let Some(bound) = self
+            .currency_gas_price_bounds
+            .as_ref()
+            .and_then(|bounds| bounds.bounds_for(account_currency_symbol))
+        {
+            gas_constants.min_price_per_gas_unit = GasPrice::new(bound.min_price_per_gas_unit);
+            gas_constants.max_price_per_gas_unit = GasPrice::new(bound.max_price_per_gas_unit);
         }
+        check_gas_with_constants(&gas_constants, txn_data, &CheckGasOptions::default(), log_context)
+    }
 
-        // The submitted gas price is greater than the maximum gas unit price set by the VM.
-        if txn_data.gas_unit_price().get() > gas_constants.max_price_per_gas_unit.get() {
-            warn!(
-                *log_context,
-                "[VM] Gas unit error; min {}, submitted {}",
-                gas_constants.max_price_per_gas_unit.get(),
-                txn_data.gas_unit_price().get(),
-            );
-            return Err(VMStatus::Error(StatusCode::GAS_UNIT_PRICE_ABOVE_MAX_BOUND));
+    /// Runs `check_gas` on every transaction in `txns`, loading the gas schedule once.
+    /// Every transaction gets a result, in the same order, so callers can reject selectively.
+    pub fn check_gas_batch(
+        &self,
+        txns: &[TransactionMetadata],
+        log_context: &impl LogContext,
+    ) -> Vec<Result<(), VMStatus>> {
+        match self.get_gas_schedule(log_context) {
+            Ok(gas_schedule) => txns
+                .iter()
+                .map(|txn_data| {
+                    check_gas_with_constants(
+                        &gas_schedule.gas_constants,
+                        txn_data,
+                        &CheckGasOptions::default(),
+                        log_context,
+                    )
+                })
+                .collect(),
+            Err(err) => txns.iter().map(|_| Err(err.clone())).collect(),
         }
-        Ok(())
     }
 
     /// Run the prologue of a transaction by calling into either `SCRIPT_PROLOGUE_NAME` function
@@ -218,61 +517,55 @@ impl DiemVMImpl {
         account_currency_symbol: &IdentStr,
         log_context: &impl LogContext,
     ) -> Result<(), VMStatus> {
+        let _timer = PROLOGUE_EPILOGUE_SECONDS
+            .with_label_values(&["script_prologue"])
+            .start_timer();
         let gas_currency_ty =
             account_config::type_tag_for_currency_code(account_currency_symbol.to_owned());
-        let txn_sequence_number = txn_data.sequence_number();
-        let txn_public_key = txn_data.authentication_key_preimage().to_vec();
-        let txn_gas_price = txn_data.gas_unit_price().get();
-        let txn_max_gas_units = txn_data.max_gas_amount().get();
-        let txn_expiration_timestamp_secs = txn_data.expiration_timestamp_secs();
-        let chain_id = txn_data.chain_id();
         let mut gas_status = GasStatus::new_unmetered();
-        let secondary_public_key_hashes: Vec<MoveValue> = txn_data
-            .secondary_authentication_key_preimages
-            .iter()
-            .map(|preimage| {
-                MoveValue::vector_u8(HashValue::sha3_256_of(&preimage.to_vec()).to_vec())
-            })
-            .collect();
-        let args = if self.get_diem_version()? >= DIEM_VERSION_3 && txn_data.is_multi_agent() {
-            vec![
-                MoveValue::Signer(txn_data.sender),
-                MoveValue::U64(txn_sequence_number),
-                MoveValue::vector_u8(txn_public_key),
-                MoveValue::vector_address(txn_data.secondary_signers()),
-                MoveValue::Vector(secondary_public_key_hashes),
-                MoveValue::U64(txn_gas_price),
-                MoveValue::U64(txn_max_gas_units),
-                MoveValue::U64(txn_expiration_timestamp_secs),
-                MoveValue::U8(chain_id.id()),
-            ]
+        let multi_agent =
+            self.get_version_capabilities(log_context)?.supports_multi_agent()
+                && txn_data.is_multi_agent();
+        if multi_agent {
+            check_secondary_signer_count(txn_data, log_context)?;
+        }
+        validate_auth_key_preimage(txn_data.authentication_key_preimage())?;
+        if multi_agent {
+            for preimage in &txn_data.secondary_authentication_key_preimages {
+                validate_auth_key_preimage(preimage)?;
+            }
+        }
+        let args = if multi_agent {
+            multi_agent_prologue_args(txn_data)
         } else {
-            vec![
-                MoveValue::Signer(txn_data.sender),
-                MoveValue::U64(txn_sequence_number),
-                MoveValue::vector_u8(txn_public_key),
-                MoveValue::U64(txn_gas_price),
-                MoveValue::U64(txn_max_gas_units),
-                MoveValue::U64(txn_expiration_timestamp_secs),
-                MoveValue::U8(chain_id.id()),
-                MoveValue::vector_u8(txn_data.script_hash.clone()),
-            ]
+            script_prologue_args(txn_data)
         };
-        let prologue_function_name =
-            if self.get_diem_version()? >= DIEM_VERSION_3 && txn_data.is_multi_agent() {
-                &MULTI_AGENT_SCRIPT_PROLOGUE_NAME
-            } else {
-                &SCRIPT_PROLOGUE_NAME
-            };
-        session
-            .execute_function(
-                &account_config::ACCOUNT_MODULE,
-                prologue_function_name,
-                vec![gas_currency_ty],
-                serialize_values(&args),
-                &mut gas_status,
-                log_context,
-            )
+        let prologue_function_name = if multi_agent {
+            &MULTI_AGENT_SCRIPT_PROLOGUE_NAME
+        } else {
+            &SCRIPT_PROLOGUE_NAME
+        };
+        let result = session.execute_function(
+            &account_config::ACCOUNT_MODULE,
+            prologue_function_name,
+            vec![gas_currency_ty],
+            serialize_values(&args),
+            &mut gas_status,
+            log_context,
+        );
+        // Diagnostic only. The arguments are evaluated only when trace logging is enabled,
+        // since previewing the session copies its pending writes.
+        trace!(
+            *log_context,
+            prologue = %prologue_function_name,
+            return_values = ?result,
+            events = ?result
+                .as_ref()
+                .ok()
+                .map(|_| session.preview_changes().map(|(_, events)| events)),
+            "[VM] Script prologue finished"
+        );
+        result
             .map(|_return_vals| ())
             .map_err(|err| expect_no_verification_errors(err, log_context))
             .or_else(|err| convert_prologue_error(err, log_context))
@@ -287,29 +580,19 @@ impl DiemVMImpl {
         account_currency_symbol: &IdentStr,
         log_context: &impl LogContext,
     ) -> Result<(), VMStatus> {
+        let _timer = PROLOGUE_EPILOGUE_SECONDS
+            .with_label_values(&["module_prologue"])
+            .start_timer();
+        validate_auth_key_preimage(txn_data.authentication_key_preimage())?;
         let gas_currency_ty =
             account_config::type_tag_for_currency_code(account_currency_symbol.to_owned());
-        let txn_sequence_number = txn_data.sequence_number();
-        let txn_public_key = txn_data.authentication_key_preimage().to_vec();
-        let txn_gas_price = txn_data.gas_unit_price().get();
-        let txn_max_gas_units = txn_data.max_gas_amount().get();
-        let txn_expiration_timestamp_secs = txn_data.expiration_timestamp_secs();
-        let chain_id = txn_data.chain_id();
         let mut gas_status = GasStatus::new_unmetered();
         session
             .execute_function(
                 &account_config::ACCOUNT_MODULE,
                 &MODULE_PROLOGUE_NAME,
                 vec![gas_currency_ty],
-                serialize_values(&vec![
-                    MoveValue::Signer(txn_data.sender),
-                    MoveValue::U64(txn_sequence_number),
-                    MoveValue::vector_u8(txn_public_key),
-                    MoveValue::U64(txn_gas_price),
-                    MoveValue::U64(txn_max_gas_units),
-                    MoveValue::U64(txn_expiration_timestamp_secs),
-                    MoveValue::U8(chain_id.id()),
-                ]),
+                serialize_values(&module_prologue_args(txn_data)),
                 &mut gas_status,
                 log_context,
             )
@@ -334,6 +617,9 @@ impl DiemVMImpl {
             ))
         });
 
+        let _timer = PROLOGUE_EPILOGUE_SECONDS
+            .with_label_values(&["success_epilogue"])
+            .start_timer();
         let gas_currency_ty =
             account_config::type_tag_for_currency_code(account_currency_symbol.to_owned());
         let txn_sequence_number = txn_data.sequence_number();
@@ -360,6 +646,29 @@ impl DiemVMImpl {
             .or_else(|err| convert_epilogue_error(err, log_context))
     }
 
+    //////// 0L ////////
+    /// Same as `run_success_epilogue`, but also returns the events the epilogue emitted. They
+    /// stay in the session either way and so end up in the transaction output; this only lets
+    /// the caller tell them apart from the events of the transaction body.
+    pub(crate) fn run_success_epilogue_with_events<S: MoveStorage>(
+        &self,
+        session: &mut Session<S>,
+        gas_status: &mut GasStatus,
+        txn_data: &TransactionMetadata,
+        account_currency_symbol: &IdentStr,
+        log_context: &impl LogContext,
+    ) -> Result<Vec<MoveEvent>, VMStatus> {
+        events_emitted_during(session, |session| {
+            self.run_success_epilogue(
+                session,
+                gas_status,
+                txn_data,
+                account_currency_symbol,
+                log_context,
+            )
+        })
+    }
+
     /// Run the failure epilogue of a transaction by calling into `USER_EPILOGUE_NAME` function
     /// stored in the `ACCOUNT_MODULE` on chain.
     pub(crate) fn run_failure_epilogue<S: MoveStorage>(
@@ -370,12 +679,21 @@ impl DiemVMImpl {
         account_currency_symbol: &IdentStr,
         log_context: &impl LogContext,
     ) -> Result<(), VMStatus> {
+        let _timer = PROLOGUE_EPILOGUE_SECONDS
+            .with_label_values(&["failure_epilogue"])
+            .start_timer();
         let gas_currency_ty =
             account_config::type_tag_for_currency_code(account_currency_symbol.to_owned());
         let txn_sequence_number = txn_data.sequence_number();
         let txn_gas_price = txn_data.gas_unit_price().get();
         let txn_max_gas_units = txn_data.max_gas_amount().get();
         let gas_remaining = gas_status.remaining_gas().get();
+        //////// 0L ////////
+        // The failed transaction may have used up all of its gas, and the epilogue is what
+        // charges the fee, so it runs unmetered. `gas_remaining` above is what gets billed.
+        // Failed transaction cleanup always turned metering off before calling this; doing it
+        // here only keeps that next to the call it is for.
+        gas_status.set_metering(false);
         session
             .execute_function(
                 &account_config::ACCOUNT_MODULE,
@@ -398,6 +716,222 @@ impl DiemVMImpl {
             })
     }
 
+    //////// 0L ////////
+    /// Admission check: run only the prologue for `payload` against `state`, i.e. sequence
+    /// number, gas reservation and authentication key checks, then discard the session.
+    /// The transaction body is never executed and nothing is written.
+    pub fn validate_transaction_prologue<S: StateView>(
+        &self,
+        state: &S,
+        payload: &TransactionPayload,
+        txn_data: &TransactionMetadata,
+        account_currency_symbol: &IdentStr,
+        log_context: &impl LogContext,
+    ) -> Result<(), VMStatus> {
+        let remote_storage = RemoteStorage::new(state);
+        let mut session = self.new_session(&remote_storage);
+        match payload {
+            TransactionPayload::Script(_) | TransactionPayload::ScriptFunction(_) => self
+                .run_script_prologue(&mut session, txn_data, account_currency_symbol, log_context),
+            TransactionPayload::Module(_) => self
+                .run_module_prologue(&mut session, txn_data, account_currency_symbol, log_context),
+            TransactionPayload::WriteSet(_) => {
+                self.run_writeset_prologue(&mut session, txn_data, log_context)
+            }
+        }
+    }
+
+    /// Runs the checks publishing `module_bytes` would, i.e. deserialization, compatibility
+    /// with a published version, bytecode verification and linking against the modules in
+    /// `state`, then discards the session. Nothing is published.
+    pub fn verify_module<S: StateView>(
+        &self,
+        state: &S,
+        module_bytes: &[u8],
+    ) -> Result<(), VMStatus> {
+        let log_context = AdapterLogSchema::new(state.id(), 0);
+        let module = CompiledModule::deserialize(module_bytes)
+            .map_err(|e| e.finish(Location::Undefined).into_vm_status())?;
+        let remote_storage = RemoteStorage::new(state);
+        let mut session = self.new_session(&remote_storage);
+        session
+            .publish_module(
+                module_bytes.to_vec(),
+                *module.address(),
+                &mut GasStatus::new_unmetered(),
+                &log_context,
+            )
+            .map_err(|e| {
+                warn!(log_context, "[VM] module {} failed to verify: {:?}", module.self_id(), e);
+                e.into_vm_status()
+            })
+    }
+
+    /// Runs a script or script function payload as the transaction's senders. Scripts are
+    /// remapped to script functions where the loaded `DiemVersion` supports them.
+    pub(crate) fn execute_script_payload<S: MoveStorage>(
+        &self,
+        session: &mut Session<S>,
+        gas_status: &mut GasStatus,
+        txn_data: &TransactionMetadata,
+        payload: &TransactionPayload,
+        log_context: &impl LogContext,
+    ) -> Result<(), VMStatus> {
+        match payload {
+            TransactionPayload::Script(script) => {
+                let capabilities = self.get_version_capabilities(log_context)?;
+                let remapped_script = if capabilities.supports_script_functions() {
+                    script_to_script_function::remapping(script.code())
+                } else {
+                    None
+                };
+                let mut senders = vec![txn_data.sender()];
+                if capabilities.supports_multi_agent() {
+                    senders.extend(txn_data.secondary_signers());
+                }
+                match remapped_script {
+                    // We are in this case before VERSION_2
+                    // or if there is no remapping for the script
+                    None => session.execute_script(
+                        script.code().to_vec(),
+                        script.ty_args().to_vec(),
+                        convert_txn_args(script.args()),
+                        senders,
+                        gas_status,
+                        log_context,
+                    ),
+                    Some((module, function)) => session.execute_script_function(
+                        module,
+                        function,
+                        script.ty_args().to_vec(),
+                        convert_txn_args(script.args()),
+                        senders,
+                        gas_status,
+                        log_context,
+                    ),
+                }
+            }
+            TransactionPayload::ScriptFunction(script_fn) => {
+                let mut senders = vec![txn_data.sender()];
+                if self.get_version_capabilities(log_context)?.supports_multi_agent() {
+                    senders.extend(txn_data.secondary_signers());
+                }
+                session.execute_script_function(
+                    script_fn.module(),
+                    script_fn.function(),
+                    script_fn.ty_args().to_vec(),
+                    script_fn.args().to_vec(),
+                    senders,
+                    gas_status,
+                    log_context,
+                )
+            }
+            TransactionPayload::Module(_) | TransactionPayload::WriteSet(_) => {
+                return Err(VMStatus::Error(StatusCode::UNREACHABLE));
+            }
+        }
+        .map_err(|e| e.into_vm_status())
+    }
+
+    //////// 0L ////////
+    /// Estimates the gas `txn` will use by running it against `state`, metered up to the
+    /// most gas any transaction may use, and charging the intrinsic and write gas as
+    /// execution would. This is an estimate, not a guarantee: the prologue and epilogue are
+    /// not run, and the state may change before the transaction is executed.
+    pub fn estimate_sufficient_gas<S: StateView>(
+        &self,
+        state: &S,
+        txn: &SignedTransaction,
+    ) -> Result<GasEstimate, VMStatus> {
+        let log_context = AdapterLogSchema::new(state.id(), 0);
+        let txn_data = TransactionMetadata::new(txn);
+        let gas_schedule = self.get_gas_schedule(&log_context)?;
+        let ceiling = gas_schedule.gas_constants.maximum_number_of_gas_units;
+        let mut gas_status = GasStatus::new(gas_schedule, ceiling);
+        let remote_storage = RemoteStorage::new(state);
+        let mut session = self.new_session(&remote_storage);
+
+        self.execute_simulated_payload(
+            &mut session,
+            &mut gas_status,
+            &txn_data,
+            txn.payload(),
+            &log_context,
+        )?;
+        charge_global_write_gas_usage(&mut gas_status, &session, &txn_data.sender())?;
+
+        let estimated = gas_used(ceiling, gas_status.remaining_gas())?;
+        Ok(GasEstimate {
+            estimated,
+            breakdown: gas_breakdown(
+                &gas_status.cost_table().gas_constants,
+                txn_data.transaction_size(),
+                session.num_mutated_accounts(&txn_data.sender()),
+                estimated,
+            ),
+            sufficient: estimated <= txn_data.max_gas_amount().get(),
+        })
+    }
+
+    //////// 0L ////////
+    /// Run `txn` against `state` in a throwaway session and diff the state it would leave
+    /// behind against `state`. As with `estimate_sufficient_gas`, neither the prologue nor the
+    /// epilogue runs, and nothing is written back to `state`.
+    pub fn simulate_with_diff<S: StateView>(
+        &self,
+        state: &S,
+        txn: &SignedTransaction,
+    ) -> Result<StateDiff, VMStatus> {
+        let log_context = AdapterLogSchema::new(state.id(), 0);
+        let txn_data = TransactionMetadata::new(txn);
+        let gas_schedule = self.get_gas_schedule(&log_context)?;
+        let mut gas_status = GasStatus::new(gas_schedule, txn_data.max_gas_amount());
+        let remote_storage = RemoteStorage::new(state);
+        let mut session = self.new_session(&remote_storage);
+
+        self.execute_simulated_payload(
+            &mut session,
+            &mut gas_status,
+            &txn_data,
+            txn.payload(),
+            &log_context,
+        )?;
+        let (changeset, events) = session.finish().map_err(|e| e.into_vm_status())?;
+        let (write_set, _events) = convert_changeset_and_events_cached(&mut (), changeset, events)?;
+        state_diff(state, &write_set)
+    }
+
+    //////// 0L ////////
+    /// Charge intrinsic gas for `txn_data` and run `payload` in `session`, for the simulations
+    /// above.
+    fn execute_simulated_payload<S: MoveStorage>(
+        &self,
+        session: &mut Session<S>,
+        gas_status: &mut GasStatus,
+        txn_data: &TransactionMetadata,
+        payload: &TransactionPayload,
+        log_context: &AdapterLogSchema,
+    ) -> Result<(), VMStatus> {
+        gas_status
+            .charge_intrinsic_gas(txn_data.transaction_size())
+            .map_err(|e| e.into_vm_status())?;
+        match payload {
+            TransactionPayload::Module(module) => {
+                let module_address = if self.publishing_option(log_context)?.is_open_module() {
+                    txn_data.sender()
+                } else {
+                    account_config::CORE_CODE_ADDRESS
+                };
+                session
+                    .publish_module(module.code().to_vec(), module_address, gas_status, log_context)
+                    .map_err(|e| e.into_vm_status())
+            }
+            payload => {
+                self.execute_script_payload(session, gas_status, txn_data, payload, log_context)
+            }
+        }
+    }
+
     /// Run the prologue of a transaction by calling into `PROLOGUE_NAME` function stored
     /// in the `WRITESET_MODULE` on chain.
     pub(crate) fn run_writeset_prologue<S: MoveStorage>(
@@ -406,24 +940,13 @@ impl DiemVMImpl {
         txn_data: &TransactionMetadata,
         log_context: &impl LogContext,
     ) -> Result<(), VMStatus> {
-        let txn_sequence_number = txn_data.sequence_number();
-        let txn_public_key = txn_data.authentication_key_preimage().to_vec();
-        let txn_expiration_timestamp_secs = txn_data.expiration_timestamp_secs();
-        let chain_id = txn_data.chain_id();
-
         let mut gas_status = GasStatus::new_unmetered();
         session
             .execute_function(
                 &account_config::ACCOUNT_MODULE,
                 &WRITESET_PROLOGUE_NAME,
                 vec![],
-                serialize_values(&vec![
-                    MoveValue::Signer(txn_data.sender),
-                    MoveValue::U64(txn_sequence_number),
-                    MoveValue::vector_u8(txn_public_key),
-                    MoveValue::U64(txn_expiration_timestamp_secs),
-                    MoveValue::U8(chain_id.id()),
-                ]),
+                serialize_values(&writeset_prologue_args(txn_data)),
                 &mut gas_status,
                 log_context,
             )
@@ -462,87 +985,368 @@ impl DiemVMImpl {
             })
     }
 
-    pub fn new_session<'r, R: MoveStorage>(&self, r: &'r R) -> Session<'r, '_, R> {
-        self.move_vm.new_session(r)
-    }
-
-    //////// 0L ////////    
-    // Note: currently the upgrade needs two blocks to happen: 
-    // In the first block, consensus is reached and recorded; 
-    // in the second block, the payload is applied and history is recorded
-    pub(crate) fn tick_oracle_consensus<S: MoveStorage> (
+    //////// 0L ////////
+    /// Same as `run_writeset_epilogue`, but also returns the events the epilogue emitted.
+    pub(crate) fn run_writeset_epilogue_with_events<S: MoveStorage>(
         &self,
         session: &mut Session<S>,
-        _block_metadata: BlockMetadata,
         txn_data: &TransactionMetadata,
-        gas_status: &mut GasStatus,
+        should_trigger_reconfiguration: bool,
         log_context: &impl LogContext,
-    ) -> Result<(), VMStatus> {
-        info!("0L ==== stdlib upgrade: checking for stdlib upgrade");
-        // tick Oracle::check_upgrade
-        let args = vec![
-            MoveValue::Signer(txn_data.sender),
-        ];
-        session.execute_function(
-            &ORACLE_MODULE,
-            &CHECK_UPGRADE,
-            vec![],
-            serialize_values(&args),
-            // txn_data.sender(),
-            gas_status,
-            log_context,
-        ).map_err(|e| { info!("Couldn't check upgrade"); e } )?;
-        Ok(())
+    ) -> Result<Vec<MoveEvent>, VMStatus> {
+        events_emitted_during(session, |session| {
+            self.run_writeset_epilogue(
+                session,
+                txn_data,
+                should_trigger_reconfiguration,
+                log_context,
+            )
+        })
     }
 
-    //////// 0L ////////    
-    pub(crate) fn apply_stdlib_upgrade<S: MoveStorage> (
+    /// Run the block prologue by calling into `BLOCK_PROLOGUE` function stored in the
+    /// `DIEM_BLOCK_MODULE` on chain, on behalf of the reserved VM address.
+    pub(crate) fn run_block_prologue<S: MoveStorage>(
         &self,
         session: &mut Session<S>,
-        remote_cache: &S,
         block_metadata: BlockMetadata,
-        txn_data: &TransactionMetadata,
         gas_status: &mut GasStatus,
         log_context: &impl LogContext,
     ) -> Result<(), VMStatus> {
-        let (round, timestamp, _previous_vote, _proposer) = block_metadata.into_inner();
-        // hardcoding upgrade on round 2
-        if round==2 {
-            let payload = get_upgrade_payload(remote_cache)?.payload;
-            if payload.len() > 0 {
-                info!("0L ==== stdlib upgrade: upgrade payload elected in previous epoch");
-
-                // publish the agreed stdlib
-                let new_stdlib = import_stdlib(&payload);
-                let mut counter = 0;
-                for module in new_stdlib {
-                    let mut bytes = vec![];
-                    module
-                        .serialize(&mut bytes)
-                        .expect("Failed to serialize module");
-                    session.revise_module(
-                        bytes, 
-                        account_config::CORE_CODE_ADDRESS, 
-                        gas_status, 
-                        log_context
-                    ).expect("Failed to publish module");
-                    counter += 1;
-                }
-                info!("0L ==== stdlib upgrade: published {} modules", counter);
+        let (round, timestamp, previous_vote, proposer) = block_metadata.into_inner();
+        session
+            .execute_function(
+                &DIEM_BLOCK_MODULE,
+                &BLOCK_PROLOGUE,
+                vec![],
+                serialize_values(&vec![
+                    MoveValue::Signer(account_config::reserved_vm_address()),
+                    MoveValue::U64(round),
+                    MoveValue::U64(timestamp),
+                    MoveValue::Vector(previous_vote.into_iter().map(MoveValue::Address).collect()),
+                    MoveValue::Address(proposer),
+                ]),
+                gas_status,
+                log_context,
+            )
+            .map(|_return_vals| ())
+            .or_else(|e| {
+                expect_only_successful_execution(e, BLOCK_PROLOGUE.as_str(), log_context)
+            })
+    }
 
-                // reset the UpgradePayload
-                let args = vec![
-                    MoveValue::Signer(txn_data.sender),
-                ];
-                session.execute_function(
-                    &UPGRADE_MODULE,
-                    &RESET_PAYLOAD,
-                    vec![],
-                    serialize_values(&args),
-                    // txn_data.sender(),
-                    gas_status,
-                    log_context,
-                ).expect("Couldn't reset upgrade payload");
+    //////// 0L ////////
+    /// With proposer validation enabled, check that `proposer` is in the validator set in
+    /// `remote_cache`, and log and count it if not. Only reports, and never fails the block:
+    /// the check is a node setting, so failing would let this node disagree with its peers.
+    /// The reserved VM address, which proposes NIL blocks, always passes, and so does any
+    /// proposer while no validator set is published, e.g. before genesis. Returns whether
+    /// the proposer passed.
+    pub(crate) fn validate_proposer<S: MoveStorage>(
+        &self,
+        remote_cache: &S,
+        proposer: AccountAddress,
+        log_context: &impl LogContext,
+    ) -> bool {
+        if !self.proposer_validation_enabled
+            || proposer == account_config::reserved_vm_address()
+        {
+            return true;
+        }
+        let validator_set = match read_validator_set(remote_cache) {
+            Ok(Some(validator_set)) => validator_set,
+            Ok(None) => return true,
+            Err(e) => {
+                warn!(*log_context, "[VM] Could not read the validator set: {:?}", e);
+                return true;
+            }
+        };
+        if !validator_set
+            .payload()
+            .iter()
+            .any(|validator| *validator.account_address() == proposer)
+        {
+            UNKNOWN_BLOCK_PROPOSER.inc();
+            warn!(
+                *log_context,
+                proposer = %proposer,
+                "[VM] Block proposer is not in the validator set"
+            );
+            return false;
+        }
+        true
+    }
+
+    pub fn new_session<'r, R: MoveStorage>(&self, r: &'r R) -> Session<'r, '_, R> {
+        self.move_vm.new_session(r)
+    }
+
+    //////// 0L ////////
+    /// Same as `new_session`, over a `TracingStorage` which records every storage read the
+    /// session makes. The caller keeps the `TracingStorage` to inspect the reads afterwards.
+    pub fn new_session_traced<'r, 'a, R: MoveStorage>(
+        &self,
+        r: &'r TracingStorage<'a, R>,
+    ) -> Session<'r, '_, TracingStorage<'a, R>> {
+        self.move_vm.new_session(r)
+    }
+
+    /// Creates a new session together with a `GasStatus` metered against the loaded gas
+    /// schedule and `max_gas_amount`, so user code cannot accidentally run unmetered.
+    ///
+    /// `GasStatus::new_unmetered()` is only appropriate for the VM's own system calls, i.e. the
+    /// prologue and epilogue functions.
+    pub fn new_metered_session<'r, R: MoveStorage>(
+        &self,
+        r: &'r R,
+        max_gas_amount: GasUnits<GasCarrier>,
+        log_context: &impl LogContext,
+    ) -> Result<(Session<'r, '_, R>, GasStatus<'_>), VMStatus> {
+        let gas_schedule = self.get_gas_schedule(log_context)?;
+        Ok((
+            self.new_session(r),
+            GasStatus::new(gas_schedule, max_gas_amount),
+        ))
+    }
+
+    //////// 0L ////////    
+    // Note: the upgrade needs at least two blocks to happen:
+    // at the `oracle_tick_round` of the `UpgradeConfig`, consensus is reached and recorded;
+    // at its `upgrade_apply_round`, the payload is applied and history is recorded
+    //
+    // Returns `Ok(false)` when the tick ran out of its gas budget. It may have written to
+    // `session` before running out, so the caller must discard the session and run the block
+    // prologue again without the tick.
+    pub(crate) fn tick_oracle_consensus<S: MoveStorage, R: MoveStorage> (
+        &self,
+        session: &mut Session<S>,
+        remote_cache: &R,
+        block_metadata: BlockMetadata,
+        txn_data: &TransactionMetadata,
+        gas_status: &mut GasStatus,
+        log_context: &impl LogContext,
+    ) -> Result<bool, VMStatus> {
+        if !self.ol_hooks_enabled
+            || block_metadata.round() != self.upgrade_config.oracle_tick_round
+        {
+            return Ok(true);
+        }
+        // Stands in for `Oracle::check_upgrade` in tests: `return(ok)` succeeds as if the
+        // oracle ran, `return(skip)` returns without ticking, anything else fails the tick.
+        fail_point!("move_adapter::tick_oracle_consensus", |action: Option<String>| {
+            match action.as_deref() {
+                Some("ok") => {
+                    self.preimport_elected_upgrade(session);
+                    Ok(true)
+                }
+                Some("skip") => Ok(true),
+                _ => Err(VMStatus::Error(
+                    StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR,
+                )),
+            }
+        });
+        info!("0L ==== stdlib upgrade: checking for stdlib upgrade");
+        match leading_upgrade_proposal(remote_cache) {
+            Ok(Some((id, votes))) => info!(
+                "0L ==== stdlib upgrade: proposal {} leads with weight {} from {} validators",
+                id,
+                votes.total_weight,
+                votes.validators.len()
+            ),
+            Ok(None) => info!("0L ==== stdlib upgrade: no proposal has votes"),
+            Err(e) => warn!("0L ==== stdlib upgrade: could not read upgrade proposals: {:?}", e),
+        }
+        // Within a budget, the tick is metered separately from the block, and skipped for the
+        // block when it runs out.
+        let budget = match self.upgrade_config.oracle_tick_gas_budget {
+            0 => None,
+            units => Some(GasUnits::new(units)),
+        };
+        let result = match budget {
+            Some(budget) => {
+                let mut budget_status =
+                    GasStatus::new(self.get_gas_schedule(log_context)?, budget);
+                check_upgrade(session, txn_data, &mut budget_status, log_context)
+            }
+            None => check_upgrade(session, txn_data, gas_status, log_context),
+        };
+        match result {
+            Ok(_) => {
+                self.preimport_elected_upgrade(session);
+                Ok(true)
+            }
+            Err(e)
+                if budget.is_some() && e.major_status() == StatusCode::OUT_OF_GAS =>
+            {
+                ORACLE_TICK_SKIPPED.inc();
+                warn!("0L ==== oracle tick exceeded its gas budget, skipping for this block");
+                Ok(false)
+            }
+            Err(e) => {
+                info!("Couldn't check upgrade");
+                Err(e.into_vm_status())
+            }
+        }
+    }
+
+    /// If the tick just recorded an elected payload, deserializes and verifies it now so the
+    /// apply block only has to publish. A payload that fails to import is left for the apply
+    /// block to reject.
+    fn preimport_elected_upgrade<S: MoveStorage>(&self, session: &Session<S>) {
+        if let Some(payload) = pending_upgrade_payload(session, &self.upgrade_payload_address) {
+            match preimport_upgrade_payload(&payload) {
+                Ok(()) => info!("0L ==== stdlib upgrade: elected payload imported ahead of apply"),
+                Err(e) => warn!(
+                    "0L ==== stdlib upgrade: could not import elected payload: {:?}",
+                    e
+                ),
+            }
+        }
+    }
+
+    //////// 0L ////////    
+    pub(crate) fn apply_stdlib_upgrade<S: MoveStorage, R: MoveStorage> (
+        &self,
+        session: &mut Session<S>,
+        remote_cache: &R,
+        block_metadata: BlockMetadata,
+        txn_data: &TransactionMetadata,
+        gas_status: &mut GasStatus,
+        log_context: &impl LogContext,
+    ) -> Result<(), VMStatus> {
+        if !self.ol_hooks_enabled {
+            return Ok(());
+        }
+        let (round, timestamp, _previous_vote, proposer) = block_metadata.into_inner();
+        if round == self.upgrade_config.upgrade_apply_round {
+            // a payload too large to read is reset like one which fails to import, rather than
+            // failing every block at this round while it stays queued
+            let payload = match get_upgrade_payload(remote_cache, &self.upgrade_payload_address) {
+                Ok(resource) => resource.map(|resource| resource.payload).unwrap_or_default(),
+                Err(status) if status.status_code() == StatusCode::UPGRADE_PAYLOAD_TOO_LARGE => {
+                    return reject_upgrade_payload(
+                        session,
+                        status,
+                        "too_large",
+                        txn_data,
+                        gas_status,
+                        log_context,
+                    );
+                }
+                // `Upgrade::reset_payload` cannot read a resource which does not deserialize
+                // either, so it is skipped without a reset instead of failing the block
+                Err(status)
+                    if status.status_code() == StatusCode::FAILED_TO_DESERIALIZE_RESOURCE =>
+                {
+                    error!(
+                        *log_context,
+                        reason = "malformed_resource",
+                        "0L ==== stdlib upgrade: elected payload skipped: {:?}", status
+                    );
+                    STDLIB_UPGRADE_REJECTED.with_label_values(&["malformed_resource"]).inc();
+                    return Ok(());
+                }
+                Err(status) => return Err(upgrade_failure(status)),
+            };
+            if payload.len() > 0 {
+                info!("0L ==== stdlib upgrade: upgrade payload elected in previous epoch");
+                // the payload is kept for a later apply round rather than failing the block
+                if let Err(status) = check_upgrade_timestamp(remote_cache, timestamp, proposer) {
+                    warn!("0L ==== stdlib upgrade: not applied in this block: {:?}", status);
+                    STDLIB_UPGRADE_DEFERRED.inc();
+                    return Ok(());
+                }
+
+                // publish the agreed stdlib, usually imported when consensus was recorded
+                let new_stdlib = match imported_upgrade_modules(&payload) {
+                    Ok(modules) => modules,
+                    Err(status) => {
+                        return reject_upgrade_payload(
+                            session,
+                            status,
+                            "import_failed",
+                            txn_data,
+                            gas_status,
+                            log_context,
+                        )
+                    }
+                };
+                if let Err(status) = check_no_duplicate_modules(&new_stdlib) {
+                    return reject_upgrade_payload(
+                        session,
+                        status,
+                        "duplicate_module",
+                        txn_data,
+                        gas_status,
+                        log_context,
+                    );
+                }
+                // a payload whose modules are all published already, e.g. elected again after
+                // it was applied, is reset without publishing it a second time
+                if upgrade_already_applied(remote_cache, &new_stdlib).map_err(upgrade_failure)? {
+                    return reject_upgrade_payload(
+                        session,
+                        VMStatus::Error(StatusCode::UPGRADE_PAYLOAD_NOT_NEWER),
+                        "already_applied",
+                        txn_data,
+                        gas_status,
+                        log_context,
+                    );
+                }
+                if let Err(status) = check_upgrade_is_newer(
+                    remote_cache,
+                    &new_stdlib,
+                    self.upgrade_config.allow_upgrade_downgrade,
+                ) {
+                    return reject_upgrade_payload(
+                        session,
+                        status,
+                        "not_newer",
+                        txn_data,
+                        gas_status,
+                        log_context,
+                    );
+                }
+                // serialize every module before publishing any, so a module which cannot
+                // be published rejects the whole payload rather than leaving it half applied
+                let mut serialized = vec![];
+                for module in &new_stdlib {
+                    match serialize_upgrade_module(module, self.allow_non_core_upgrade_modules) {
+                        Ok(address_and_bytes) => serialized.push(address_and_bytes),
+                        Err(status) => {
+                            return reject_upgrade_payload(
+                                session,
+                                status,
+                                "unpublishable_module",
+                                txn_data,
+                                gas_status,
+                                log_context,
+                            )
+                        }
+                    }
+                }
+                let mut counter = 0;
+                for (position, (module, (address, bytes))) in
+                    new_stdlib.iter().zip(serialized).enumerate()
+                {
+                    revise_upgrade_module(
+                        session,
+                        module.self_id(),
+                        position,
+                        bytes,
+                        address,
+                        gas_status,
+                        log_context,
+                    )
+                    .map_err(|failure| failure.report(log_context))?;
+                    counter += 1;
+                }
+                info!("0L ==== stdlib upgrade: published {} modules", counter);
+                STDLIB_MODULES_PUBLISHED_LAST.set(counter);
+
+                // reset the UpgradePayload
+                reset_upgrade_payload(session, txn_data, gas_status, log_context)
+                    .map_err(upgrade_failure)?;
 
                 session.execute_function(
                     &DIEMCONFIG_MODULE,
@@ -552,11 +1356,14 @@ impl DiemVMImpl {
                     // txn_data.sender(),
                     gas_status,
                     log_context,
-                ).expect("Couldn't emit reconfig event");
+                ).map_err(|e| upgrade_failure(e.into_vm_status()))?;
 
                 // session.data_cache.emit_event(guid, seq_num, ty, val)
 
+                STDLIB_UPGRADE_APPLIED.inc();
                 info!("==== stdlib upgrade: end upgrade at time: {} ====", timestamp);
+            } else {
+                STDLIB_UPGRADE_SKIPPED.inc();
             }
         }
 
@@ -564,19 +1371,789 @@ impl DiemVMImpl {
       }
 }
 
-fn get_upgrade_payload<S: MoveStorage>(
+//////// 0L ////////
+// The prologue arguments, in the order of the Move signatures in `DiemAccount`. Each list
+// must change together with its prologue.
+
+/// Arguments of `DiemAccount::script_prologue`.
+pub(crate) fn script_prologue_args(txn_data: &TransactionMetadata) -> Vec<MoveValue> {
+    vec![
+        MoveValue::Signer(txn_data.sender),
+        MoveValue::U64(txn_data.sequence_number()),
+        MoveValue::vector_u8(txn_data.authentication_key_preimage().to_vec()),
+        MoveValue::U64(txn_data.gas_unit_price().get()),
+        MoveValue::U64(txn_data.max_gas_amount().get()),
+        MoveValue::U64(txn_data.expiration_timestamp_secs()),
+        MoveValue::U8(txn_data.chain_id().id()),
+        MoveValue::vector_u8(txn_data.script_hash.clone()),
+    ]
+}
+
+/// Arguments of `DiemAccount::multi_agent_script_prologue`. The secondary signers'
+/// preimages are passed as authentication keys.
+pub(crate) fn multi_agent_prologue_args(txn_data: &TransactionMetadata) -> Vec<MoveValue> {
+    let secondary_public_key_hashes: Vec<MoveValue> = txn_data
+        .secondary_authentication_key_preimages
+        .iter()
+        .map(|preimage| MoveValue::vector_u8(auth_key_from_preimage(preimage)))
+        .collect();
+    vec![
+        MoveValue::Signer(txn_data.sender),
+        MoveValue::U64(txn_data.sequence_number()),
+        MoveValue::vector_u8(txn_data.authentication_key_preimage().to_vec()),
+        MoveValue::vector_address(txn_data.secondary_signers()),
+        MoveValue::Vector(secondary_public_key_hashes),
+        MoveValue::U64(txn_data.gas_unit_price().get()),
+        MoveValue::U64(txn_data.max_gas_amount().get()),
+        MoveValue::U64(txn_data.expiration_timestamp_secs()),
+        MoveValue::U8(txn_data.chain_id().id()),
+    ]
+}
+
+/// Arguments of `DiemAccount::module_prologue`.
+pub(crate) fn module_prologue_args(txn_data: &TransactionMetadata) -> Vec<MoveValue> {
+    vec![
+        MoveValue::Signer(txn_data.sender),
+        MoveValue::U64(txn_data.sequence_number()),
+        MoveValue::vector_u8(txn_data.authentication_key_preimage().to_vec()),
+        MoveValue::U64(txn_data.gas_unit_price().get()),
+        MoveValue::U64(txn_data.max_gas_amount().get()),
+        MoveValue::U64(txn_data.expiration_timestamp_secs()),
+        MoveValue::U8(txn_data.chain_id().id()),
+    ]
+}
+
+/// Arguments of `DiemAccount::writeset_prologue`.
+pub(crate) fn writeset_prologue_args(txn_data: &TransactionMetadata) -> Vec<MoveValue> {
+    vec![
+        MoveValue::Signer(txn_data.sender),
+        MoveValue::U64(txn_data.sequence_number()),
+        MoveValue::vector_u8(txn_data.authentication_key_preimage().to_vec()),
+        MoveValue::U64(txn_data.expiration_timestamp_secs()),
+        MoveValue::U8(txn_data.chain_id().id()),
+    ]
+}
+
+/// Ticks `Oracle::check_upgrade` as `txn_data.sender`.
+fn check_upgrade<S: MoveStorage>(
+    session: &mut Session<S>,
+    txn_data: &TransactionMetadata,
+    gas_status: &mut GasStatus,
+    log_context: &impl LogContext,
+) -> VMResult<Vec<Vec<u8>>> {
+    let args = vec![
+        MoveValue::Signer(txn_data.sender),
+    ];
+    session.execute_function(
+        &ORACLE_MODULE,
+        &CHECK_UPGRADE,
+        vec![],
+        serialize_values(&args),
+        gas_status,
+        log_context,
+    )
+}
+
+/// Clears the `UpgradePayload` by calling `Upgrade::reset_payload` as `txn_data.sender`.
+fn reset_upgrade_payload<S: MoveStorage>(
+    session: &mut Session<S>,
+    txn_data: &TransactionMetadata,
+    gas_status: &mut GasStatus,
+    log_context: &impl LogContext,
+) -> Result<(), VMStatus> {
+    let args = vec![
+        MoveValue::Signer(txn_data.sender),
+    ];
+    session.execute_function(
+        &UPGRADE_MODULE,
+        &RESET_PAYLOAD,
+        vec![],
+        serialize_values(&args),
+        gas_status,
+        log_context,
+    )
+    .map(|_return_vals| ())
+    .map_err(|e| e.into_vm_status())
+}
+
+//////// 0L ////////
+/// Resets an elected payload which fails a check before anything is published, so the block
+/// goes on without the upgrade instead of failing, and counts it under `reason`. Only a
+/// failure of the reset itself fails the block.
+fn reject_upgrade_payload<S: MoveStorage>(
+    session: &mut Session<S>,
+    status: VMStatus,
+    reason: &str,
+    txn_data: &TransactionMetadata,
+    gas_status: &mut GasStatus,
+    log_context: &impl LogContext,
+) -> Result<(), VMStatus> {
+    error!(
+        *log_context,
+        reason = reason,
+        "0L ==== stdlib upgrade: elected payload rejected and reset: {:?}", status
+    );
+    STDLIB_UPGRADE_REJECTED.with_label_values(&[reason]).inc();
+    reset_upgrade_payload(session, txn_data, gas_status, log_context).map_err(upgrade_failure)
+}
+
+//////// 0L ////////
+/// Which of the gas checks in `check_gas` to enforce. The transaction size limit is always
+/// enforced. The default enforces everything; only simulation and estimation should relax it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CheckGasOptions {
+    /// `max_gas_amount` must cover the intrinsic cost of the transaction
+    pub enforce_min_fee: bool,
+    /// `max_gas_amount` must not exceed `maximum_number_of_gas_units`
+    pub enforce_max_units: bool,
+    /// `gas_unit_price` must be within `min_price_per_gas_unit..=max_price_per_gas_unit`
+    pub enforce_price_bounds: bool,
+}
+
+impl Default for CheckGasOptions {
+    fn default() -> Self {
+        Self {
+            enforce_min_fee: true,
+            enforce_max_units: true,
+            enforce_price_bounds: true,
+        }
+    }
+}
+
+/// The checks behind `DiemVMImpl::check_gas`, against an already loaded gas schedule.
+fn check_gas_with_constants(
+    gas_constants: &GasConstants,
+    txn_data: &TransactionMetadata,
+    options: &CheckGasOptions,
+    log_context: &impl LogContext,
+) -> Result<(), VMStatus> {
+    let raw_bytes_len = txn_data.transaction_size;
+    // The transaction is too large.
+    if txn_data.transaction_size.get() > gas_constants.max_transaction_size_in_bytes {
+        warn!(
+            *log_context,
+            "[VM] Transaction size too big {} (max {})",
+            raw_bytes_len.get(),
+            gas_constants.max_transaction_size_in_bytes,
+        );
+        return Err(VMStatus::Error(StatusCode::EXCEEDED_MAX_TRANSACTION_SIZE));
+    }
+
+    // Check is performed on `txn.raw_txn_bytes_len()` which is the same as
+    // `raw_bytes_len`
+    assume!(raw_bytes_len.get() <= gas_constants.max_transaction_size_in_bytes);
+
+    // The submitted max gas units that the transaction can consume is greater than the
+    // maximum number of gas units bound that we have set for any
+    // transaction.
+    if options.enforce_max_units
+        && txn_data.max_gas_amount().get() > gas_constants.maximum_number_of_gas_units.get()
+    {
+        warn!(
+            *log_context,
+            "[VM] Gas unit error; max {}, submitted {}",
+            gas_constants.maximum_number_of_gas_units.get(),
+            txn_data.max_gas_amount().get(),
+        );
+        return Err(VMStatus::Error(
+            StatusCode::MAX_GAS_UNITS_EXCEEDS_MAX_GAS_UNITS_BOUND,
+        ));
+    }
+
+    // The submitted transactions max gas units needs to be at least enough to cover the
+    // intrinsic cost of the transaction as calculated against the size of the
+    // underlying `RawTransaction`
+    let min_txn_fee = min_txn_fee(gas_constants, raw_bytes_len);
+    if options.enforce_min_fee && txn_data.max_gas_amount().get() < min_txn_fee.get() {
+        warn!(
+            *log_context,
+            "[VM] Gas unit error; min {}, submitted {}",
+            min_txn_fee.get(),
+            txn_data.max_gas_amount().get(),
+        );
+        return Err(VMStatus::Error(
+            StatusCode::MAX_GAS_UNITS_BELOW_MIN_TRANSACTION_GAS_UNITS,
+        ));
+    }
+
+    // The submitted gas price is less than the minimum gas unit price set by the VM.
+    // NB: MIN_PRICE_PER_GAS_UNIT may equal zero, but need not in the future. Hence why
+    // we turn off the clippy warning.
+    #[allow(clippy::absurd_extreme_comparisons)]
+    let below_min_bound =
+        txn_data.gas_unit_price().get() < gas_constants.min_price_per_gas_unit.get();
+    if options.enforce_price_bounds && below_min_bound {
+        warn!(
+            *log_context,
+            "[VM] Gas unit error; min {}, submitted {}",
+            gas_constants.min_price_per_gas_unit.get(),
+            txn_data.gas_unit_price().get(),
+        );
+        return Err(VMStatus::Error(StatusCode::GAS_UNIT_PRICE_BELOW_MIN_BOUND));
+    }
+
+    // The submitted gas price is greater than the maximum gas unit price set by the VM.
+    if options.enforce_price_bounds
+        && txn_data.gas_unit_price().get() > gas_constants.max_price_per_gas_unit.get()
+    {
+        warn!(
+            *log_context,
+            "[VM] Gas unit error; min {}, submitted {}",
+            gas_constants.max_price_per_gas_unit.get(),
+            txn_data.gas_unit_price().get(),
+        );
+        return Err(VMStatus::Error(StatusCode::GAS_UNIT_PRICE_ABOVE_MAX_BOUND));
+    }
+    Ok(())
+}
+
+/// The least `max_gas_amount` which covers the intrinsic cost of a transaction of
+/// `transaction_size` bytes, in external gas units.
+fn min_txn_fee(
+    gas_constants: &GasConstants,
+    transaction_size: AbstractMemorySize<GasCarrier>,
+) -> GasUnits<GasCarrier> {
+    gas_constants.to_external_units(calculate_intrinsic_gas(transaction_size, gas_constants))
+}
+
+//////// 0L ////////
+/// The limits `check_gas` holds one transaction to, in external gas units and gas prices.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct GasThresholds {
+    /// the least `max_gas_amount` accepted, the intrinsic cost of the transaction
+    pub min_txn_fee: u64,
+    /// the most `max_gas_amount` accepted
+    pub max_gas_units: u64,
+    /// the least `gas_unit_price` accepted
+    pub min_price_per_gas_unit: u64,
+    /// the most `gas_unit_price` accepted
+    pub max_price_per_gas_unit: u64,
+    /// the largest transaction accepted, in bytes
+    pub max_transaction_size_in_bytes: u64,
+}
+
+//////// 0L ////////
+/// The limits `check_gas` holds `txn_data` to under `gas_constants`.
+pub fn gas_thresholds(
+    gas_constants: &GasConstants,
+    txn_data: &TransactionMetadata,
+) -> GasThresholds {
+    GasThresholds {
+        min_txn_fee: min_txn_fee(gas_constants, txn_data.transaction_size()).get(),
+        max_gas_units: gas_constants.maximum_number_of_gas_units.get(),
+        min_price_per_gas_unit: gas_constants.min_price_per_gas_unit.get(),
+        max_price_per_gas_unit: gas_constants.max_price_per_gas_unit.get(),
+        max_transaction_size_in_bytes: gas_constants.max_transaction_size_in_bytes,
+    }
+}
+
+//////// 0L ////////
+/// A sentence explaining why `check_gas` rejected `txn_data` with `status`, with the numbers
+/// involved, for showing to users. `None` if `status` is not one of `check_gas`'s errors.
+pub fn explain_gas_rejection(
+    status: &VMStatus,
+    txn_data: &TransactionMetadata,
+    gas_constants: &GasConstants,
+) -> Option<String> {
+    let code = match status {
+        VMStatus::Error(code) => *code,
+        _ => return None,
+    };
+    let explanation = match code {
+        StatusCode::EXCEEDED_MAX_TRANSACTION_SIZE => format!(
+            "your transaction is {} bytes, above the maximum of {}",
+            txn_data.transaction_size().get(),
+            gas_constants.max_transaction_size_in_bytes
+        ),
+        StatusCode::MAX_GAS_UNITS_EXCEEDS_MAX_GAS_UNITS_BOUND => format!(
+            "your max gas amount {} is above the maximum of {}",
+            txn_data.max_gas_amount().get(),
+            gas_constants.maximum_number_of_gas_units.get()
+        ),
+        StatusCode::MAX_GAS_UNITS_BELOW_MIN_TRANSACTION_GAS_UNITS => format!(
+            "your max gas amount {} is below the {} this transaction needs to be processed",
+            txn_data.max_gas_amount().get(),
+            min_txn_fee(gas_constants, txn_data.transaction_size()).get()
+        ),
+        StatusCode::GAS_UNIT_PRICE_BELOW_MIN_BOUND => format!(
+            "your gas price {} is below the minimum {}",
+            txn_data.gas_unit_price().get(),
+            gas_constants.min_price_per_gas_unit.get()
+        ),
+        StatusCode::GAS_UNIT_PRICE_ABOVE_MAX_BOUND => format!(
+            "your gas price {} is above the maximum {}",
+            txn_data.gas_unit_price().get(),
+            gas_constants.max_price_per_gas_unit.get()
+        ),
+        _ => return None,
+    };
+    Some(explanation)
+}
+
+/// The address an upgrade module is published at, which is the one in its own `ModuleId`.
+/// Anything but `CORE_CODE_ADDRESS` is rejected unless `allow_non_core` is set.
+pub(crate) fn upgrade_module_address(
+    module: &CompiledModule,
+    allow_non_core: bool,
+) -> Result<AccountAddress, VMStatus> {
+    let address = *module.self_id().address();
+    if address == account_config::CORE_CODE_ADDRESS || allow_non_core {
+        Ok(address)
+    } else {
+        error!(
+            "0L ==== stdlib upgrade: module {} is not at the core code address",
+            module.self_id()
+        );
+        Err(VMStatus::Error(StatusCode::INVALID_MODULE_PUBLISHER))
+    }
+}
+
+/// The address and bytes to publish an upgrade module with. The bytes are deserialized
+/// again and must give back the same module, with the same self id, so a serialization bug
+/// cannot publish something other than what was elected.
+pub(crate) fn serialize_upgrade_module(
+    module: &CompiledModule,
+    allow_non_core: bool,
+) -> Result<(AccountAddress, Vec<u8>), VMStatus> {
+    let address = upgrade_module_address(module, allow_non_core)?;
+    let round_trip_mismatch = || {
+        error!(
+            "0L ==== stdlib upgrade: module {} does not round-trip through serialization",
+            module.self_id()
+        );
+        VMStatus::Error(StatusCode::UPGRADE_MODULE_ROUND_TRIP_MISMATCH)
+    };
+    let mut bytes = vec![];
+    module.serialize(&mut bytes).map_err(|_| round_trip_mismatch())?;
+    match CompiledModule::deserialize(&bytes) {
+        Ok(round_trip) if round_trip == *module => Ok((address, bytes)),
+        _ => Err(round_trip_mismatch()),
+    }
+}
+
+//////// 0L ////////
+/// The network's base currency, for callers which have no gas currency of their own.
+pub fn default_gas_currency_symbol() -> &'static IdentStr {
+    account_config::GAS_IDENTIFIER
+}
+
+//////// 0L ////////
+/// Every secondary signer needs exactly one authentication key preimage; the multi-agent
+/// prologue receives the two as separate vectors and assumes they line up.
+pub(crate) fn check_secondary_signer_count(
+    txn_data: &TransactionMetadata,
+    log_context: &impl LogContext,
+) -> Result<(), VMStatus> {
+    let signers = txn_data.secondary_signers.len();
+    let preimages = txn_data.secondary_authentication_key_preimages.len();
+    if signers != preimages {
+        warn!(
+            *log_context,
+            "[VM] {} secondary signers but {} secondary authentication keys", signers, preimages,
+        );
+        return Err(VMStatus::Error(
+            StatusCode::SECONDARY_KEYS_ADDRESSES_COUNT_MISMATCH,
+        ));
+    }
+    Ok(())
+}
+
+//////// 0L ////////
+/// Most public keys in a `MultiEd25519` public key, as in `diem_crypto::multi_ed25519`.
+const MAX_MULTI_ED25519_KEYS: usize = 32;
+
+//////// 0L ////////
+/// Reject an authentication key preimage whose length cannot match its scheme, the last byte,
+/// before paying for a prologue which would reject it anyway. A well-formed preimage can
+/// still fail the prologue's own checks.
+pub(crate) fn validate_auth_key_preimage(preimage: &[u8]) -> Result<(), VMStatus> {
+    let well_formed = match preimage.split_last() {
+        Some((&scheme, key)) if scheme == Scheme::Ed25519 as u8 => {
+            key.len() == ED25519_PUBLIC_KEY_LENGTH
+        }
+        // the public keys, then the threshold byte
+        Some((&scheme, key)) if scheme == Scheme::MultiEd25519 as u8 => match key.split_last() {
+            Some((_threshold, keys)) => {
+                !keys.is_empty()
+                    && keys.len() % ED25519_PUBLIC_KEY_LENGTH == 0
+                    && keys.len() / ED25519_PUBLIC_KEY_LENGTH <= MAX_MULTI_ED25519_KEYS
+            }
+            None => false,
+        },
+        _ => false,
+    };
+    if !well_formed {
+        return Err(VMStatus::Error(StatusCode::INVALID_AUTH_KEY));
+    }
+    Ok(())
+}
+
+//////// 0L ////////
+/// The upgrade is recorded with the time of the block applying it, so that block must be
+/// timestamped after the previous one, whose time is still in `remote_cache`. A NIL block,
+/// proposed by the reserved VM address, carries the previous time instead, so only has to
+/// not go back. Without a previous time, e.g. before `DiemTimestamp` is published, there is
+/// nothing to check.
+pub(crate) fn check_upgrade_timestamp<S: MoveStorage>(
+    remote_cache: &S,
+    timestamp: u64,
+    proposer: AccountAddress,
+) -> Result<(), VMStatus> {
+    let blob = remote_cache
+        .get_resource(&account_config::diem_root_address(), &DiemTimestampResource::struct_tag())
+        .map_err(|e| e.finish(Location::Undefined).into_vm_status())?;
+    let previous = match blob {
+        Some(blob) => bcs::from_bytes::<DiemTimestampResource>(&blob)
+            .map_err(|_| VMStatus::Error(StatusCode::FAILED_TO_DESERIALIZE_RESOURCE))?
+            .diem_timestamp
+            .microseconds,
+        None => return Ok(()),
+    };
+    let nil_block = proposer == account_config::reserved_vm_address();
+    if timestamp < previous || (timestamp == previous && !nil_block) {
+        error!(
+            "0L ==== stdlib upgrade: block timestamp {} is not after the previous block's {}",
+            timestamp, previous
+        );
+        return Err(VMStatus::Error(StatusCode::UPGRADE_BLOCK_TIMESTAMP_NOT_MONOTONIC));
+    }
+    Ok(())
+}
+
+//////// 0L ////////
+/// The on-chain validator set, stored like every on-chain config as a
+/// `DiemConfig::DiemConfig<T>` resource at the config address.
+pub(crate) fn read_validator_set<S: MoveStorage>(
     remote_cache: &S,
-) -> Result<UpgradePayloadResource, VMStatus> {
-    if let Ok(Some(blob)) = remote_cache.get_resource(
-      &account_config::diem_root_address(),
-      &UpgradePayloadResource::struct_tag(),
-  ) {
-      let x = bcs::from_bytes::<UpgradePayloadResource>(&blob)
-          .map_err(|_| VMStatus::Error(StatusCode::RESOURCE_DOES_NOT_EXIST))?;
-      Ok(x)
-  } else {
-      Err(VMStatus::Error(StatusCode::CURRENCY_INFO_DOES_NOT_EXIST))
-  }
+) -> Result<Option<ValidatorSet>, VMStatus> {
+    let config_name = Identifier::new(ValidatorSet::IDENTIFIER)
+        .map_err(|_| VMStatus::Error(StatusCode::UNREACHABLE))?;
+    let tag = StructTag {
+        address: account_config::CORE_CODE_ADDRESS,
+        module: ConfigurationResource::MODULE_NAME.to_owned(),
+        name: ConfigurationResource::MODULE_NAME.to_owned(),
+        type_params: vec![TypeTag::Struct(StructTag {
+            address: account_config::CORE_CODE_ADDRESS,
+            module: config_name.clone(),
+            name: config_name,
+            type_params: vec![],
+        })],
+    };
+    let blob = remote_cache
+        .get_resource(&config_address(), &tag)
+        .map_err(|e| e.finish(Location::Undefined).into_vm_status())?;
+    blob.map(|blob| {
+        ValidatorSet::deserialize_into_config(&blob)
+            .map_err(|_| VMStatus::Error(StatusCode::FAILED_TO_DESERIALIZE_RESOURCE))
+    })
+    .transpose()
+}
+
+//////// 0L ////////
+/// A payload with two modules of the same id would have the first silently overwritten when
+/// publishing, which is a packaging bug. Reject it before anything is published.
+pub(crate) fn check_no_duplicate_modules(modules: &[CompiledModule]) -> Result<(), VMStatus> {
+    let mut seen = HashSet::new();
+    for module in modules {
+        let id = module.self_id();
+        if !seen.insert(id.clone()) {
+            STDLIB_UPGRADE_DUPLICATE_MODULE.inc();
+            error!("0L ==== stdlib upgrade: payload contains module {} more than once", id);
+            return Err(VMStatus::Error(StatusCode::UPGRADE_PAYLOAD_DUPLICATE_MODULE));
+        }
+    }
+    Ok(())
+}
+
+//////// 0L ////////
+/// Whether every module of an elected stdlib is already published byte for byte, i.e. the
+/// same payload was applied before. This is read from the published modules rather than
+/// recorded separately, so it holds however the payload came to be elected again.
+pub(crate) fn upgrade_already_applied<S: MoveStorage>(
+    remote_cache: &S,
+    modules: &[CompiledModule],
+) -> Result<bool, VMStatus> {
+    if modules.is_empty() {
+        return Ok(false);
+    }
+    for module in modules {
+        let published = remote_cache
+            .get_module(&module.self_id())
+            .map_err(|e| e.into_vm_status())?;
+        let mut bytes = vec![];
+        module
+            .serialize(&mut bytes)
+            .map_err(|_| VMStatus::Error(StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR))?;
+        if published.as_ref() != Some(&bytes) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+//////// 0L ////////
+/// Compare an elected stdlib with the modules published in `remote_cache`. Payloads carry no
+/// framework version, so a payload whose modules are all byte-for-byte the published ones
+/// counts as the same framework, and one with a module in an older bytecode version than the
+/// published module counts as older. Both are rejected unless `allow_downgrade` is set.
+///
+/// The on-chain `DiemVersion` is not consulted: a payload is only a list of module blobs
+/// with nothing to compare it against, and applying an upgrade does not bump `DiemVersion`,
+/// so it says nothing about which framework is published.
+pub(crate) fn check_upgrade_is_newer<S: MoveStorage>(
+    remote_cache: &S,
+    modules: &[CompiledModule],
+    allow_downgrade: bool,
+) -> Result<(), VMStatus> {
+    if allow_downgrade {
+        return Ok(());
+    }
+    let mut unchanged = 0;
+    for module in modules {
+        let published = match remote_cache
+            .get_module(&module.self_id())
+            .map_err(|e| e.into_vm_status())?
+        {
+            Some(bytes) => bytes,
+            None => continue,
+        };
+        let mut bytes = vec![];
+        module
+            .serialize(&mut bytes)
+            .map_err(|_| VMStatus::Error(StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR))?;
+        if bytes == published {
+            unchanged += 1;
+            continue;
+        }
+        if let Ok(published) = CompiledModule::deserialize(&published) {
+            if published.version() > module.version() {
+                error!(
+                    "0L ==== stdlib upgrade: module {} is older than the published one",
+                    module.self_id()
+                );
+                return Err(VMStatus::Error(StatusCode::UPGRADE_PAYLOAD_NOT_NEWER));
+            }
+        }
+    }
+    if unchanged == modules.len() {
+        error!("0L ==== stdlib upgrade: payload is identical to the published framework");
+        return Err(VMStatus::Error(StatusCode::UPGRADE_PAYLOAD_NOT_NEWER));
+    }
+    Ok(())
+}
+
+//////// 0L ////////
+/// The upgrade proposal leading in the oracle at the diem root account: its
+/// `version_id` and the votes for the payload with the most weight.
+/// `None` if nothing has been voted on in the current window.
+pub(crate) fn leading_upgrade_proposal<S: MoveStorage>(
+    storage: &S,
+) -> Result<Option<(u64, VoteCount)>, VMStatus> {
+    let blob = storage
+        .get_resource(&account_config::diem_root_address(), &OracleResource::struct_tag())
+        .map_err(|e| e.finish(Location::Undefined).into_vm_status())?;
+    let oracle = match blob {
+        Some(blob) => OracleResource::try_from_bytes(&blob)
+            .map_err(|_| VMStatus::Error(StatusCode::FAILED_TO_DESERIALIZE_RESOURCE))?
+            .upgrade,
+        None => return Ok(None),
+    };
+    let id = oracle.version_id;
+    Ok(oracle
+        .vote_counts
+        .into_iter()
+        .max_by_key(|count| count.total_weight)
+        .map(|count| (id, count)))
+}
+
+//////// 0L ////////
+/// A module of a stdlib upgrade which could not be published.
+#[derive(Debug)]
+pub(crate) struct UpgradeModuleFailure {
+    pub module_id: ModuleId,
+    /// index of the module in the upgrade payload
+    pub position: usize,
+    /// serialized size of the module, in bytes
+    pub size: usize,
+    pub error: VMError,
+}
+
+impl UpgradeModuleFailure {
+    /// A framework upgrade failing part way is an incident: alert, log everything known
+    /// about the module, and record the failed upgrade.
+    pub(crate) fn report(self, log_context: &impl LogContext) -> VMStatus {
+        log_context.alert();
+        error!(
+            *log_context,
+            module_id = %self.module_id,
+            position = self.position,
+            size = self.size,
+            error = ?self.error,
+            "0L ==== stdlib upgrade: could not publish module"
+        );
+        upgrade_failure(self.error.into_vm_status())
+    }
+}
+
+/// Publishes one module of a stdlib upgrade, keeping what is needed to diagnose a failure.
+pub(crate) fn revise_upgrade_module<S: MoveStorage>(
+    session: &mut Session<S>,
+    module_id: ModuleId,
+    position: usize,
+    bytes: Vec<u8>,
+    address: AccountAddress,
+    gas_status: &mut GasStatus,
+    log_context: &impl LogContext,
+) -> Result<(), UpgradeModuleFailure> {
+    let size = bytes.len();
+    session
+        .revise_module(bytes, address, gas_status, log_context)
+        .map_err(|error| UpgradeModuleFailure {
+            module_id,
+            position,
+            size,
+            error,
+        })
+}
+
+/// Records a failed stdlib upgrade before the error is propagated.
+fn upgrade_failure(status: VMStatus) -> VMStatus {
+    STDLIB_UPGRADE_FAILED.inc();
+    status
+}
+
+//////// 0L ////////
+/// Largest upgrade payload resource, in bytes, the VM will deserialize.
+pub const MAX_UPGRADE_PAYLOAD_BYTES: usize = 4 * 1024 * 1024;
+/// Most modules a single stdlib upgrade may publish.
+pub(crate) const MAX_UPGRADE_MODULES: usize = 256;
+
+/// Reads the `UpgradePayloadResource` stored at `address`. Returns `Ok(None)` when no
+/// resource is published there; whether an empty payload matters is left to the caller.
+pub(crate) fn get_upgrade_payload<S: MoveStorage>(
+    remote_cache: &S,
+    address: &AccountAddress,
+) -> Result<Option<UpgradePayloadResource>, VMStatus> {
+    let blob = remote_cache
+        .get_resource(address, &UpgradePayloadResource::struct_tag())
+        .map_err(|e| e.finish(Location::Undefined).into_vm_status())?;
+    blob.map(|blob| {
+        if blob.len() > MAX_UPGRADE_PAYLOAD_BYTES {
+            return Err(VMStatus::Error(StatusCode::UPGRADE_PAYLOAD_TOO_LARGE));
+        }
+        bcs::from_bytes::<UpgradePayloadResource>(&blob)
+            .map_err(|_| VMStatus::Error(StatusCode::FAILED_TO_DESERIALIZE_RESOURCE))
+    })
+    .transpose()
+}
+
+//////// 0L ////////
+/// The queued `UpgradePayloadResource` in `state`, e.g. for governance tooling to inspect a
+/// pending upgrade. `address` defaults to the diem root account, where the VM reads it from
+/// unless configured otherwise. `Ok(None)` when nothing is queued there.
+pub fn read_upgrade_payload<S: StateView>(
+    state: &S,
+    address: Option<AccountAddress>,
+) -> Result<Option<UpgradePayloadResource>, VMStatus> {
+    let address = address.unwrap_or_else(account_config::diem_root_address);
+    get_upgrade_payload(&RemoteStorage::new(state), &address)
+}
+
+//////// 0L ////////
+/// The last upgrade payload imported ahead of its apply block, keyed by the hash of the
+/// payload bytes. Process wide, since a `DiemVMImpl` only lives for one block, so shared by
+/// every VM in the process. That is safe because only `preimport_upgrade_payload` fills it,
+/// always with the import of the very bytes it is keyed by, and `imported_upgrade_modules`
+/// checks the key against the hash of the bytes it applies: whoever filled it, a hit gives
+/// the same modules a fresh import would.
+static PREIMPORTED_UPGRADE: Lazy<Mutex<Option<(HashValue, Vec<CompiledModule>)>>> =
+    Lazy::new(|| Mutex::new(None));
+
+/// Deserializes and verifies an upgrade payload.
+fn import_upgrade_payload(payload: &[u8]) -> Result<Vec<CompiledModule>, VMStatus> {
+    import_stdlib_bounded(payload, MAX_UPGRADE_MODULES).map_err(|e| {
+        error!("0L ==== stdlib upgrade: rejected payload: {}", e);
+        VMStatus::Error(match e {
+            StdlibImportError::Malformed(_) => StatusCode::UPGRADE_PAYLOAD_MALFORMED,
+            StdlibImportError::TooManyModules { .. } => StatusCode::UPGRADE_PAYLOAD_TOO_LARGE,
+            StdlibImportError::ModuleMalformed { .. } => StatusCode::UPGRADE_MODULE_MALFORMED,
+            StdlibImportError::ModuleUnverified { .. } => StatusCode::UPGRADE_MODULE_UNVERIFIED,
+        })
+    })
+}
+
+/// Imports `payload` now and keeps the modules for the block which applies it, replacing
+/// any payload imported before.
+pub(crate) fn preimport_upgrade_payload(payload: &[u8]) -> Result<(), VMStatus> {
+    let modules = import_upgrade_payload(payload)?;
+    *PREIMPORTED_UPGRADE.lock().unwrap() = Some((HashValue::sha3_256_of(payload), modules));
+    Ok(())
+}
+
+/// The hash of the payload currently pre-imported, if any.
+#[cfg(test)]
+pub(crate) fn preimported_upgrade_hash() -> Option<HashValue> {
+    PREIMPORTED_UPGRADE.lock().unwrap().as_ref().map(|(hash, _modules)| *hash)
+}
+
+/// The modules of `payload`, taken from the pre-imported payload if its bytes are the same,
+/// otherwise imported now. A pre-imported payload that does not match is kept.
+pub(crate) fn imported_upgrade_modules(payload: &[u8]) -> Result<Vec<CompiledModule>, VMStatus> {
+    let hash = HashValue::sha3_256_of(payload);
+    {
+        let mut preimported = PREIMPORTED_UPGRADE.lock().unwrap();
+        match preimported.take() {
+            Some((cached, modules)) if cached == hash => return Ok(modules),
+            other => *preimported = other,
+        }
+    }
+    import_upgrade_payload(payload)
+}
+
+/// The non-empty upgrade payload written to `address` by the changes pending in `session`.
+fn pending_upgrade_payload<S: MoveStorage>(
+    session: &Session<S>,
+    address: &AccountAddress,
+) -> Option<Vec<u8>> {
+    let (changes, _events) = session.preview_changes().ok()?;
+    let tag = UpgradePayloadResource::struct_tag();
+    let blob = changes
+        .resources()
+        .find(|(addr, struct_tag, _)| addr == address && **struct_tag == tag)
+        .and_then(|(_, _, blob)| blob)?;
+    if blob.len() > MAX_UPGRADE_PAYLOAD_BYTES {
+        return None;
+    }
+    bcs::from_bytes::<UpgradePayloadResource>(blob)
+        .ok()
+        .map(|resource| resource.payload)
+        .filter(|payload| !payload.is_empty())
+}
+
+//////// 0L ////////
+/// The configs a `DiemVMImpl` loaded, see `DiemVMInternals::config_snapshot`.
+/// A config which failed to load is `None`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct VmConfigSnapshot {
+    pub gas_schedule: Option<GasScheduleSummary>,
+    pub diem_version: Option<u64>,
+    pub publishing_option: Option<PublishingSummary>,
+}
+
+/// Table sizes and constants of a gas schedule.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct GasScheduleSummary {
+    pub instructions: usize,
+    pub natives: usize,
+    pub gas_constants: GasConstants,
+}
+
+/// What a `VMPublishingOption` allows.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct PublishingSummary {
+    pub is_open_module: bool,
+    pub is_open_script: bool,
+    pub allowed_scripts: usize,
 }
 
 /// Internal APIs for the Diem VM, primarily used for testing.
@@ -593,14 +2170,107 @@ impl<'a> DiemVMInternals<'a> {
         &self.0.move_vm
     }
 
+    //////// 0L ////////
+    /// Returns the ids of the modules currently in the Move VM's code cache.
+    pub fn loaded_module_ids(self) -> Vec<ModuleId> {
+        self.0.move_vm.loaded_module_ids()
+    }
+
     /// Returns the internal gas schedule if it has been loaded, or an error if it hasn't.
     pub fn gas_schedule(self, log_context: &impl LogContext) -> Result<&'a CostTable, VMStatus> {
         self.0.get_gas_schedule(log_context)
     }
 
     /// Returns the version of Move Runtime.
-    pub fn diem_version(self) -> Result<DiemVersion, VMStatus> {
-        self.0.get_diem_version()
+    pub fn diem_version(self, log_context: &impl LogContext) -> Result<DiemVersion, VMStatus> {
+        self.0.get_diem_version(log_context)
+    }
+
+    //////// 0L ////////
+    /// Returns what the loaded Diem version supports.
+    pub fn version_capabilities(
+        self,
+        log_context: &impl LogContext,
+    ) -> Result<VersionCapabilities, VMStatus> {
+        self.0.get_version_capabilities(log_context)
+    }
+
+    /// Returns the publishing option if it has been loaded, or an error if it hasn't.
+    pub fn publishing_option(
+        self,
+        log_context: &impl LogContext,
+    ) -> Result<&'a VMPublishingOption, VMStatus> {
+        self.0.publishing_option(log_context)
+    }
+
+    //////// 0L ////////
+    /// Summarizes the gas schedule, Diem version and publishing option the VM loaded, e.g.
+    /// for a support bundle. Configs which failed to load are left out of the snapshot;
+    /// it is an error only if none of them loaded.
+    pub fn config_snapshot(
+        self,
+        log_context: &impl LogContext,
+    ) -> Result<VmConfigSnapshot, VMStatus> {
+        let gas_schedule = self.gas_schedule(log_context);
+        let snapshot = VmConfigSnapshot {
+            gas_schedule: gas_schedule.as_ref().ok().map(|table| GasScheduleSummary {
+                instructions: table.instruction_table.len(),
+                natives: table.native_table.len(),
+                gas_constants: table.gas_constants.clone(),
+            }),
+            diem_version: self.diem_version(log_context).ok().map(|v| v.major),
+            publishing_option: self
+                .publishing_option(log_context)
+                .ok()
+                .map(|option| PublishingSummary {
+                    is_open_module: option.is_open_module(),
+                    is_open_script: option.is_open_script(),
+                    allowed_scripts: option.script_allow_list.len(),
+                }),
+        };
+        match gas_schedule {
+            Err(e)
+                if snapshot.diem_version.is_none() && snapshot.publishing_option.is_none() =>
+            {
+                Err(e)
+            }
+            _ => Ok(snapshot),
+        }
+    }
+
+    //////// 0L ////////
+    /// Runs the success epilogue in `session` and returns the events it emitted.
+    pub fn run_success_epilogue_with_events<S: MoveStorage>(
+        self,
+        session: &mut Session<S>,
+        gas_status: &mut GasStatus,
+        txn_data: &TransactionMetadata,
+        account_currency_symbol: &IdentStr,
+        log_context: &impl LogContext,
+    ) -> Result<Vec<MoveEvent>, VMStatus> {
+        self.0.run_success_epilogue_with_events(
+            session,
+            gas_status,
+            txn_data,
+            account_currency_symbol,
+            log_context,
+        )
+    }
+
+    /// Runs the write set epilogue in `session` and returns the events it emitted.
+    pub fn run_writeset_epilogue_with_events<S: MoveStorage>(
+        self,
+        session: &mut Session<S>,
+        txn_data: &TransactionMetadata,
+        should_trigger_reconfiguration: bool,
+        log_context: &impl LogContext,
+    ) -> Result<Vec<MoveEvent>, VMStatus> {
+        self.0.run_writeset_epilogue_with_events(
+            session,
+            txn_data,
+            should_trigger_reconfiguration,
+            log_context,
+        )
     }
 
     /// Executes the given code within the context of a transaction.
@@ -619,10 +2289,40 @@ impl<'a> DiemVMInternals<'a> {
     }
 }
 
+//////// 0L ////////
+/// Runs `f` against `session` and returns the events emitted while it ran, in order. Events
+/// are only appended to a session, so these are whatever follows the ones already there.
+fn events_emitted_during<S: MoveStorage>(
+    session: &mut Session<S>,
+    f: impl FnOnce(&mut Session<S>) -> Result<(), VMStatus>,
+) -> Result<Vec<MoveEvent>, VMStatus> {
+    let emitted_events = |session: &Session<S>| {
+        session
+            .preview_changes()
+            .map(|(_, events)| events)
+            .map_err(|e| e.into_vm_status())
+    };
+    let before = emitted_events(session)?.len();
+    f(session)?;
+    Ok(emitted_events(session)?.split_off(before))
+}
+
 pub fn convert_changeset_and_events_cached<C: AccessPathCache>(
     ap_cache: &mut C,
     changeset: MoveChangeSet,
     events: Vec<MoveEvent>,
+) -> Result<(WriteSet, Vec<ContractEvent>), VMStatus> {
+    convert_changeset_and_events_filtered(ap_cache, changeset, events, |_| true)
+}
+
+//////// 0L ////////
+/// Same as `convert_changeset_and_events_cached`, but only events matching `predicate` are
+/// converted and returned, in their original order. The write set is always complete.
+pub fn convert_changeset_and_events_filtered<C: AccessPathCache>(
+    ap_cache: &mut C,
+    changeset: MoveChangeSet,
+    events: Vec<MoveEvent>,
+    predicate: impl Fn(&MoveEvent) -> bool,
 ) -> Result<(WriteSet, Vec<ContractEvent>), VMStatus> {
     // TODO: Cache access path computations if necessary.
     let mut ops = vec![];
@@ -655,6 +2355,7 @@ pub fn convert_changeset_and_events_cached<C: AccessPathCache>(
 
     let events = events
         .into_iter()
+        .filter(|event| predicate(event))
         .map(|(guid, seq_num, ty_tag, blob)| {
             let key = EventKey::try_from(guid.as_slice())
                 .map_err(|_| VMStatus::Error(StatusCode::EVENT_KEY_MISMATCH))?;
@@ -672,23 +2373,165 @@ pub fn convert_changeset_and_events(
     convert_changeset_and_events_cached(&mut (), changeset, events)
 }
 
+/// Returns true if applying `write_set` requires a reconfiguration, i.e. it writes one of the
+/// on-chain configs in `ON_CHAIN_CONFIG_REGISTRY` or a module under `CORE_CODE_ADDRESS`.
+pub fn writeset_triggers_reconfig(write_set: &WriteSet) -> bool {
+    let config_paths = ON_CHAIN_CONFIG_REGISTRY
+        .iter()
+        .map(|config_id| config_id.access_path())
+        .collect::<HashSet<_>>();
+    write_set.iter().any(|(ap, _)| {
+        config_paths.contains(ap)
+            || (ap.address == account_config::CORE_CODE_ADDRESS
+                && matches!(bcs::from_bytes::<Path>(&ap.path), Ok(Path::Code(_))))
+    })
+}
+
+//////// 0L ////////
+/// Returns true if a transaction's output reconfigured the chain, e.g. after a stdlib upgrade:
+/// `events` include a new epoch event, or `write_set` writes the `ConfigurationResource`
+/// holding the epoch. Unlike `writeset_triggers_reconfig`, this looks at what happened rather
+/// than what should happen.
+pub fn writeset_contains_reconfig(write_set: &WriteSet, events: &[ContractEvent]) -> bool {
+    let epoch_key = new_epoch_event_key();
+    let configuration_path =
+        AccessPath::new(config_address(), ConfigurationResource::resource_path());
+    events.iter().any(|event| *event.key() == epoch_key)
+        || write_set.iter().any(|(ap, _)| *ap == configuration_path)
+}
+
 pub(crate) fn charge_global_write_gas_usage<R: MoveStorage>(
     gas_status: &mut GasStatus,
     session: &Session<R>,
     sender: &AccountAddress,
 ) -> Result<(), VMStatus> {
-    let total_cost = session.num_mutated_accounts(sender)
-        * gas_status
-            .cost_table()
-            .gas_constants
-            .global_memory_per_byte_write_cost
-            .mul(gas_status.cost_table().gas_constants.default_account_size)
-            .get();
+    let total_cost = total_write_cost(
+        session.num_mutated_accounts(sender),
+        &gas_status.cost_table().gas_constants,
+    );
     gas_status
         .deduct_gas(InternalGasUnits::new(total_cost))
         .map_err(|p_err| p_err.finish(Location::Undefined).into_vm_status())
 }
 
+//////// 0L ////////
+/// The write charge for one account, in internal gas units: every account a transaction
+/// mutates is charged as if `default_account_size` bytes of it were written, at
+/// `global_memory_per_byte_write_cost` per byte, whatever was actually written.
+pub fn per_account_write_cost(constants: &GasConstants) -> u64 {
+    constants
+        .global_memory_per_byte_write_cost
+        .mul(constants.default_account_size)
+        .get()
+}
+
+/// The write charge for a transaction which mutated `accounts` accounts, in internal gas
+/// units. Saturates rather than wraps, so it can only ever overcharge.
+pub fn total_write_cost(accounts: u64, constants: &GasConstants) -> u64 {
+    per_account_write_cost(constants).saturating_mul(accounts)
+}
+
+/// How the gas used by a transaction splits up, in external gas units.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct GasBreakdown {
+    /// the flat and per-byte cost of the transaction itself
+    pub intrinsic: u64,
+    /// running the transaction, i.e. everything not in the other two
+    pub execution: u64,
+    /// the charge for each account the transaction wrote to
+    pub writes: u64,
+}
+
+/// The outcome of `DiemVMImpl::estimate_sufficient_gas`, in external gas units.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct GasEstimate {
+    /// the gas the simulated run used
+    pub estimated: u64,
+    /// how `estimated` splits up
+    pub breakdown: GasBreakdown,
+    /// whether the transaction's `max_gas_amount` covers `estimated`
+    pub sufficient: bool,
+}
+
+//////// 0L ////////
+/// How a simulated transaction changes one access path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum ChangeKind {
+    Created,
+    Modified,
+    Deleted,
+}
+
+//////// 0L ////////
+/// One access path written by a simulated transaction.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct StateChange {
+    pub access_path: AccessPath,
+    pub kind: ChangeKind,
+    /// the value in the state the transaction ran against
+    pub before: Option<Vec<u8>>,
+    /// the value the transaction would leave behind
+    pub after: Option<Vec<u8>>,
+}
+
+//////// 0L ////////
+/// The outcome of `DiemVMImpl::simulate_with_diff`, in write set order.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct StateDiff {
+    pub changes: Vec<StateChange>,
+}
+
+//////// 0L ////////
+/// Diff `write_set` against the values in `state`. Deleting a value which is not there is
+/// still reported as a deletion, with no `before`.
+pub fn state_diff<S: StateView>(state: &S, write_set: &WriteSet) -> Result<StateDiff, VMStatus> {
+    let changes = write_set
+        .iter()
+        .map(|(access_path, op)| {
+            let before = state
+                .get(access_path)
+                .map_err(|_| VMStatus::Error(StatusCode::STORAGE_ERROR))?;
+            let (kind, after) = match op {
+                WriteOp::Value(value) if before.is_none() => (ChangeKind::Created, Some(value)),
+                WriteOp::Value(value) => (ChangeKind::Modified, Some(value)),
+                WriteOp::Deletion => (ChangeKind::Deleted, None),
+            };
+            Ok(StateChange {
+                access_path: access_path.clone(),
+                kind,
+                before,
+                after: after.cloned(),
+            })
+        })
+        .collect::<Result<_, VMStatus>>()?;
+    Ok(StateDiff { changes })
+}
+
+/// Split `gas_used` into intrinsic, execution and write gas, as charged by the cost table
+/// with `gas_constants`, for a transaction of `transaction_size` bytes which wrote to
+/// `mutated_accounts` accounts. The parts always add up to `gas_used`; if they would
+/// exceed it, e.g. after rounding to external units, the later parts are cut short.
+pub fn gas_breakdown(
+    gas_constants: &GasConstants,
+    transaction_size: AbstractMemorySize<GasCarrier>,
+    mutated_accounts: u64,
+    gas_used: u64,
+) -> GasBreakdown {
+    let intrinsic = gas_constants
+        .to_external_units(calculate_intrinsic_gas(transaction_size, gas_constants))
+        .get()
+        .min(gas_used);
+    let writes = gas_constants
+        .to_external_units(InternalGasUnits::new(total_write_cost(mutated_accounts, gas_constants)))
+        .get()
+        .min(gas_used - intrinsic);
+    GasBreakdown {
+        intrinsic,
+        execution: gas_used - intrinsic - writes,
+        writes,
+    }
+}
+
 pub(crate) fn get_transaction_output<A: AccessPathCache, S: MoveStorage>(
     ap_cache: &mut A,
     session: Session<S>,
@@ -696,10 +2539,11 @@ pub(crate) fn get_transaction_output<A: AccessPathCache, S: MoveStorage>(
     txn_data: &TransactionMetadata,
     status: KeptVMStatus,
 ) -> Result<TransactionOutput, VMStatus> {
-    let gas_used: u64 = txn_data.max_gas_amount().sub(gas_left).get();
+    let gas_used = gas_used(txn_data.max_gas_amount(), gas_left)?;
 
     let (changeset, events) = session.finish().map_err(|e| e.into_vm_status())?;
     let (write_set, events) = convert_changeset_and_events_cached(ap_cache, changeset, events)?;
+    check_write_set_size(&write_set, MAX_WRITE_SET_BYTES)?;
 
     Ok(TransactionOutput::new(
         write_set,
@@ -709,6 +2553,60 @@ pub(crate) fn get_transaction_output<A: AccessPathCache, S: MoveStorage>(
     ))
 }
 
+//////// 0L ////////
+/// `gas_units * gas_price` XDX in a currency worth `to_xdx_exchange_rate` XDX per unit, as a
+/// 32.32 fixed-point number, rounded down.
+pub(crate) fn fee_in_currency(
+    gas_units: u64,
+    gas_price: u64,
+    to_xdx_exchange_rate: u64,
+) -> Result<u64, VMStatus> {
+    if to_xdx_exchange_rate == 0 {
+        return Err(VMStatus::Error(StatusCode::ARITHMETIC_ERROR));
+    }
+    let fee_in_xdx = (gas_units as u128) * (gas_price as u128);
+    fee_in_xdx
+        .checked_mul(1 << 32)
+        .and_then(|fee| u64::try_from(fee / to_xdx_exchange_rate as u128).ok())
+        .ok_or(VMStatus::Error(StatusCode::ARITHMETIC_ERROR))
+}
+
+//////// 0L ////////
+/// Reject a write set whose access paths and values add up to more than `max_bytes`.
+pub(crate) fn check_write_set_size(write_set: &WriteSet, max_bytes: u64) -> Result<(), VMStatus> {
+    let size: u64 = write_set
+        .iter()
+        .map(|(ap, op)| {
+            let value_len = match op {
+                WriteOp::Value(blob) => blob.len(),
+                WriteOp::Deletion => 0,
+            };
+            (AccountAddress::LENGTH + ap.path.len() + value_len) as u64
+        })
+        .sum();
+    if size > max_bytes {
+        warn!("[VM] Write set too large {} (max {})", size, max_bytes);
+        return Err(VMStatus::Error(StatusCode::WRITE_SET_TOO_LARGE));
+    }
+    Ok(())
+}
+
+/// Gas consumed by a transaction. More gas left than the transaction started with means the
+/// gas accounting is broken, so fail the transaction rather than charge a wrapped amount.
+pub(crate) fn gas_used(
+    max_gas_amount: GasUnits<GasCarrier>,
+    gas_left: GasUnits<GasCarrier>,
+) -> Result<u64, VMStatus> {
+    max_gas_amount.get().checked_sub(gas_left.get()).ok_or_else(|| {
+        error!(
+            max_gas_amount = max_gas_amount.get(),
+            gas_left = gas_left.get(),
+            "[VM] Gas left exceeds max gas amount"
+        );
+        VMStatus::Error(StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR)
+    })
+}
+
 #[test]
 fn vm_thread_safe() {
     fn assert_send<T: Send>() {}
@@ -1,6 +1,7 @@
 // Copyright (c) The Diem Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::counters::VM_STARTUP_FAILURES;
 use diem_logger::prelude::*;
 use move_binary_format::errors::VMError;
 use move_core_types::vm_status::{known_locations, StatusCode, VMStatus};
@@ -192,3 +193,30 @@ pub fn expect_only_successful_execution(
         }
     })
 }
+
+/// Why the VM could not start. All of these surface to callers as `VM_STARTUP_FAILURE`;
+/// the reason is only used to tell them apart in logs and metrics.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StartupFailure {
+    GasScheduleNotFound,
+    DiemVersionNotFound,
+    PublishingOptionNotFound,
+}
+
+impl StartupFailure {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StartupFailure::GasScheduleNotFound => "GAS_SCHEDULE_NOT_FOUND",
+            StartupFailure::DiemVersionNotFound => "DIEM_VERSION_NOT_FOUND",
+            StartupFailure::PublishingOptionNotFound => "PUBLISHING_OPTION_NOT_FOUND",
+        }
+    }
+}
+
+/// Records a VM startup failure for `reason` and returns the status to report.
+pub fn startup_failure(reason: StartupFailure) -> VMStatus {
+    VM_STARTUP_FAILURES
+        .with_label_values(&[reason.as_str()])
+        .inc();
+    VMStatus::Error(StatusCode::VM_STARTUP_FAILURE)
+}
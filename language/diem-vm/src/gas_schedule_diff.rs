@@ -0,0 +1,130 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//////// 0L ////////
+//! Field-by-field comparison of two gas schedules, e.g. a proposed schedule against the one
+//! currently on chain.
+
+use move_core_types::gas_schedule::{CostTable, GasAlgebra, GasCarrier, GasConstants, GasCost};
+use std::fmt;
+
+/// A cost table entry which differs. `None` means the entry is missing from that table.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CostChange {
+    pub index: usize,
+    pub old: Option<GasCost>,
+    pub new: Option<GasCost>,
+}
+
+/// A gas constant which differs, by field name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConstantChange {
+    pub name: &'static str,
+    pub old: GasCarrier,
+    pub new: GasCarrier,
+}
+
+/// Everything that differs between two `CostTable`s.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CostTableDiff {
+    pub instructions: Vec<CostChange>,
+    pub natives: Vec<CostChange>,
+    pub gas_constants: Vec<ConstantChange>,
+}
+
+impl CostTableDiff {
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty() && self.natives.is_empty() && self.gas_constants.is_empty()
+    }
+}
+
+/// Compare `old` against `new`.
+pub fn diff_cost_tables(old: &CostTable, new: &CostTable) -> CostTableDiff {
+    CostTableDiff {
+        instructions: diff_costs(&old.instruction_table, &new.instruction_table),
+        natives: diff_costs(&old.native_table, &new.native_table),
+        gas_constants: diff_constants(&old.gas_constants, &new.gas_constants),
+    }
+}
+
+fn diff_costs(old: &[GasCost], new: &[GasCost]) -> Vec<CostChange> {
+    (0..old.len().max(new.len()))
+        .filter_map(|index| {
+            let (o, n) = (old.get(index), new.get(index));
+            if o == n {
+                None
+            } else {
+                Some(CostChange {
+                    index,
+                    old: o.cloned(),
+                    new: n.cloned(),
+                })
+            }
+        })
+        .collect()
+}
+
+fn diff_constants(old: &GasConstants, new: &GasConstants) -> Vec<ConstantChange> {
+    let fields = |c: &GasConstants| {
+        vec![
+            ("global_memory_per_byte_cost", c.global_memory_per_byte_cost.get()),
+            ("global_memory_per_byte_write_cost", c.global_memory_per_byte_write_cost.get()),
+            ("min_transaction_gas_units", c.min_transaction_gas_units.get()),
+            ("large_transaction_cutoff", c.large_transaction_cutoff.get()),
+            ("intrinsic_gas_per_byte", c.intrinsic_gas_per_byte.get()),
+            ("maximum_number_of_gas_units", c.maximum_number_of_gas_units.get()),
+            ("min_price_per_gas_unit", c.min_price_per_gas_unit.get()),
+            ("max_price_per_gas_unit", c.max_price_per_gas_unit.get()),
+            ("max_transaction_size_in_bytes", c.max_transaction_size_in_bytes),
+            ("gas_unit_scaling_factor", c.gas_unit_scaling_factor),
+            ("default_account_size", c.default_account_size.get()),
+        ]
+    };
+    fields(old)
+        .into_iter()
+        .zip(fields(new))
+        .filter(|((_, o), (_, n))| o != n)
+        .map(|((name, old), (_, new))| ConstantChange { name, old, new })
+        .collect()
+}
+
+fn fmt_cost(cost: &Option<GasCost>) -> String {
+    match cost {
+        Some(c) => format!(
+            "instruction {} / memory {}",
+            c.instruction_gas.get(),
+            c.memory_gas.get()
+        ),
+        None => "missing".to_string(),
+    }
+}
+
+impl fmt::Display for CostTableDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "gas schedules are identical");
+        }
+        for c in &self.gas_constants {
+            writeln!(f, "constant {}: {} -> {}", c.name, c.old, c.new)?;
+        }
+        for c in &self.instructions {
+            writeln!(
+                f,
+                "instruction #{}: {} -> {}",
+                c.index,
+                fmt_cost(&c.old),
+                fmt_cost(&c.new)
+            )?;
+        }
+        for c in &self.natives {
+            writeln!(
+                f,
+                "native #{}: {} -> {}",
+                c.index,
+                fmt_cost(&c.old),
+                fmt_cost(&c.new)
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -116,6 +116,7 @@ pub mod transaction_metadata;
 
 pub mod diem_transaction_executor;
 pub mod diem_transaction_validator;
+pub mod gas_schedule_diff;
 pub mod logging;
 pub mod script_to_script_function;
 pub mod system_module_names;
@@ -123,9 +124,21 @@ pub mod system_module_names;
 #[cfg(test)]
 mod unit_tests;
 
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod test_utils;
+
 pub use crate::{
-    diem_transaction_executor::DiemVM, diem_transaction_validator::DiemVMValidator,
-    diem_vm::convert_changeset_and_events,
+    diem_transaction_executor::DiemVM,
+    diem_transaction_validator::DiemVMValidator,
+    diem_vm::{
+        convert_changeset_and_events, convert_changeset_and_events_filtered,
+        default_gas_currency_symbol, explain_gas_rejection, gas_breakdown, gas_thresholds,
+        per_account_write_cost, read_upgrade_payload, state_diff, total_write_cost,
+        writeset_contains_reconfig, writeset_triggers_reconfig, ChangeKind, CheckGasOptions,
+        GasBreakdown, GasEstimate, GasScheduleSummary, GasThresholds, PublishingSummary,
+        StateChange, StateDiff, VmConfigSnapshot, MAX_UPGRADE_PAYLOAD_BYTES, MAX_WRITE_SET_BYTES,
+    },
+    transaction_metadata::{auth_key_from_preimage, script_hash},
 };
 
 use diem_state_view::StateView;
@@ -54,6 +54,17 @@ pub static RESET_PAYLOAD: Lazy<Identifier> =
 
 pub static UPGRADE_RECONFIG: Lazy<Identifier> =
     Lazy::new(|| Identifier::new("upgrade_reconfig").unwrap());
+
+/// Every module the VM calls into directly, which must be present at genesis.
+pub fn core_system_modules() -> Vec<ModuleId> {
+    vec![
+        account_config::ACCOUNT_MODULE.clone(),
+        DIEM_BLOCK_MODULE.clone(),
+        DIEMCONFIG_MODULE.clone(),
+        ORACLE_MODULE.clone(),
+        UPGRADE_MODULE.clone(),
+    ]
+}
 //////// 0L end ////////    
 
 // Names for special functions and structs
@@ -0,0 +1,68 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//////// 0L ////////
+//! Helpers for constructing VM inputs in tests.
+
+use crate::transaction_metadata::TransactionMetadata;
+use diem_types::{account_address::AccountAddress, chain_id::ChainId};
+use move_core_types::gas_schedule::{GasAlgebra, GasPrice, GasUnits};
+
+/// Builds a `TransactionMetadata`, starting from `TransactionMetadata::default()`.
+pub struct TransactionMetadataBuilder(TransactionMetadata);
+
+impl TransactionMetadataBuilder {
+    pub fn new() -> Self {
+        Self(TransactionMetadata::default())
+    }
+
+    pub fn sender(mut self, sender: AccountAddress) -> Self {
+        self.0.sender = sender;
+        self
+    }
+
+    pub fn sequence_number(mut self, sequence_number: u64) -> Self {
+        self.0.sequence_number = sequence_number;
+        self
+    }
+
+    pub fn max_gas_amount(mut self, max_gas_amount: u64) -> Self {
+        self.0.max_gas_amount = GasUnits::new(max_gas_amount);
+        self
+    }
+
+    pub fn gas_unit_price(mut self, gas_unit_price: u64) -> Self {
+        self.0.gas_unit_price = GasPrice::new(gas_unit_price);
+        self
+    }
+
+    pub fn expiration(mut self, expiration_timestamp_secs: u64) -> Self {
+        self.0.expiration_timestamp_secs = expiration_timestamp_secs;
+        self
+    }
+
+    /// Makes the transaction multi-agent. Each secondary signer gets the same authentication
+    /// key preimage as the default sender, so signer and key counts always match.
+    pub fn multi_agent(mut self, secondary_signers: Vec<AccountAddress>) -> Self {
+        let preimage = self.0.authentication_key_preimage.clone();
+        self.0.secondary_authentication_key_preimages =
+            secondary_signers.iter().map(|_| preimage.clone()).collect();
+        self.0.secondary_signers = secondary_signers;
+        self
+    }
+
+    pub fn chain_id(mut self, chain_id: ChainId) -> Self {
+        self.0.chain_id = chain_id;
+        self
+    }
+
+    pub fn build(self) -> TransactionMetadata {
+        self.0
+    }
+}
+
+impl Default for TransactionMetadataBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -26,6 +26,9 @@ pub struct TransactionMetadata {
     pub expiration_timestamp_secs: u64,
     pub chain_id: ChainId,
     pub script_hash: Vec<u8>,
+    //////// 0L ////////
+    /// Account paying for gas, when it is not the sender. `None` means the sender pays.
+    pub sponsor: Option<AccountAddress>,
 }
 
 impl TransactionMetadata {
@@ -51,11 +54,12 @@ impl TransactionMetadata {
             expiration_timestamp_secs: txn.expiration_timestamp_secs(),
             chain_id: txn.chain_id(),
             script_hash: match txn.payload() {
-                TransactionPayload::Script(s) => HashValue::sha3_256_of(s.code()).to_vec(),
+                TransactionPayload::Script(s) => script_hash(s.code()),
                 TransactionPayload::ScriptFunction(_) => vec![],
                 TransactionPayload::Module(_) => vec![],
                 TransactionPayload::WriteSet(_) => vec![],
             },
+            sponsor: None,
         }
     }
 
@@ -100,6 +104,28 @@ impl TransactionMetadata {
     }
 }
 
+//////// 0L ////////
+/// The account gas should be charged to: the sponsor if one is set, otherwise the sender.
+/// Only for tooling for now. The epilogue still runs as the sender, since it also checks and
+/// bumps the sender's sequence number, and no epilogue takes a separate payer yet.
+pub fn gas_payer(txn_data: &TransactionMetadata) -> AccountAddress {
+    txn_data.sponsor.unwrap_or(txn_data.sender)
+}
+
+//////// 0L ////////
+/// The hash the prologue checks against the `VMPublishingOption` script allowlist: sha3-256
+/// of the script's code. Allowlist entries must be computed the same way.
+pub fn script_hash(script_bytes: &[u8]) -> Vec<u8> {
+    HashValue::sha3_256_of(script_bytes).to_vec()
+}
+
+//////// 0L ////////
+/// The authentication key for an authentication key preimage (public key bytes followed by
+/// the scheme id): its sha3-256 hash. Clients deriving keys must use the same convention.
+pub fn auth_key_from_preimage(preimage: &[u8]) -> Vec<u8> {
+    HashValue::sha3_256_of(preimage).to_vec()
+}
+
 impl Default for TransactionMetadata {
     fn default() -> Self {
         let mut buf = [0u8; Ed25519PrivateKey::LENGTH];
@@ -117,6 +143,7 @@ impl Default for TransactionMetadata {
             expiration_timestamp_secs: 0,
             chain_id: ChainId::test(),
             script_hash: vec![],
+            sponsor: None,
         }
     }
 }
@@ -0,0 +1,1675 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    counters::UNKNOWN_BLOCK_PROPOSER,
+    data_cache::{BlockResourceCache, StorageRead, TracingStorage},
+    diem_transaction_validator::resolve_gas_currency_code,
+    diem_vm::{
+        check_no_duplicate_modules, check_secondary_signer_count, check_upgrade_is_newer,
+        check_upgrade_timestamp, check_write_set_size, convert_changeset_and_events_filtered,
+        default_gas_currency_symbol, explain_gas_rejection, fee_in_currency, gas_breakdown,
+        gas_used, get_upgrade_payload, imported_upgrade_modules, leading_upgrade_proposal,
+        module_prologue_args, multi_agent_prologue_args, per_account_write_cost,
+        preimport_upgrade_payload, preimported_upgrade_hash, read_upgrade_payload,
+        revise_upgrade_module, script_prologue_args, serialize_upgrade_module, state_diff,
+        total_write_cost, upgrade_already_applied, upgrade_module_address,
+        validate_auth_key_preimage, writeset_contains_reconfig, writeset_prologue_args,
+        writeset_triggers_reconfig, ChangeKind, CheckGasOptions, DiemVMImpl, MAX_UPGRADE_MODULES,
+        MAX_UPGRADE_PAYLOAD_BYTES,
+    },
+    gas_schedule_diff::{diff_cost_tables, ConstantChange},
+    system_module_names::core_system_modules,
+    test_utils::TransactionMetadataBuilder,
+    transaction_metadata::{auth_key_from_preimage, gas_payer, script_hash, TransactionMetadata},
+};
+use diem_crypto::HashValue;
+use diem_types::{
+    access_path::AccessPath,
+    account_address::AccountAddress,
+    account_config::{self, AccountResource},
+    block_metadata::BlockMetadata,
+    on_chain_config::{
+        DiemVersion, OnChainConfig, UpgradeConfig, VMConfig, VMPublishingOption,
+        DEFAULT_UPGRADE_APPLY_ROUND, DIEM_VERSION_2, DIEM_VERSION_3,
+    },
+    vm_status::{StatusCode, StatusType, VMStatus},
+    write_set::{WriteOp, WriteSet, WriteSetMut},
+};
+use move_binary_format::{
+    errors::{PartialVMResult, VMResult},
+    file_format::empty_module,
+    CompiledModule,
+};
+use move_core_types::{
+    gas_schedule::{AbstractMemorySize, GasAlgebra, GasConstants, GasPrice, GasUnits},
+    identifier::Identifier,
+    language_storage::{ModuleId, StructTag},
+    move_resource::MoveStructType,
+};
+use move_vm_runtime::{data_cache::MoveStorage, logging::NoContextLog};
+use move_vm_types::gas_schedule::{calculate_intrinsic_gas, zero_cost_schedule, GasStatus};
+
+fn write_set_of(paths: Vec<AccessPath>) -> WriteSet {
+    WriteSetMut::new(
+        paths
+            .into_iter()
+            .map(|ap| (ap, WriteOp::Value(vec![])))
+            .collect(),
+    )
+    .freeze()
+    .unwrap()
+}
+
+#[test]
+fn config_write_triggers_reconfig() {
+    let ws = write_set_of(vec![DiemVersion::CONFIG_ID.access_path()]);
+    assert!(writeset_triggers_reconfig(&ws));
+}
+
+#[test]
+fn core_module_write_triggers_reconfig() {
+    let module_id = ModuleId::new(
+        account_config::CORE_CODE_ADDRESS,
+        Identifier::new("DiemAccount").unwrap(),
+    );
+    let ws = write_set_of(vec![AccessPath::code_access_path(module_id)]);
+    assert!(writeset_triggers_reconfig(&ws));
+}
+
+#[test]
+fn account_write_does_not_trigger_reconfig() {
+    let ap = AccessPath::new(
+        AccountAddress::random(),
+        AccessPath::resource_access_vec(AccountResource::struct_tag()),
+    );
+    let ws = write_set_of(vec![ap]);
+    assert!(!writeset_triggers_reconfig(&ws));
+    assert!(!writeset_triggers_reconfig(&WriteSet::default()));
+}
+
+#[test]
+fn reconfig_is_detected_from_epoch_event_or_configuration_write() {
+    use diem_types::{
+        contract_event::ContractEvent,
+        event::EventKey,
+        on_chain_config::{config_address, new_epoch_event_key, ConfigurationResource},
+    };
+    use move_core_types::{language_storage::TypeTag, move_resource::MoveResource};
+
+    let event_with_key = |key| ContractEvent::new(key, 0, TypeTag::U64, vec![]);
+    let empty = write_set_of(vec![]);
+
+    assert!(writeset_contains_reconfig(
+        &empty,
+        &[event_with_key(new_epoch_event_key())]
+    ));
+    assert!(!writeset_contains_reconfig(
+        &empty,
+        &[event_with_key(EventKey::random())]
+    ));
+    assert!(!writeset_contains_reconfig(&empty, &[]));
+
+    let epoch_write = write_set_of(vec![AccessPath::new(
+        config_address(),
+        ConfigurationResource::resource_path(),
+    )]);
+    assert!(writeset_contains_reconfig(&epoch_write, &[]));
+}
+
+#[test]
+fn oversized_write_set_is_rejected() {
+    let ap = AccessPath::new(AccountAddress::random(), vec![0; 8]);
+    let write_set = WriteSetMut::new(vec![(ap, WriteOp::Value(vec![0; 100]))])
+        .freeze()
+        .unwrap();
+    let size = (AccountAddress::LENGTH + 8 + 100) as u64;
+
+    assert_eq!(check_write_set_size(&write_set, size), Ok(()));
+    assert_eq!(
+        check_write_set_size(&write_set, size - 1),
+        Err(VMStatus::Error(StatusCode::WRITE_SET_TOO_LARGE))
+    );
+    assert_eq!(
+        StatusCode::WRITE_SET_TOO_LARGE.status_type(),
+        StatusType::Execution
+    );
+}
+
+fn vm_with_publishing_option(publishing_option: VMPublishingOption) -> DiemVMImpl {
+    DiemVMImpl::init_with_config(
+        DIEM_VERSION_2,
+        VMConfig {
+            gas_schedule: zero_cost_schedule(),
+        },
+        publishing_option,
+    )
+}
+
+#[test]
+fn open_publishing_allows_any_address() {
+    let vm = vm_with_publishing_option(VMPublishingOption::open());
+    let log_context = NoContextLog::new();
+    assert!(vm
+        .check_publish_allowed(&AccountAddress::random(), &log_context)
+        .is_ok());
+    assert!(vm
+        .check_publish_allowed(&account_config::CORE_CODE_ADDRESS, &log_context)
+        .is_ok());
+}
+
+#[test]
+fn custom_scripts_publishing_is_core_only() {
+    let vm = vm_with_publishing_option(VMPublishingOption::custom_scripts());
+    let log_context = NoContextLog::new();
+    assert_eq!(
+        vm.check_publish_allowed(&AccountAddress::random(), &log_context),
+        Err(VMStatus::Error(StatusCode::INVALID_MODULE_PUBLISHER))
+    );
+    assert!(vm
+        .check_publish_allowed(&account_config::CORE_CODE_ADDRESS, &log_context)
+        .is_ok());
+}
+
+#[test]
+fn allowlist_publishing_is_core_only() {
+    let vm = vm_with_publishing_option(VMPublishingOption::locked(vec![HashValue::random()]));
+    let log_context = NoContextLog::new();
+    assert_eq!(
+        vm.check_publish_allowed(&AccountAddress::random(), &log_context),
+        Err(VMStatus::Error(StatusCode::INVALID_MODULE_PUBLISHER))
+    );
+    assert!(vm
+        .check_publish_allowed(&account_config::CORE_CODE_ADDRESS, &log_context)
+        .is_ok());
+}
+
+#[test]
+fn internals_expose_publishing_option() {
+    let option = VMPublishingOption::locked(vec![HashValue::random()]);
+    let vm = vm_with_publishing_option(option.clone());
+    assert_eq!(
+        vm.internals().publishing_option(&NoContextLog::new()),
+        Ok(&option)
+    );
+}
+
+#[test]
+fn diem_version_number_is_the_major_version() {
+    let vm = vm_with_publishing_option(VMPublishingOption::open());
+    let log_context = NoContextLog::new();
+    assert_eq!(vm.diem_version_number(&log_context), Ok(DIEM_VERSION_2.major));
+    assert_eq!(
+        vm.diem_version_at_least(&DIEM_VERSION_2, &log_context),
+        Ok(true)
+    );
+    assert_eq!(
+        vm.diem_version_at_least(&DIEM_VERSION_3, &log_context),
+        Ok(false)
+    );
+}
+
+#[test]
+fn transaction_limits_match_gas_constants() {
+    let vm = vm_with_publishing_option(VMPublishingOption::open());
+    let limits = vm.transaction_limits(&NoContextLog::new()).unwrap();
+    let gas_constants = zero_cost_schedule().gas_constants;
+    assert_eq!(
+        limits.max_transaction_size_in_bytes,
+        gas_constants.max_transaction_size_in_bytes
+    );
+    assert_eq!(
+        limits.maximum_number_of_gas_units,
+        gas_constants.maximum_number_of_gas_units.get()
+    );
+}
+
+/// Storage holding the same resource blob under every address and tag.
+struct SingleBlobStorage(Vec<u8>);
+
+impl MoveStorage for SingleBlobStorage {
+    fn get_module(&self, _module_id: &ModuleId) -> VMResult<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    fn get_resource(
+        &self,
+        _address: &AccountAddress,
+        _tag: &StructTag,
+    ) -> PartialVMResult<Option<Vec<u8>>> {
+        Ok(Some(self.0.clone()))
+    }
+}
+
+#[test]
+fn unknown_block_proposer_is_reported() {
+    use diem_crypto::{ed25519::Ed25519PrivateKey, PrivateKey, Uniform};
+    use diem_types::{on_chain_config::ValidatorSet, validator_info::ValidatorInfo};
+
+    let validator = AccountAddress::random();
+    let validator_set = ValidatorSet::new(vec![ValidatorInfo::new_with_test_network_keys(
+        validator,
+        Ed25519PrivateKey::generate_for_testing().public_key(),
+        1,
+    )]);
+    let storage = SingleBlobStorage(bcs::to_bytes(&validator_set).unwrap());
+    let unknown = AccountAddress::random();
+    let log_context = NoContextLog::new();
+
+    let vm = vm_with_publishing_option(VMPublishingOption::open());
+    // off by default: consensus is trusted
+    assert!(vm.validate_proposer(&storage, unknown, &log_context));
+
+    let vm = vm.with_proposer_validation();
+    assert!(vm.validate_proposer(&storage, validator, &log_context));
+    assert!(vm.validate_proposer(&storage, account_config::reserved_vm_address(), &log_context));
+    let reported = UNKNOWN_BLOCK_PROPOSER.get();
+    assert!(!vm.validate_proposer(&storage, unknown, &log_context));
+    assert!(UNKNOWN_BLOCK_PROPOSER.get() > reported);
+}
+
+#[test]
+fn oversized_upgrade_payload_is_rejected() {
+    let storage = SingleBlobStorage(vec![0u8; MAX_UPGRADE_PAYLOAD_BYTES + 1]);
+    assert_eq!(
+        get_upgrade_payload(&storage, &account_config::diem_root_address()).unwrap_err(),
+        VMStatus::Error(StatusCode::UPGRADE_PAYLOAD_TOO_LARGE)
+    );
+}
+
+#[test]
+fn check_gas_batch_returns_a_result_per_transaction() {
+    let vm = vm_with_publishing_option(VMPublishingOption::open());
+    let gas_constants = zero_cost_schedule().gas_constants;
+    let ok = TransactionMetadata::default();
+    let too_large = TransactionMetadata {
+        transaction_size: AbstractMemorySize::new(gas_constants.max_transaction_size_in_bytes + 1),
+        ..Default::default()
+    };
+    let too_expensive = TransactionMetadata {
+        gas_unit_price: GasPrice::new(gas_constants.max_price_per_gas_unit.get() + 1),
+        ..Default::default()
+    };
+
+    let results = vm.check_gas_batch(&[too_large, ok, too_expensive], &NoContextLog::new());
+    assert_eq!(
+        results,
+        vec![
+            Err(VMStatus::Error(StatusCode::EXCEEDED_MAX_TRANSACTION_SIZE)),
+            Ok(()),
+            Err(VMStatus::Error(StatusCode::GAS_UNIT_PRICE_ABOVE_MAX_BOUND)),
+        ]
+    );
+}
+
+#[test]
+fn oracle_tick_is_a_noop_without_ol_hooks() {
+    // No Oracle module is published in this storage, so any call into it fails.
+    let storage = SingleBlobStorage(vec![]);
+    let block = BlockMetadata::new(HashValue::zero(), 2, 0, vec![], AccountAddress::ZERO);
+    let txn_data = TransactionMetadata::default();
+    let log_context = NoContextLog::new();
+
+    let vm = vm_with_publishing_option(VMPublishingOption::open());
+    let mut session = vm.new_session(&storage);
+    assert!(vm
+        .tick_oracle_consensus(
+            &mut session,
+            &storage,
+            block.clone(),
+            &txn_data,
+            &mut GasStatus::new_unmetered(),
+            &log_context,
+        )
+        .is_err());
+
+    let vm = vm.without_ol_hooks();
+    let mut session = vm.new_session(&storage);
+    assert!(vm
+        .tick_oracle_consensus(
+            &mut session,
+            &storage,
+            block,
+            &txn_data,
+            &mut GasStatus::new_unmetered(),
+            &log_context,
+        )
+        .is_ok());
+}
+
+#[cfg(feature = "failpoints")]
+#[test]
+fn oracle_tick_fail_point_stands_in_for_the_oracle() {
+    let storage = SingleBlobStorage(vec![]);
+    let block = BlockMetadata::new(HashValue::zero(), 2, 0, vec![], AccountAddress::ZERO);
+    let vm = vm_with_publishing_option(VMPublishingOption::open());
+
+    // Without an Oracle module the tick fails on loading it; the fail point fails it
+    // with its own status instead, before anything is loaded.
+    fail::cfg("move_adapter::tick_oracle_consensus", "return(fail)").unwrap();
+    let mut session = vm.new_session(&storage);
+    let result = vm.tick_oracle_consensus(
+        &mut session,
+        &storage,
+        block,
+        &TransactionMetadata::default(),
+        &mut GasStatus::new_unmetered(),
+        &NoContextLog::new(),
+    );
+    fail::remove("move_adapter::tick_oracle_consensus");
+    assert_eq!(
+        result.unwrap_err(),
+        VMStatus::Error(StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR)
+    );
+}
+
+#[test]
+fn traced_session_records_storage_reads() {
+    let storage = SingleBlobStorage(vec![]);
+    let traced = TracingStorage::new(&storage);
+    let block = BlockMetadata::new(HashValue::zero(), 2, 0, vec![], AccountAddress::ZERO);
+    let vm = vm_with_publishing_option(VMPublishingOption::open());
+
+    // No Oracle module is published, so the tick fails after trying to load it.
+    let mut session = vm.new_session_traced(&traced);
+    assert!(vm
+        .tick_oracle_consensus(
+            &mut session,
+            &traced,
+            block,
+            &TransactionMetadata::default(),
+            &mut GasStatus::new_unmetered(),
+            &NoContextLog::new(),
+        )
+        .is_err());
+    drop(session);
+    assert!(traced
+        .reads()
+        .iter()
+        .any(|read| matches!(read, StorageRead::Module(_))));
+
+    // reads are passed through unchanged
+    let address = account_config::diem_root_address();
+    let tag = StructTag {
+        address,
+        module: Identifier::new("M").unwrap(),
+        name: Identifier::new("R").unwrap(),
+        type_params: vec![],
+    };
+    assert_eq!(
+        traced.get_resource(&address, &tag).unwrap(),
+        storage.get_resource(&address, &tag).unwrap()
+    );
+    assert_eq!(
+        traced.reads().last(),
+        Some(&StorageRead::Resource(address, tag))
+    );
+}
+
+#[test]
+fn stdlib_upgrade_waits_for_the_configured_round() {
+    // The payload blob is not a valid UpgradePayloadResource, so reading it fails.
+    let storage = SingleBlobStorage(vec![]);
+    let txn_data = TransactionMetadata::default();
+    let log_context = NoContextLog::new();
+    let vm = vm_with_publishing_option(VMPublishingOption::open()).with_upgrade_config(
+        UpgradeConfig {
+            upgrade_apply_round: 5,
+            ..UpgradeConfig::default()
+        },
+    );
+
+    let apply_at = |round: u64| {
+        let block = BlockMetadata::new(HashValue::zero(), round, 0, vec![], AccountAddress::ZERO);
+        let mut session = vm.new_session(&storage);
+        vm.apply_stdlib_upgrade(
+            &mut session,
+            &storage,
+            block,
+            &txn_data,
+            &mut GasStatus::new_unmetered(),
+            &log_context,
+        )
+    };
+    // the payload is only read at the apply round
+    assert!(apply_at(DEFAULT_UPGRADE_APPLY_ROUND).is_ok());
+    assert!(apply_at(5).is_err());
+}
+
+#[test]
+fn gas_payer_defaults_to_sender() {
+    let sender = AccountAddress::random();
+    let sponsor = AccountAddress::random();
+    let txn_data = TransactionMetadata {
+        sender,
+        secondary_signers: vec![sponsor],
+        ..Default::default()
+    };
+    assert_eq!(gas_payer(&txn_data), sender);
+
+    let sponsored = TransactionMetadata {
+        sponsor: Some(sponsor),
+        ..txn_data
+    };
+    assert_eq!(gas_payer(&sponsored), sponsor);
+}
+
+#[test]
+fn script_hash_is_sha3_256_of_the_code() {
+    let empty =
+        HashValue::from_hex("a7ffc6f8bf1ed76651c14756a061d662f580ff4de43b49fa82d80a4b80f8434a");
+    assert_eq!(script_hash(&[]), empty.unwrap().to_vec());
+    let code = vec![0xa1, 0x1c, 0xeb, 0x0b];
+    assert_eq!(script_hash(&code), HashValue::sha3_256_of(&code).to_vec());
+}
+
+#[test]
+fn auth_key_is_sha3_256_of_the_preimage() {
+    use diem_crypto::{ed25519::Ed25519PublicKey, traits::ValidCryptoMaterialStringExt};
+    use diem_types::transaction::authenticator::{AuthenticationKey, AuthenticationKeyPreimage};
+
+    // RFC 8032 test 1 public key
+    let public_key = Ed25519PublicKey::from_encoded_string(
+        "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+    )
+    .unwrap();
+    let preimage = AuthenticationKeyPreimage::ed25519(&public_key).into_vec();
+    let expected =
+        HashValue::from_hex("63c5215e87770d17b9f4cd47c777e322f4eb152cfd2054c1080fd9d57c48913b");
+    assert_eq!(auth_key_from_preimage(&preimage), expected.unwrap().to_vec());
+    assert_eq!(
+        auth_key_from_preimage(&preimage),
+        AuthenticationKey::ed25519(&public_key).to_vec()
+    );
+}
+
+#[test]
+fn cost_table_diff_reports_changed_constant() {
+    let old = zero_cost_schedule();
+    let mut new = old.clone();
+    assert!(diff_cost_tables(&old, &new).is_empty());
+
+    new.gas_constants.min_price_per_gas_unit = GasPrice::new(5);
+    let diff = diff_cost_tables(&old, &new);
+    assert!(diff.instructions.is_empty());
+    assert!(diff.natives.is_empty());
+    assert_eq!(
+        diff.gas_constants,
+        vec![ConstantChange {
+            name: "min_price_per_gas_unit",
+            old: old.gas_constants.min_price_per_gas_unit.get(),
+            new: 5,
+        }]
+    );
+}
+
+#[test]
+fn missing_core_modules_are_reported() {
+    let vm = vm_with_publishing_option(VMPublishingOption::open());
+    // This storage has no modules at all.
+    let storage = SingleBlobStorage(vec![]);
+    assert_eq!(
+        vm.verify_core_modules_present(&storage),
+        Err(core_system_modules())
+    );
+}
+
+#[test]
+fn gas_left_above_max_is_an_invariant_violation() {
+    assert_eq!(gas_used(GasUnits::new(100), GasUnits::new(40)), Ok(60));
+    assert_eq!(
+        gas_used(GasUnits::new(100), GasUnits::new(101)),
+        Err(VMStatus::Error(StatusCode::UNKNOWN_INVARIANT_VIOLATION_ERROR))
+    );
+}
+
+#[test]
+fn check_gas_options_relax_individual_checks() {
+    let vm = vm_with_publishing_option(VMPublishingOption::open());
+    let gas_constants = zero_cost_schedule().gas_constants;
+    let log_context = NoContextLog::new();
+    let strict = CheckGasOptions::default();
+
+    // Large enough that the intrinsic cost is more than one gas unit.
+    let below_min_fee = TransactionMetadata {
+        transaction_size: AbstractMemorySize::new(10_600),
+        max_gas_amount: GasUnits::new(1),
+        ..Default::default()
+    };
+    assert_eq!(
+        vm.check_gas_with_options(&below_min_fee, &strict, &log_context),
+        Err(VMStatus::Error(
+            StatusCode::MAX_GAS_UNITS_BELOW_MIN_TRANSACTION_GAS_UNITS
+        ))
+    );
+    let no_min_fee = CheckGasOptions {
+        enforce_min_fee: false,
+        ..strict
+    };
+    assert_eq!(
+        vm.check_gas_with_options(&below_min_fee, &no_min_fee, &log_context),
+        Ok(())
+    );
+
+    let too_many_units = TransactionMetadata {
+        max_gas_amount: GasUnits::new(gas_constants.maximum_number_of_gas_units.get() + 1),
+        ..Default::default()
+    };
+    assert_eq!(
+        vm.check_gas_with_options(&too_many_units, &strict, &log_context),
+        Err(VMStatus::Error(
+            StatusCode::MAX_GAS_UNITS_EXCEEDS_MAX_GAS_UNITS_BOUND
+        ))
+    );
+    let no_max_units = CheckGasOptions {
+        enforce_max_units: false,
+        ..strict
+    };
+    assert_eq!(
+        vm.check_gas_with_options(&too_many_units, &no_max_units, &log_context),
+        Ok(())
+    );
+
+    let too_expensive = TransactionMetadata {
+        gas_unit_price: GasPrice::new(gas_constants.max_price_per_gas_unit.get() + 1),
+        ..Default::default()
+    };
+    assert_eq!(
+        vm.check_gas_with_options(&too_expensive, &strict, &log_context),
+        Err(VMStatus::Error(StatusCode::GAS_UNIT_PRICE_ABOVE_MAX_BOUND))
+    );
+    let no_price_bounds = CheckGasOptions {
+        enforce_price_bounds: false,
+        ..strict
+    };
+    assert_eq!(
+        vm.check_gas_with_options(&too_expensive, &no_price_bounds, &log_context),
+        Ok(())
+    );
+
+    // The size limit is always enforced.
+    let too_large = TransactionMetadata {
+        transaction_size: AbstractMemorySize::new(gas_constants.max_transaction_size_in_bytes + 1),
+        ..Default::default()
+    };
+    let nothing = CheckGasOptions {
+        enforce_min_fee: false,
+        enforce_max_units: false,
+        enforce_price_bounds: false,
+    };
+    assert_eq!(
+        vm.check_gas_with_options(&too_large, &nothing, &log_context),
+        Err(VMStatus::Error(StatusCode::EXCEEDED_MAX_TRANSACTION_SIZE))
+    );
+}
+
+#[test]
+fn gas_rejections_are_explained_with_their_numbers() {
+    let gas_constants = GasConstants {
+        min_price_per_gas_unit: GasPrice::new(1),
+        ..zero_cost_schedule().gas_constants
+    };
+    let txn_data = TransactionMetadata {
+        gas_unit_price: GasPrice::new(0),
+        max_gas_amount: GasUnits::new(7),
+        ..Default::default()
+    };
+    let explain = |code: StatusCode| {
+        explain_gas_rejection(&VMStatus::Error(code), &txn_data, &gas_constants)
+    };
+
+    assert_eq!(
+        explain(StatusCode::GAS_UNIT_PRICE_BELOW_MIN_BOUND).unwrap(),
+        "your gas price 0 is below the minimum 1"
+    );
+    assert_eq!(
+        explain(StatusCode::GAS_UNIT_PRICE_ABOVE_MAX_BOUND).unwrap(),
+        format!(
+            "your gas price 0 is above the maximum {}",
+            gas_constants.max_price_per_gas_unit.get()
+        )
+    );
+    assert_eq!(
+        explain(StatusCode::MAX_GAS_UNITS_EXCEEDS_MAX_GAS_UNITS_BOUND).unwrap(),
+        format!(
+            "your max gas amount 7 is above the maximum of {}",
+            gas_constants.maximum_number_of_gas_units.get()
+        )
+    );
+    assert!(explain(StatusCode::MAX_GAS_UNITS_BELOW_MIN_TRANSACTION_GAS_UNITS)
+        .unwrap()
+        .starts_with("your max gas amount 7 is below the "));
+    assert_eq!(
+        explain(StatusCode::EXCEEDED_MAX_TRANSACTION_SIZE).unwrap(),
+        format!(
+            "your transaction is {} bytes, above the maximum of {}",
+            txn_data.transaction_size().get(),
+            gas_constants.max_transaction_size_in_bytes
+        )
+    );
+    assert_eq!(explain(StatusCode::SEQUENCE_NUMBER_TOO_OLD), None);
+}
+
+#[test]
+fn gas_thresholds_are_the_ones_check_gas_enforces() {
+    use move_core_types::gas_schedule::{CostTable, InternalGasUnits};
+
+    let gas_constants = GasConstants {
+        min_transaction_gas_units: InternalGasUnits::new(600_000),
+        min_price_per_gas_unit: GasPrice::new(1),
+        ..GasConstants::default()
+    };
+    let vm = vm_with_publishing_option(VMPublishingOption::open())
+        .with_overridden_gas_schedule(CostTable {
+            gas_constants: gas_constants.clone(),
+            ..zero_cost_schedule()
+        });
+    let log_context = NoContextLog::new();
+    let txn_data = |max_gas_amount: u64| TransactionMetadata {
+        gas_unit_price: GasPrice::new(1),
+        max_gas_amount: GasUnits::new(max_gas_amount),
+        ..Default::default()
+    };
+
+    let thresholds = vm.compute_gas_thresholds(&txn_data(1), &log_context).unwrap();
+    assert_eq!(thresholds.min_txn_fee, 600);
+    assert_eq!(thresholds.max_gas_units, gas_constants.maximum_number_of_gas_units.get());
+    assert_eq!(thresholds.min_price_per_gas_unit, 1);
+    assert_eq!(thresholds.max_price_per_gas_unit, gas_constants.max_price_per_gas_unit.get());
+    assert_eq!(
+        thresholds.max_transaction_size_in_bytes,
+        gas_constants.max_transaction_size_in_bytes
+    );
+
+    assert_eq!(
+        vm.check_gas(&txn_data(thresholds.min_txn_fee - 1), &log_context),
+        Err(VMStatus::Error(
+            StatusCode::MAX_GAS_UNITS_BELOW_MIN_TRANSACTION_GAS_UNITS
+        ))
+    );
+    assert_eq!(vm.check_gas(&txn_data(thresholds.min_txn_fee), &log_context), Ok(()));
+}
+
+#[test]
+fn upgrade_modules_outside_core_need_the_test_flag() {
+    let module_at = |address: AccountAddress| -> CompiledModule {
+        let mut module = empty_module();
+        module.address_identifiers[0] = address;
+        module.freeze().unwrap()
+    };
+    let test_address = AccountAddress::random();
+    let payload = vec![
+        module_at(account_config::CORE_CODE_ADDRESS),
+        module_at(test_address),
+    ];
+
+    assert_eq!(
+        upgrade_module_address(&payload[0], false),
+        Ok(account_config::CORE_CODE_ADDRESS)
+    );
+    assert_eq!(
+        upgrade_module_address(&payload[1], false),
+        Err(VMStatus::Error(StatusCode::INVALID_MODULE_PUBLISHER))
+    );
+
+    let targets: Vec<_> = payload
+        .iter()
+        .map(|module| upgrade_module_address(module, true))
+        .collect();
+    assert_eq!(
+        targets,
+        vec![Ok(account_config::CORE_CODE_ADDRESS), Ok(test_address)]
+    );
+}
+
+#[test]
+fn upgrade_modules_round_trip_before_publishing() {
+    let core = empty_module().freeze().unwrap();
+    let (address, bytes) = serialize_upgrade_module(&core, false).unwrap();
+    assert_eq!(address, account_config::CORE_CODE_ADDRESS);
+    assert_eq!(CompiledModule::deserialize(&bytes).unwrap(), core);
+
+    let mut elsewhere = empty_module();
+    elsewhere.address_identifiers[0] = AccountAddress::random();
+    assert_eq!(
+        serialize_upgrade_module(&elsewhere.freeze().unwrap(), false),
+        Err(VMStatus::Error(StatusCode::INVALID_MODULE_PUBLISHER))
+    );
+}
+
+#[test]
+fn filtered_conversion_keeps_matching_events_in_order() {
+    use diem_types::event::EventKey;
+    use move_core_types::{effects::ChangeSet, language_storage::TypeTag};
+
+    let event = |seq_num: u64, ty_tag: TypeTag| {
+        (EventKey::random().to_vec(), seq_num, ty_tag, vec![])
+    };
+    let events = vec![
+        event(0, TypeTag::U64),
+        event(1, TypeTag::Bool),
+        event(2, TypeTag::U64),
+    ];
+
+    let (write_set, kept) = convert_changeset_and_events_filtered(
+        &mut (),
+        ChangeSet::new(),
+        events,
+        |(_, _, ty_tag, _)| *ty_tag == TypeTag::U64,
+    )
+    .unwrap();
+    assert!(write_set.is_empty());
+    assert_eq!(
+        kept.iter().map(|e| e.sequence_number()).collect::<Vec<_>>(),
+        vec![0, 2]
+    );
+    assert!(kept.iter().all(|e| *e.type_tag() == TypeTag::U64));
+}
+
+#[test]
+fn upgrade_payload_is_read_once_per_block() {
+    use std::cell::Cell;
+
+    struct CountingStorage {
+        blob: Vec<u8>,
+        reads: Cell<usize>,
+    }
+
+    impl MoveStorage for CountingStorage {
+        fn get_module(&self, _module_id: &ModuleId) -> VMResult<Option<Vec<u8>>> {
+            Ok(None)
+        }
+
+        fn get_resource(
+            &self,
+            _address: &AccountAddress,
+            _tag: &StructTag,
+        ) -> PartialVMResult<Option<Vec<u8>>> {
+            self.reads.set(self.reads.get() + 1);
+            Ok(Some(self.blob.clone()))
+        }
+    }
+
+    let storage = CountingStorage {
+        blob: bcs::to_bytes(&vec![1u8, 2, 3]).unwrap(),
+        reads: Cell::new(0),
+    };
+    let address = account_config::diem_root_address();
+
+    let block_cache = BlockResourceCache::new(&storage);
+    for _ in 0..2 {
+        let resource = get_upgrade_payload(&block_cache, &address).unwrap().unwrap();
+        assert_eq!(resource.payload, vec![1, 2, 3]);
+    }
+    assert_eq!(storage.reads.get(), 1);
+
+    // the next block starts with a fresh cache
+    let next_block = BlockResourceCache::new(&storage);
+    get_upgrade_payload(&next_block, &address).unwrap();
+    assert_eq!(storage.reads.get(), 2);
+}
+
+#[test]
+fn secondary_signer_count_must_match_keys() {
+    let log_context = NoContextLog::new();
+    let signers = vec![AccountAddress::random(), AccountAddress::random()];
+    let matching = TransactionMetadataBuilder::new()
+        .multi_agent(signers.clone())
+        .build();
+    assert_eq!(check_secondary_signer_count(&matching, &log_context), Ok(()));
+
+    let mismatched = TransactionMetadata {
+        secondary_signers: signers,
+        secondary_authentication_key_preimages: vec![vec![1]],
+        ..Default::default()
+    };
+    assert_eq!(
+        check_secondary_signer_count(&mismatched, &log_context),
+        Err(VMStatus::Error(
+            StatusCode::SECONDARY_KEYS_ADDRESSES_COUNT_MISMATCH
+        ))
+    );
+}
+
+#[test]
+fn malformed_auth_key_preimages_fail_fast() {
+    use diem_crypto::{
+        ed25519::Ed25519PrivateKey, multi_ed25519::MultiEd25519PublicKey, PrivateKey, Uniform,
+    };
+    use diem_types::transaction::authenticator::AuthenticationKeyPreimage;
+
+    let public_keys: Vec<_> = (0..3)
+        .map(|_| Ed25519PrivateKey::generate_for_testing().public_key())
+        .collect();
+    let single = AuthenticationKeyPreimage::ed25519(&public_keys[0]).into_vec();
+    let multi_key = MultiEd25519PublicKey::new(public_keys, 2).unwrap();
+    let multi = AuthenticationKeyPreimage::multi_ed25519(&multi_key).into_vec();
+    assert_eq!(validate_auth_key_preimage(&single), Ok(()));
+    assert_eq!(validate_auth_key_preimage(&multi), Ok(()));
+
+    let too_short = single[1..].to_vec();
+    let mut unknown_scheme = single.clone();
+    *unknown_scheme.last_mut().unwrap() = 7;
+    let mut single_as_multi = single;
+    *single_as_multi.last_mut().unwrap() = 1;
+    for preimage in &[vec![], vec![0], too_short, unknown_scheme, single_as_multi] {
+        assert_eq!(
+            validate_auth_key_preimage(preimage),
+            Err(VMStatus::Error(StatusCode::INVALID_AUTH_KEY))
+        );
+    }
+}
+
+#[test]
+fn approved_gas_currencies_reads_the_registry() {
+    use diem_state_view::StateView;
+
+    struct RegistryView(Option<Vec<u8>>);
+
+    impl StateView for RegistryView {
+        fn get(&self, _access_path: &AccessPath) -> anyhow::Result<Option<Vec<u8>>> {
+            Ok(self.0.clone())
+        }
+
+        fn is_genesis(&self) -> bool {
+            false
+        }
+    }
+
+    let vm = vm_with_publishing_option(VMPublishingOption::open());
+    let codes = vec![Identifier::new("GAS").unwrap()];
+    let registry = RegistryView(Some(bcs::to_bytes(&codes).unwrap()));
+    assert_eq!(vm.approved_gas_currencies(&registry), Ok(codes));
+
+    let empty = RegistryView(Some(bcs::to_bytes(&Vec::<Identifier>::new()).unwrap()));
+    assert_eq!(vm.approved_gas_currencies(&empty), Ok(vec![]));
+    assert_eq!(vm.approved_gas_currencies(&RegistryView(None)), Ok(vec![]));
+}
+
+#[test]
+fn gas_fee_is_converted_at_the_exchange_rate() {
+    use diem_state_view::StateView;
+    use diem_types::{
+        account_config::CurrencyInfoResource,
+        event::{EventHandle, EventKey},
+    };
+
+    struct CurrencyView(Option<Vec<u8>>);
+
+    impl StateView for CurrencyView {
+        fn get(&self, _access_path: &AccessPath) -> anyhow::Result<Option<Vec<u8>>> {
+            Ok(self.0.clone())
+        }
+
+        fn is_genesis(&self) -> bool {
+            false
+        }
+    }
+
+    let handle = || EventHandle::new(EventKey::random(), 0);
+    // one coin is worth half an XDX
+    let half = 1u64 << 31;
+    let currency_info = CurrencyInfoResource::new(
+        0,
+        0,
+        half,
+        false,
+        1_000_000,
+        100,
+        Identifier::new("COIN").unwrap(),
+        true,
+        handle(),
+        handle(),
+        handle(),
+        handle(),
+        handle(),
+    );
+    let view = CurrencyView(Some(bcs::to_bytes(&currency_info).unwrap()));
+    let vm = vm_with_publishing_option(VMPublishingOption::open());
+    let coin = Identifier::new("COIN").unwrap();
+
+    assert_eq!(vm.convert_gas_to_currency(&view, &coin, 10, 5), Ok(100));
+    assert_eq!(
+        vm.convert_gas_to_currency(&CurrencyView(None), &coin, 10, 5),
+        Err(VMStatus::Error(StatusCode::CURRENCY_INFO_DOES_NOT_EXIST))
+    );
+    assert_eq!(
+        fee_in_currency(10, 5, 0),
+        Err(VMStatus::Error(StatusCode::ARITHMETIC_ERROR))
+    );
+    assert_eq!(
+        fee_in_currency(u64::MAX, u64::MAX, 1 << 32),
+        Err(VMStatus::Error(StatusCode::ARITHMETIC_ERROR))
+    );
+}
+
+#[test]
+fn upgrade_must_be_newer_than_the_published_framework() {
+    use move_binary_format::file_format_common::{VERSION_1, VERSION_2};
+    use std::collections::BTreeMap;
+
+    struct ModuleStorage(BTreeMap<ModuleId, Vec<u8>>);
+
+    impl MoveStorage for ModuleStorage {
+        fn get_module(&self, module_id: &ModuleId) -> VMResult<Option<Vec<u8>>> {
+            Ok(self.0.get(module_id).cloned())
+        }
+
+        fn get_resource(
+            &self,
+            _address: &AccountAddress,
+            _tag: &StructTag,
+        ) -> PartialVMResult<Option<Vec<u8>>> {
+            Ok(None)
+        }
+    }
+
+    // No resources, so no `DiemVersion` either: a payload has no version to compare with it,
+    // and applying one does not bump it, so the check can only go by the modules.
+    let module_in_version = |version: u32| -> CompiledModule {
+        let mut module = empty_module();
+        module.version = version;
+        module.freeze().unwrap()
+    };
+    let mut bytes = vec![];
+    module_in_version(VERSION_1).serialize(&mut bytes).unwrap();
+    let published = module_in_version(VERSION_1);
+    let storage = ModuleStorage(vec![(published.self_id(), bytes)].into_iter().collect());
+    let not_newer = Err(VMStatus::Error(StatusCode::UPGRADE_PAYLOAD_NOT_NEWER));
+
+    let newer = vec![module_in_version(VERSION_2)];
+    assert_eq!(check_upgrade_is_newer(&storage, &newer, false), Ok(()));
+
+    let equal = vec![published];
+    assert_eq!(check_upgrade_is_newer(&storage, &equal, false), not_newer);
+
+    let storage_v2 = {
+        let mut bytes = vec![];
+        module_in_version(VERSION_2).serialize(&mut bytes).unwrap();
+        ModuleStorage(vec![(equal[0].self_id(), bytes)].into_iter().collect())
+    };
+    let older = vec![module_in_version(VERSION_1)];
+    assert_eq!(check_upgrade_is_newer(&storage_v2, &older, false), not_newer);
+
+    // the emergency flag lets both through
+    assert_eq!(check_upgrade_is_newer(&storage, &equal, true), Ok(()));
+    assert_eq!(check_upgrade_is_newer(&storage_v2, &older, true), Ok(()));
+}
+
+#[test]
+fn preimported_upgrade_is_used_only_for_the_same_payload() {
+    let module = empty_module().freeze().unwrap();
+    let mut bytes = vec![];
+    module.serialize(&mut bytes).unwrap();
+    let payload = bcs::to_bytes(&vec![bytes]).unwrap();
+    let hash = HashValue::sha3_256_of(&payload);
+    preimport_upgrade_payload(&payload).unwrap();
+    assert_eq!(preimported_upgrade_hash(), Some(hash));
+
+    // another payload is imported on its own and leaves the pre-import in place
+    let other = bcs::to_bytes(&vec![Vec::<u8>::new(); MAX_UPGRADE_MODULES + 1]).unwrap();
+    assert_eq!(
+        imported_upgrade_modules(&other).unwrap_err(),
+        VMStatus::Error(StatusCode::UPGRADE_PAYLOAD_TOO_LARGE)
+    );
+    assert_eq!(preimported_upgrade_hash(), Some(hash));
+
+    // the pre-import is used once
+    assert_eq!(imported_upgrade_modules(&payload).unwrap(), vec![module]);
+    assert_eq!(preimported_upgrade_hash(), None);
+}
+
+#[test]
+fn rejected_upgrade_payloads_report_why() {
+    let not_a_module = bcs::to_bytes(&vec![vec![0u8]]).unwrap();
+    assert_eq!(
+        imported_upgrade_modules(&not_a_module).unwrap_err(),
+        VMStatus::Error(StatusCode::UPGRADE_MODULE_MALFORMED)
+    );
+
+    // deserializes, but the duplicate identifier fails verification
+    let mut module = empty_module();
+    module.identifiers.push(module.identifiers[0].clone());
+    let mut bytes = vec![];
+    module.freeze().unwrap().serialize(&mut bytes).unwrap();
+    let unverified = bcs::to_bytes(&vec![bytes]).unwrap();
+    assert_eq!(
+        imported_upgrade_modules(&unverified).unwrap_err(),
+        VMStatus::Error(StatusCode::UPGRADE_MODULE_UNVERIFIED)
+    );
+}
+
+#[test]
+fn empty_gas_currency_resolves_to_the_default() {
+    assert_eq!(
+        resolve_gas_currency_code("").unwrap().as_ident_str(),
+        default_gas_currency_symbol()
+    );
+    assert_eq!(resolve_gas_currency_code("XUS").unwrap().as_str(), "XUS");
+    assert_eq!(
+        resolve_gas_currency_code("not a code").unwrap_err(),
+        VMStatus::Error(StatusCode::INVALID_GAS_SPECIFIER)
+    );
+}
+
+#[test]
+fn verify_module_checks_without_publishing() {
+    use diem_state_view::StateView;
+    use move_binary_format::file_format::{AddressIdentifierIndex, IdentifierIndex, ModuleHandle};
+
+    struct EmptyView;
+
+    impl StateView for EmptyView {
+        fn get(&self, _access_path: &AccessPath) -> anyhow::Result<Option<Vec<u8>>> {
+            Ok(None)
+        }
+
+        fn is_genesis(&self) -> bool {
+            false
+        }
+    }
+
+    let vm = vm_with_publishing_option(VMPublishingOption::open());
+    let mut bytes = vec![];
+    empty_module().freeze().unwrap().serialize(&mut bytes).unwrap();
+    assert_eq!(vm.verify_module(&EmptyView, &bytes), Ok(()));
+
+    assert_eq!(
+        vm.verify_module(&EmptyView, &[0u8; 4]).unwrap_err().status_type(),
+        StatusType::Deserialization
+    );
+
+    // a dependency which is not published fails to link
+    let mut dependent = empty_module();
+    dependent.identifiers.push(Identifier::new("Missing").unwrap());
+    dependent.module_handles.push(ModuleHandle {
+        address: AddressIdentifierIndex(0),
+        name: IdentifierIndex(1),
+    });
+    let mut bytes = vec![];
+    dependent.freeze().unwrap().serialize(&mut bytes).unwrap();
+    assert_eq!(
+        vm.verify_module(&EmptyView, &bytes).unwrap_err().status_code(),
+        StatusCode::LINKER_ERROR
+    );
+}
+
+#[test]
+fn config_snapshot_summarizes_loaded_configs() {
+    use diem_state_view::StateView;
+
+    struct EmptyView;
+
+    impl StateView for EmptyView {
+        fn get(&self, _access_path: &AccessPath) -> anyhow::Result<Option<Vec<u8>>> {
+            Ok(None)
+        }
+
+        fn is_genesis(&self) -> bool {
+            false
+        }
+    }
+
+    let log_context = NoContextLog::new();
+    let vm = vm_with_publishing_option(VMPublishingOption::locked(vec![HashValue::zero()]));
+    let snapshot = vm.internals().config_snapshot(&log_context).unwrap();
+    assert_eq!(snapshot.diem_version, Some(DIEM_VERSION_2.major));
+    let gas_schedule = snapshot.gas_schedule.unwrap();
+    assert_eq!(gas_schedule.gas_constants, zero_cost_schedule().gas_constants);
+    let publishing = snapshot.publishing_option.unwrap();
+    assert!(!publishing.is_open_module && !publishing.is_open_script);
+    assert_eq!(publishing.allowed_scripts, 1);
+
+    // with nothing loaded there is nothing to report
+    let unconfigured = DiemVMImpl::new(&EmptyView);
+    assert!(unconfigured.internals().config_snapshot(&log_context).is_err());
+}
+
+#[test]
+fn account_sequence_number_reads_the_account_resource() {
+    use diem_state_view::StateView;
+    use diem_types::event::{EventHandle, EventKey};
+
+    struct AccountView(Option<Vec<u8>>);
+
+    impl StateView for AccountView {
+        fn get(&self, _access_path: &AccessPath) -> anyhow::Result<Option<Vec<u8>>> {
+            Ok(self.0.clone())
+        }
+
+        fn is_genesis(&self) -> bool {
+            false
+        }
+    }
+
+    let vm = vm_with_publishing_option(VMPublishingOption::open());
+    let address = AccountAddress::random();
+    let handle = || EventHandle::new(EventKey::random(), 0);
+    let account = AccountResource::new(42, vec![0; 32], None, None, handle(), handle());
+    let view = AccountView(Some(bcs::to_bytes(&account).unwrap()));
+    assert_eq!(vm.account_sequence_number(&view, address), Ok(42));
+    assert_eq!(
+        vm.account_sequence_number(&AccountView(None), address),
+        Err(VMStatus::Error(StatusCode::RESOURCE_DOES_NOT_EXIST))
+    );
+}
+
+#[test]
+fn upgrade_payload_with_a_module_twice_is_rejected() {
+    let module_named = |name: &str| {
+        let mut module = empty_module();
+        module.identifiers[0] = Identifier::new(name).unwrap();
+        module.freeze().unwrap()
+    };
+    let distinct = vec![module_named("A"), module_named("B")];
+    assert_eq!(check_no_duplicate_modules(&distinct), Ok(()));
+
+    let duplicated = vec![module_named("A"), module_named("B"), module_named("A")];
+    assert_eq!(
+        check_no_duplicate_modules(&duplicated),
+        Err(VMStatus::Error(StatusCode::UPGRADE_PAYLOAD_DUPLICATE_MODULE))
+    );
+}
+
+#[test]
+fn overridden_gas_schedule_replaces_the_loaded_one() {
+    let log_context = NoContextLog::new();
+    let mut table = zero_cost_schedule();
+    table.gas_constants.min_price_per_gas_unit = GasPrice::new(7);
+    let vm = vm_with_publishing_option(VMPublishingOption::open())
+        .with_overridden_gas_schedule(table.clone());
+    assert_eq!(vm.get_gas_schedule(&log_context), Ok(&table));
+    assert_ne!(table, zero_cost_schedule());
+}
+
+#[test]
+fn leading_upgrade_proposal_has_the_most_weight() {
+    use diem_types::ol_oracle_upgrade_state::{OracleResource, UpgradeOracle, VoteCount};
+
+    let count = |data: u8, total_weight: u64| VoteCount {
+        data: vec![data],
+        validators: vec![AccountAddress::random()],
+        hash: vec![],
+        total_weight,
+    };
+    let oracle = |vote_counts: Vec<VoteCount>| OracleResource {
+        upgrade: UpgradeOracle {
+            id: 1,
+            validators_voted: vec![],
+            vote_counts,
+            votes: vec![],
+            vote_window: 100,
+            version_id: 7,
+            consensus: count(0, 0),
+        },
+    };
+
+    let storage = SingleBlobStorage(bcs::to_bytes(&oracle(vec![])).unwrap());
+    assert_eq!(leading_upgrade_proposal(&storage), Ok(None));
+
+    let leading = |counts: Vec<VoteCount>| {
+        let storage = SingleBlobStorage(bcs::to_bytes(&oracle(counts)).unwrap());
+        leading_upgrade_proposal(&storage)
+            .unwrap()
+            .map(|(id, votes)| (id, votes.data))
+    };
+    // a single proposal leads
+    assert_eq!(leading(vec![count(1, 3)]), Some((7, vec![1])));
+    assert_eq!(leading(vec![count(1, 3), count(2, 5)]), Some((7, vec![2])));
+}
+
+#[test]
+fn queued_upgrade_payload_is_readable_from_state() {
+    use diem_state_view::StateView;
+    use diem_types::ol_upgrade_payload::UpgradePayloadResource;
+
+    struct PayloadView(Option<Vec<u8>>);
+
+    impl StateView for PayloadView {
+        fn get(&self, _access_path: &AccessPath) -> anyhow::Result<Option<Vec<u8>>> {
+            Ok(self.0.clone())
+        }
+
+        fn is_genesis(&self) -> bool {
+            false
+        }
+    }
+
+    let queued = UpgradePayloadResource::new(vec![1, 2, 3]);
+    let view = PayloadView(Some(bcs::to_bytes(&queued).unwrap()));
+    let read = read_upgrade_payload(&view, None).unwrap().unwrap();
+    assert_eq!(read.payload, queued.payload);
+
+    assert!(read_upgrade_payload(&PayloadView(None), None)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn gas_breakdown_parts_add_up_to_gas_used() {
+    let gas_constants = GasConstants::default();
+    let size = AbstractMemorySize::new(gas_constants.large_transaction_cutoff.get() + 100);
+    let min_fee = gas_constants
+        .to_external_units(calculate_intrinsic_gas(size, &gas_constants))
+        .get();
+
+    for gas_used in &[0, 1, min_fee, min_fee + 1, 10 * min_fee + 5_000, u64::MAX] {
+        let breakdown = gas_breakdown(&gas_constants, size, 2, *gas_used);
+        assert_eq!(
+            breakdown.intrinsic + breakdown.execution + breakdown.writes,
+            *gas_used
+        );
+    }
+
+    let breakdown = gas_breakdown(&gas_constants, size, 0, 10 * min_fee);
+    assert_eq!(breakdown.intrinsic, min_fee);
+    assert_eq!(breakdown.writes, 0);
+    assert_eq!(breakdown.execution, 9 * min_fee);
+}
+
+#[test]
+fn failed_upgrade_module_keeps_its_context() {
+    let storage = SingleBlobStorage(vec![]);
+    let vm = vm_with_publishing_option(VMPublishingOption::open());
+    let mut session = vm.new_session(&storage);
+    let module_id = empty_module().freeze().unwrap().self_id();
+
+    // the bytes are not a module, so publishing fails in the deserializer
+    let failure = revise_upgrade_module(
+        &mut session,
+        module_id.clone(),
+        3,
+        vec![0u8; 7],
+        AccountAddress::ZERO,
+        &mut GasStatus::new_unmetered(),
+        &NoContextLog::new(),
+    )
+    .unwrap_err();
+    assert_eq!(failure.module_id, module_id);
+    assert_eq!(failure.position, 3);
+    assert_eq!(failure.size, 7);
+    assert_eq!(failure.error.status_type(), StatusType::Deserialization);
+
+    let status = failure.report(&NoContextLog::new());
+    assert_eq!(status.status_type(), StatusType::Deserialization);
+}
+
+#[test]
+fn gas_estimate_covers_intrinsic_and_write_gas() {
+    use diem_crypto::{ed25519::Ed25519PrivateKey, PrivateKey, Uniform};
+    use diem_state_view::StateView;
+    use diem_types::{
+        chain_id::ChainId,
+        transaction::{Module, RawTransaction},
+    };
+
+    struct EmptyView;
+
+    impl StateView for EmptyView {
+        fn get(&self, _access_path: &AccessPath) -> anyhow::Result<Option<Vec<u8>>> {
+            Ok(None)
+        }
+
+        fn is_genesis(&self) -> bool {
+            false
+        }
+    }
+
+    let module = empty_module().freeze().unwrap();
+    let mut bytes = vec![];
+    module.serialize(&mut bytes).unwrap();
+    let private_key = Ed25519PrivateKey::generate_for_testing();
+    let publish = |max_gas_amount: u64| {
+        RawTransaction::new_module(
+            *module.address(),
+            0,
+            Module::new(bytes.clone()),
+            max_gas_amount,
+            0,
+            "GAS".to_owned(),
+            u64::MAX,
+            ChainId::test(),
+        )
+        .sign(&private_key, private_key.public_key())
+        .unwrap()
+        .into_inner()
+    };
+
+    let vm = vm_with_publishing_option(VMPublishingOption::open());
+    let estimate = vm.estimate_sufficient_gas(&EmptyView, &publish(1_000_000)).unwrap();
+    // publishing itself is free, so nearly all of it is the charge for the sender's account
+    assert!(estimate.breakdown.writes > 0);
+    assert!(estimate.estimated >= estimate.breakdown.intrinsic + estimate.breakdown.writes);
+    assert!(estimate.sufficient);
+
+    let too_little = vm.estimate_sufficient_gas(&EmptyView, &publish(1)).unwrap();
+    assert_eq!(too_little.estimated, estimate.estimated);
+    assert!(!too_little.sufficient);
+}
+
+#[test]
+fn simulated_publish_diffs_as_a_created_module() {
+    use diem_crypto::{ed25519::Ed25519PrivateKey, PrivateKey, Uniform};
+    use diem_state_view::StateView;
+    use diem_types::{
+        chain_id::ChainId,
+        transaction::{Module, RawTransaction},
+    };
+
+    struct EmptyView;
+
+    impl StateView for EmptyView {
+        fn get(&self, _access_path: &AccessPath) -> anyhow::Result<Option<Vec<u8>>> {
+            Ok(None)
+        }
+
+        fn is_genesis(&self) -> bool {
+            false
+        }
+    }
+
+    let module = empty_module().freeze().unwrap();
+    let mut bytes = vec![];
+    module.serialize(&mut bytes).unwrap();
+    let private_key = Ed25519PrivateKey::generate_for_testing();
+    let txn = RawTransaction::new_module(
+        *module.address(),
+        0,
+        Module::new(bytes.clone()),
+        1_000_000,
+        0,
+        "GAS".to_owned(),
+        u64::MAX,
+        ChainId::test(),
+    )
+    .sign(&private_key, private_key.public_key())
+    .unwrap()
+    .into_inner();
+
+    let vm = vm_with_publishing_option(VMPublishingOption::open());
+    let diff = vm.simulate_with_diff(&EmptyView, &txn).unwrap();
+    assert_eq!(diff.changes.len(), 1);
+    let change = &diff.changes[0];
+    assert_eq!(change.access_path, AccessPath::code_access_path(module.self_id()));
+    assert_eq!(change.kind, ChangeKind::Created);
+    assert_eq!(change.before, None);
+    assert_eq!(change.after, Some(bytes));
+}
+
+#[test]
+fn state_diff_classifies_each_write() {
+    use diem_state_view::StateView;
+
+    let existing = AccessPath::new(AccountAddress::random(), vec![0]);
+    let also_existing = AccessPath::new(AccountAddress::random(), vec![1]);
+    let fresh = AccessPath::new(AccountAddress::random(), vec![2]);
+
+    struct SomeValuesView(Vec<AccessPath>);
+
+    impl StateView for SomeValuesView {
+        fn get(&self, access_path: &AccessPath) -> anyhow::Result<Option<Vec<u8>>> {
+            Ok(Some(vec![7]).filter(|_| self.0.contains(access_path)))
+        }
+
+        fn is_genesis(&self) -> bool {
+            false
+        }
+    }
+
+    let view = SomeValuesView(vec![existing.clone(), also_existing.clone()]);
+    let write_set = WriteSetMut::new(vec![
+        (existing.clone(), WriteOp::Value(vec![8])),
+        (also_existing.clone(), WriteOp::Deletion),
+        (fresh.clone(), WriteOp::Value(vec![9])),
+    ])
+    .freeze()
+    .unwrap();
+
+    let diff = state_diff(&view, &write_set).unwrap();
+    let summary: Vec<_> = diff
+        .changes
+        .into_iter()
+        .map(|change| (change.access_path, change.kind, change.before, change.after))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (existing, ChangeKind::Modified, Some(vec![7]), Some(vec![8])),
+            (also_existing, ChangeKind::Deleted, Some(vec![7]), None),
+            (fresh, ChangeKind::Created, None, Some(vec![9])),
+        ]
+    );
+}
+
+#[test]
+fn upgrade_block_must_be_after_the_previous_block() {
+    use diem_types::diem_timestamp::{DiemTimestamp, DiemTimestampResource};
+
+    let previous = DiemTimestampResource {
+        diem_timestamp: DiemTimestamp { microseconds: 1_000 },
+    };
+    let storage = SingleBlobStorage(bcs::to_bytes(&previous).unwrap());
+    let proposer = AccountAddress::random();
+    assert_eq!(check_upgrade_timestamp(&storage, 1_001, proposer), Ok(()));
+    for timestamp in &[1_000, 999, 0] {
+        assert_eq!(
+            check_upgrade_timestamp(&storage, *timestamp, proposer),
+            Err(VMStatus::Error(StatusCode::UPGRADE_BLOCK_TIMESTAMP_NOT_MONOTONIC))
+        );
+    }
+
+    // a NIL block repeats the previous time
+    let nil = account_config::reserved_vm_address();
+    assert_eq!(check_upgrade_timestamp(&storage, 1_000, nil), Ok(()));
+    assert_eq!(
+        check_upgrade_timestamp(&storage, 999, nil),
+        Err(VMStatus::Error(StatusCode::UPGRADE_BLOCK_TIMESTAMP_NOT_MONOTONIC))
+    );
+}
+
+#[test]
+fn gas_price_bounds_can_be_set_per_currency() {
+    use diem_types::on_chain_config::{CurrencyGasPriceBounds, GasPriceBound};
+
+    let xus = Identifier::new("XUS").unwrap();
+    let bounds = CurrencyGasPriceBounds::new(vec![GasPriceBound {
+        currency_code: xus.clone(),
+        min_price_per_gas_unit: 5,
+        max_price_per_gas_unit: 50,
+    }]);
+    let vm = vm_with_publishing_option(VMPublishingOption::open())
+        .with_currency_gas_price_bounds(bounds);
+    let gas_constants = zero_cost_schedule().gas_constants;
+    let log_context = NoContextLog::new();
+    let priced = |price: u64| TransactionMetadata {
+        gas_unit_price: GasPrice::new(price),
+        ..Default::default()
+    };
+
+    assert_eq!(vm.check_gas_in_currency(&priced(20), &xus, &log_context), Ok(()));
+    assert_eq!(
+        vm.check_gas_in_currency(&priced(2), &xus, &log_context),
+        Err(VMStatus::Error(StatusCode::GAS_UNIT_PRICE_BELOW_MIN_BOUND))
+    );
+    assert_eq!(
+        vm.check_gas_in_currency(&priced(60), &xus, &log_context),
+        Err(VMStatus::Error(StatusCode::GAS_UNIT_PRICE_ABOVE_MAX_BOUND))
+    );
+
+    // a currency without bounds of its own uses the gas schedule's
+    let gas = default_gas_currency_symbol();
+    assert_eq!(vm.check_gas_in_currency(&priced(2), gas, &log_context), Ok(()));
+    assert_eq!(vm.check_gas_in_currency(&priced(60), gas, &log_context), Ok(()));
+    let above_global = priced(gas_constants.max_price_per_gas_unit.get() + 1);
+    assert_eq!(
+        vm.check_gas_in_currency(&above_global, gas, &log_context),
+        Err(VMStatus::Error(StatusCode::GAS_UNIT_PRICE_ABOVE_MAX_BOUND))
+    );
+}
+
+#[test]
+fn prologue_args_follow_the_move_signatures() {
+    use diem_types::chain_id::ChainId;
+    use move_core_types::value::MoveValue;
+
+    let sender = AccountAddress::random();
+    let secondary = AccountAddress::random();
+    let builder = || {
+        TransactionMetadataBuilder::new()
+            .sender(sender)
+            .sequence_number(7)
+            .gas_unit_price(3)
+            .max_gas_amount(1_000)
+            .expiration(99)
+            .chain_id(ChainId::new(4))
+    };
+    let txn_data = builder().build();
+    let preimage = txn_data.authentication_key_preimage().to_vec();
+
+    assert_eq!(
+        script_prologue_args(&txn_data),
+        vec![
+            MoveValue::Signer(sender),
+            MoveValue::U64(7),
+            MoveValue::vector_u8(preimage.clone()),
+            MoveValue::U64(3),
+            MoveValue::U64(1_000),
+            MoveValue::U64(99),
+            MoveValue::U8(4),
+            MoveValue::vector_u8(txn_data.script_hash.clone()),
+        ]
+    );
+    assert_eq!(
+        module_prologue_args(&txn_data),
+        vec![
+            MoveValue::Signer(sender),
+            MoveValue::U64(7),
+            MoveValue::vector_u8(preimage.clone()),
+            MoveValue::U64(3),
+            MoveValue::U64(1_000),
+            MoveValue::U64(99),
+            MoveValue::U8(4),
+        ]
+    );
+    assert_eq!(
+        writeset_prologue_args(&txn_data),
+        vec![
+            MoveValue::Signer(sender),
+            MoveValue::U64(7),
+            MoveValue::vector_u8(preimage.clone()),
+            MoveValue::U64(99),
+            MoveValue::U8(4),
+        ]
+    );
+
+    let multi_agent = builder().multi_agent(vec![secondary]).build();
+    assert_eq!(
+        multi_agent_prologue_args(&multi_agent),
+        vec![
+            MoveValue::Signer(sender),
+            MoveValue::U64(7),
+            MoveValue::vector_u8(preimage.clone()),
+            MoveValue::vector_address(vec![secondary]),
+            MoveValue::Vector(vec![MoveValue::vector_u8(auth_key_from_preimage(&preimage))]),
+            MoveValue::U64(3),
+            MoveValue::U64(1_000),
+            MoveValue::U64(99),
+            MoveValue::U8(4),
+        ]
+    );
+}
+
+#[test]
+fn genesis_without_a_gas_schedule_is_reported() {
+    use diem_state_view::StateView;
+    use diem_types::access_path::Path;
+
+    // Serves every module and the version and publishing configs, but no VMConfig.
+    struct NoGasScheduleView;
+
+    impl StateView for NoGasScheduleView {
+        fn get(&self, access_path: &AccessPath) -> anyhow::Result<Option<Vec<u8>>> {
+            if let Path::Code(_) = access_path.get_path() {
+                return Ok(Some(vec![0]));
+            }
+            Ok(if *access_path == DiemVersion::CONFIG_ID.access_path() {
+                Some(bcs::to_bytes(&DIEM_VERSION_2).unwrap())
+            } else if *access_path == VMPublishingOption::CONFIG_ID.access_path() {
+                Some(bcs::to_bytes(&VMPublishingOption::open()).unwrap())
+            } else {
+                None
+            })
+        }
+
+        fn is_genesis(&self) -> bool {
+            true
+        }
+    }
+
+    assert_eq!(
+        DiemVMImpl::validate_genesis(&NoGasScheduleView),
+        Err(vec!["gas schedule (VMConfig)".to_string()])
+    );
+}
+
+#[test]
+fn an_applied_upgrade_payload_is_recognized() {
+    // the state after the payload's only module was published
+    struct AppliedStorage {
+        module_id: ModuleId,
+        module: Vec<u8>,
+    }
+
+    impl MoveStorage for AppliedStorage {
+        fn get_module(&self, module_id: &ModuleId) -> VMResult<Option<Vec<u8>>> {
+            Ok(Some(self.module.clone()).filter(|_| *module_id == self.module_id))
+        }
+
+        fn get_resource(
+            &self,
+            _address: &AccountAddress,
+            _tag: &StructTag,
+        ) -> PartialVMResult<Option<Vec<u8>>> {
+            Ok(None)
+        }
+    }
+
+    let module = empty_module().freeze().unwrap();
+    let mut bytes = vec![];
+    module.serialize(&mut bytes).unwrap();
+    let storage = AppliedStorage {
+        module_id: module.self_id(),
+        module: bytes,
+    };
+
+    assert_eq!(upgrade_already_applied(&storage, &[module]), Ok(true));
+    assert_eq!(upgrade_already_applied(&storage, &[]), Ok(false));
+    let mut unpublished = empty_module();
+    unpublished.identifiers[0] = Identifier::new("Unpublished").unwrap();
+    let unpublished = unpublished.freeze().unwrap();
+    assert_eq!(upgrade_already_applied(&storage, &[unpublished]), Ok(false));
+}
+
+#[test]
+fn write_cost_is_per_byte_cost_times_account_size() {
+    use move_core_types::gas_schedule::InternalGasUnits;
+
+    let constants = GasConstants {
+        global_memory_per_byte_write_cost: InternalGasUnits::new(9),
+        default_account_size: AbstractMemorySize::new(800),
+        ..GasConstants::default()
+    };
+    assert_eq!(per_account_write_cost(&constants), 7_200);
+    assert_eq!(total_write_cost(0, &constants), 0);
+    assert_eq!(total_write_cost(3, &constants), 21_600);
+    assert_eq!(total_write_cost(u64::MAX, &constants), u64::MAX);
+
+    // the breakdown charges writes the same way, in external units
+    let breakdown = gas_breakdown(&constants, AbstractMemorySize::new(0), 3, 1_000_000);
+    assert_eq!(
+        breakdown.writes,
+        constants
+            .to_external_units(InternalGasUnits::new(21_600))
+            .get()
+    );
+}
@@ -10,6 +10,7 @@ use language_e2e_tests::{
 use move_core_types::gas_schedule::{GasAlgebra, GasPrice, GasUnits};
 use move_vm_runtime::logging::NoContextLog;
 use move_vm_types::gas_schedule::{zero_cost_schedule, GasStatus};
+use vm_genesis::genesis_gas_schedule::INITIAL_GAS_SCHEDULE;
 
 #[test]
 fn failed_transaction_cleanup_test() {
@@ -71,6 +72,46 @@ fn failed_transaction_cleanup_test() {
     }
 }
 
+//////// 0L ////////
+/// Pins existing behavior: the failure epilogue has always run unmetered, so a failed
+/// transaction is charged even with its gas used up. This guards against that regressing.
+#[test]
+fn failure_epilogue_charges_with_gas_exhausted() {
+    test_with_different_versions! {CURRENT_RELEASE_VERSIONS, |test_env| {
+        let mut executor = test_env.executor;
+        let sender = executor.create_raw_account_data(1_000_000, 10);
+        executor.add_account_data(&sender);
+
+        let log_context = NoContextLog::new();
+        let diem_vm = DiemVM::new(executor.get_state_view());
+        let data_cache = StateViewCache::new(executor.get_state_view());
+
+        let txn_data = TransactionMetadata {
+            sender: *sender.address(),
+            max_gas_amount: GasUnits::new(100_000),
+            gas_unit_price: GasPrice::new(1),
+            sequence_number: 10,
+            ..Default::default()
+        };
+
+        // A real schedule with a single unit left: far too little to run the epilogue metered.
+        let mut gas_status = GasStatus::new(&INITIAL_GAS_SCHEDULE, GasUnits::new(1));
+
+        let output = diem_vm.failed_transaction_cleanup(
+            VMStatus::Error(StatusCode::TYPE_MISMATCH),
+            &mut gas_status,
+            &txn_data,
+            &data_cache,
+            &account::xus_currency_code(),
+            &log_context,
+        );
+        assert!(!output.status().is_discarded());
+        assert!(!output.write_set().is_empty());
+        assert_eq!(output.gas_used(), 99_999);
+    }
+    }
+}
+
 #[test]
 fn non_existent_sender() {
     test_with_different_versions! {CURRENT_RELEASE_VERSIONS, |test_env| {
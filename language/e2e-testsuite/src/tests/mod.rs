@@ -43,4 +43,6 @@ mod ol_upgrade_oracle;
 mod ol_minerstate_commit;
 mod ol_demo;
 mod ol_autopay_enable;
-mod ol_autopay_create;
\ No newline at end of file
+mod ol_autopay_create;
+mod ol_epilogue_events;
+mod ol_upgrade_rejected_payloads;
\ No newline at end of file
@@ -0,0 +1,115 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//////// 0L ////////
+use diem_types::account_config;
+use diem_vm::{transaction_metadata::TransactionMetadata, DiemVM};
+use language_e2e_tests::{
+    account::{self, Account},
+    test_with_different_versions,
+    versioning::CURRENT_RELEASE_VERSIONS,
+};
+use move_core_types::{
+    gas_schedule::{GasAlgebra, GasPrice, GasUnits},
+    language_storage::TypeTag,
+};
+use move_vm_runtime::logging::NoContextLog;
+use move_vm_types::gas_schedule::{zero_cost_schedule, GasStatus};
+
+#[test]
+fn writeset_epilogue_events_are_captured() {
+    test_with_different_versions! {CURRENT_RELEASE_VERSIONS, |test_env| {
+        let executor = test_env.executor;
+        let diem_root = Account::new_diem_root();
+        let sequence_number = executor
+            .read_account_resource(&diem_root)
+            .expect("diem root must exist")
+            .sequence_number();
+        let txn_data = TransactionMetadata {
+            sender: *diem_root.address(),
+            sequence_number,
+            ..Default::default()
+        };
+
+        let state_view = executor.get_state_view();
+        let vm = DiemVM::new(state_view);
+        let events = vm.internals().with_txn_data_cache(state_view, |mut session| {
+            vm.internals()
+                .run_writeset_epilogue_with_events(
+                    &mut session,
+                    &txn_data,
+                    false,
+                    &NoContextLog::new(),
+                )
+                .unwrap()
+        });
+
+        assert_eq!(events.len(), 1);
+        match &events[0].2 {
+            TypeTag::Struct(tag) => {
+                assert_eq!(tag.address, account_config::CORE_CODE_ADDRESS);
+                assert_eq!(tag.module.as_str(), "DiemAccount");
+                assert_eq!(tag.name.as_str(), "AdminTransactionEvent");
+            }
+            tag => panic!("unexpected event type {}", tag),
+        }
+    }
+    }
+}
+
+#[test]
+fn success_epilogue_events_exclude_earlier_ones() {
+    test_with_different_versions! {CURRENT_RELEASE_VERSIONS, |test_env| {
+        let mut executor = test_env.executor;
+        let sender = executor.create_raw_account_data(1_000_000, 10);
+        executor.add_account_data(&sender);
+        let diem_root = Account::new_diem_root();
+        let root_sequence_number = executor
+            .read_account_resource(&diem_root)
+            .expect("diem root must exist")
+            .sequence_number();
+
+        let log_context = NoContextLog::new();
+        let gas_schedule = zero_cost_schedule();
+        let mut gas_status = GasStatus::new(&gas_schedule, GasUnits::new(10_000));
+        let root_txn_data = TransactionMetadata {
+            sender: *diem_root.address(),
+            sequence_number: root_sequence_number,
+            ..Default::default()
+        };
+        let txn_data = TransactionMetadata {
+            sender: *sender.address(),
+            max_gas_amount: GasUnits::new(100_000),
+            gas_unit_price: GasPrice::new(1),
+            sequence_number: 10,
+            ..Default::default()
+        };
+
+        let state_view = executor.get_state_view();
+        let vm = DiemVM::new(state_view);
+        let events = vm.internals().with_txn_data_cache(state_view, |mut session| {
+            // Leaves an event in the session before the epilogue under test runs.
+            vm.internals()
+                .run_writeset_epilogue_with_events(
+                    &mut session,
+                    &root_txn_data,
+                    false,
+                    &log_context,
+                )
+                .unwrap();
+            vm.internals()
+                .run_success_epilogue_with_events(
+                    &mut session,
+                    &mut gas_status,
+                    &txn_data,
+                    &account::xus_currency_code(),
+                    &log_context,
+                )
+                .unwrap()
+        });
+
+        // Paying the fee emits nothing, and the earlier event is not attributed to it.
+        assert!(events.is_empty());
+    }
+    }
+}
@@ -0,0 +1,189 @@
+// Copyright (c) 0lsf
+// SPDX-License-Identifier: Apache-2.0
+
+//////// 0L ////////
+use diem_crypto::HashValue;
+use diem_framework_releases::{current_module_blobs, current_modules};
+use diem_types::{
+    access_path::AccessPath,
+    block_metadata::BlockMetadata,
+    ol_upgrade_payload::UpgradePayloadResource,
+    on_chain_config::{OnChainConfig, UpgradeConfig, ValidatorSet},
+    transaction::{Transaction, TransactionOutput},
+    write_set::{WriteOp, WriteSetMut},
+};
+use diem_vm::MAX_UPGRADE_PAYLOAD_BYTES;
+use language_e2e_tests::executor::FakeExecutor;
+use move_binary_format::file_format::empty_module;
+use move_core_types::account_address::AccountAddress;
+
+/// Queues `payload` at diem root, as if the oracle had elected it.
+fn stage_upgrade_payload(executor: &mut FakeExecutor, payload: Vec<u8>) {
+    let blob = bcs::to_bytes(&UpgradePayloadResource::new(payload)).unwrap();
+    let write_set = WriteSetMut::new(vec![(
+        UpgradePayloadResource::access_path(),
+        WriteOp::Value(blob),
+    )])
+    .freeze()
+    .unwrap();
+    executor.apply_write_set(&write_set);
+}
+
+fn staged_upgrade_payload(executor: &FakeExecutor) -> Vec<u8> {
+    let blob = executor
+        .read_from_access_path(&UpgradePayloadResource::access_path())
+        .expect("the upgrade payload must be published at genesis");
+    UpgradePayloadResource::try_from_bytes(&blob).unwrap().payload
+}
+
+/// Publishes `config` as the on-chain `UpgradeConfig`, as a governance writeset would.
+fn stage_upgrade_config(executor: &mut FakeExecutor, config: UpgradeConfig) {
+    let write_set = WriteSetMut::new(vec![(
+        UpgradeConfig::CONFIG_ID.access_path(),
+        WriteOp::Value(bcs::to_bytes(&config).unwrap()),
+    )])
+    .freeze()
+    .unwrap();
+    executor.apply_write_set(&write_set);
+}
+
+/// The output of the prologue of a block in `round`, without applying it.
+fn block_prologue_output(executor: &FakeExecutor, round: u64, timestamp: u64) -> TransactionOutput {
+    let validator_set = ValidatorSet::fetch_config(executor.get_state_view())
+        .expect("Unable to retrieve the validator set from storage");
+    let block = BlockMetadata::new(
+        HashValue::zero(),
+        round,
+        timestamp,
+        vec![],
+        *validator_set.payload()[0].account_address(),
+    );
+    executor
+        .execute_transaction_block(vec![Transaction::BlockMetadata(block)])
+        .expect("Executing block prologue should succeed")
+        .pop()
+        .unwrap()
+}
+
+#[test]
+fn payload_is_only_applied_at_the_configured_round() {
+    let mut executor = FakeExecutor::from_genesis_file();
+    let payload = b"not a module list".to_vec();
+    stage_upgrade_payload(&mut executor, payload.clone());
+    stage_upgrade_config(
+        &mut executor,
+        UpgradeConfig {
+            upgrade_apply_round: 5,
+            ..UpgradeConfig::default()
+        },
+    );
+
+    // the default round no longer applies, so the payload stays queued
+    executor.new_custom_block(2);
+    assert_eq!(staged_upgrade_payload(&executor), payload);
+
+    // the configured round takes it up, and resets it since it does not import
+    executor.new_custom_block(5);
+    assert!(staged_upgrade_payload(&executor).is_empty());
+}
+
+#[test]
+fn duplicate_module_payload_is_reset_without_failing_the_block() {
+    let mut executor = FakeExecutor::from_genesis_file();
+    let module = current_module_blobs()[0].clone();
+    stage_upgrade_payload(&mut executor, bcs::to_bytes(&vec![module.clone(), module]).unwrap());
+
+    // panics if the block prologue fails
+    executor.new_custom_block(2);
+    assert!(staged_upgrade_payload(&executor).is_empty());
+}
+
+#[test]
+fn payload_with_a_module_outside_the_core_address_is_reset() {
+    let mut executor = FakeExecutor::from_genesis_file();
+    let mut module = empty_module();
+    module.address_identifiers[0] = AccountAddress::random();
+    let mut bytes = vec![];
+    module.freeze().unwrap().serialize(&mut bytes).unwrap();
+    stage_upgrade_payload(&mut executor, bcs::to_bytes(&vec![bytes]).unwrap());
+
+    executor.new_custom_block(2);
+    assert!(staged_upgrade_payload(&executor).is_empty());
+}
+
+#[test]
+fn payload_which_is_not_a_module_list_is_reset() {
+    let mut executor = FakeExecutor::from_genesis_file();
+    stage_upgrade_payload(&mut executor, b"not a module list".to_vec());
+
+    executor.new_custom_block(2);
+    assert!(staged_upgrade_payload(&executor).is_empty());
+}
+
+#[test]
+fn already_applied_payload_is_reset_without_publishing() {
+    let mut executor = FakeExecutor::from_genesis_file();
+    // the framework published at genesis, byte for byte
+    let published: Vec<Vec<u8>> = current_modules()
+        .iter()
+        .map(|module| {
+            executor
+                .read_from_access_path(&AccessPath::code_access_path(module.self_id()))
+                .expect("the framework must be published at genesis")
+        })
+        .collect();
+    let payload = bcs::to_bytes(&published).unwrap();
+
+    // elected again at every later apply round, and reset each time
+    for _ in 0..2 {
+        stage_upgrade_payload(&mut executor, payload.clone());
+        executor.new_custom_block(2);
+        assert!(staged_upgrade_payload(&executor).is_empty());
+    }
+}
+
+#[test]
+fn oversized_payload_is_reset_without_failing_the_block() {
+    let mut executor = FakeExecutor::from_genesis_file();
+    stage_upgrade_payload(&mut executor, vec![0u8; MAX_UPGRADE_PAYLOAD_BYTES + 1]);
+
+    executor.new_custom_block(2);
+    assert!(staged_upgrade_payload(&executor).is_empty());
+}
+
+#[test]
+fn unreadable_payload_resource_does_not_fail_the_block() {
+    let mut executor = FakeExecutor::from_genesis_file();
+    let write_set = WriteSetMut::new(vec![(
+        UpgradePayloadResource::access_path(),
+        WriteOp::Value(vec![0xff]),
+    )])
+    .freeze()
+    .unwrap();
+    executor.apply_write_set(&write_set);
+
+    // Move cannot reset it either, so it is left in place
+    executor.new_custom_block(2);
+    assert_eq!(
+        executor.read_from_access_path(&UpgradePayloadResource::access_path()),
+        Some(vec![0xff])
+    );
+}
+
+#[test]
+fn oracle_tick_out_of_gas_leaves_no_writes() {
+    let mut executor = FakeExecutor::from_genesis_file();
+    stage_upgrade_config(
+        &mut executor,
+        UpgradeConfig {
+            oracle_tick_gas_budget: 1,
+            ..UpgradeConfig::default()
+        },
+    );
+    let timestamp = executor.get_block_time() + 1;
+
+    // the tick only runs at round 2, so a block in round 3 is the same block without it
+    let ticked = block_prologue_output(&executor, 2, timestamp);
+    let not_ticked = block_prologue_output(&executor, 3, timestamp);
+    assert_eq!(ticked.write_set(), not_ticked.write_set());
+}
@@ -22,6 +22,7 @@ use language_e2e_tests::{
 use move_core_types::{
     identifier::Identifier, language_storage::ModuleId, transaction_argument::convert_txn_args,
 };
+use move_vm_runtime::logging::NoContextLog;
 
 #[test]
 fn initial_diem_version() {
@@ -31,7 +32,7 @@ fn initial_diem_version() {
         let vm = DiemVM::new(executor.get_state_view());
 
         assert_eq!(
-            vm.internals().diem_version().unwrap(),
+            vm.internals().diem_version(&NoContextLog::new()).unwrap(),
             DiemVersion { major: test_env.version_number }
         );
 
@@ -52,7 +53,7 @@ fn initial_diem_version() {
 
         let new_vm = DiemVM::new(executor.get_state_view());
         assert_eq!(
-            new_vm.internals().diem_version().unwrap(),
+            new_vm.internals().diem_version(&NoContextLog::new()).unwrap(),
             DiemVersion { major: test_env.version_number + 1 }
         );
     }
@@ -66,7 +67,7 @@ fn drop_txn_after_reconfiguration() {
         let vm = DiemVM::new(executor.get_state_view());
 
         assert_eq!(
-            vm.internals().diem_version().unwrap(),
+            vm.internals().diem_version(&NoContextLog::new()).unwrap(),
             DiemVersion { major: test_env.version_number }
         );
 
@@ -15,6 +15,7 @@ use language_e2e_tests::{
     compile::compile_module_with_address, test_with_different_versions,
     versioning::CURRENT_RELEASE_VERSIONS,
 };
+use move_vm_runtime::logging::NoContextLog;
 
 #[test]
 fn build_upgrade_writeset() {
@@ -68,7 +69,7 @@ fn build_upgrade_writeset() {
 
         let new_vm = DiemVM::new(executor.get_state_view());
         assert_eq!(
-            new_vm.internals().diem_version().unwrap(),
+            new_vm.internals().diem_version(&NoContextLog::new()).unwrap(),
             DiemVersion { major: 11 }
         );
 
@@ -594,6 +594,23 @@ pub enum StatusCode {
     FAILED_TO_DESERIALIZE_RESOURCE = 2020,
     // Failed to resolve type due to linking being broken after verification
     TYPE_RESOLUTION_FAILURE = 2021,
+    //////// 0L ////////
+    // The stdlib upgrade payload exceeds the size or module count the VM accepts
+    UPGRADE_PAYLOAD_TOO_LARGE = 2022,
+    // The stdlib upgrade payload is identical to, or older than, the published framework
+    UPGRADE_PAYLOAD_NOT_NEWER = 2023,
+    // The stdlib upgrade payload contains two modules with the same module id
+    UPGRADE_PAYLOAD_DUPLICATE_MODULE = 2024,
+    // The block applying a stdlib upgrade is not timestamped after the previous block
+    UPGRADE_BLOCK_TIMESTAMP_NOT_MONOTONIC = 2025,
+    // The stdlib upgrade payload is not a BCS encoded list of module blobs
+    UPGRADE_PAYLOAD_MALFORMED = 2027,
+    // A module in the stdlib upgrade payload does not deserialize
+    UPGRADE_MODULE_MALFORMED = 2028,
+    // A module in the stdlib upgrade payload fails bytecode verification
+    UPGRADE_MODULE_UNVERIFIED = 2029,
+    // A module in the stdlib upgrade payload does not serialize back to the same module
+    UPGRADE_MODULE_ROUND_TRIP_MISMATCH = 2030,
 
     // Errors that can arise from binary decoding (deserialization)
     // Deserializtion Errors: 3000-3999
@@ -635,6 +652,9 @@ pub enum StatusCode {
     CALL_STACK_OVERFLOW = 4021,
     VM_MAX_TYPE_DEPTH_REACHED = 4024,
     VM_MAX_VALUE_DEPTH_REACHED = 4025,
+    //////// 0L ////////
+    // The transaction's write set exceeds the maximum size the VM commits
+    WRITE_SET_TOO_LARGE = 4026,
 
     // A reserved status to represent an unknown vm status.
     // this is std::u64::MAX, but we can't pattern match on that, so put the hardcoded value in
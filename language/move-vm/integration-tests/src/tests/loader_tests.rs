@@ -153,3 +153,16 @@ fn load_concurrent_many() {
     // makes 150 threads
     adapter.call_functions_async(30);
 }
+
+//////// 0L ////////
+#[test]
+fn loaded_module_ids() {
+    let data_store = InMemoryStorage::new();
+    let mut adapter = Adapter::new(data_store);
+    assert!(adapter.vm.loaded_module_ids().is_empty());
+
+    adapter.publish_modules(get_modules());
+    let c = ModuleId::new(WORKING_ACCOUNT, Identifier::new("C").unwrap());
+    adapter.call_function(&c, &Identifier::new("just_c").unwrap());
+    assert!(adapter.vm.loaded_module_ids().contains(&c));
+}
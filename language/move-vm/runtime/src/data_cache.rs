@@ -146,6 +146,57 @@ impl<'r, 'l, S: MoveStorage> TransactionDataCache<'r, 'l, S> {
         Ok((change_set, events))
     }
 
+    /// Same as `into_effects`, but without consuming the cache. Every changed resource is
+    /// copied and serialized, so this is as expensive as producing the final effects.
+    pub(crate) fn effects(&self) -> PartialVMResult<(ChangeSet, Vec<Event>)> {
+        let mut change_set = ChangeSet::new();
+        for (addr, account_data_cache) in self.account_map.iter() {
+            let mut modules = BTreeMap::new();
+            for (module_name, module_blob) in account_data_cache.module_map.iter() {
+                modules.insert(module_name.clone(), Some(module_blob.clone()));
+            }
+
+            let mut resources = BTreeMap::new();
+            for (ty, (layout, gv)) in account_data_cache.data_map.iter() {
+                match gv.copy_effect()? {
+                    GlobalValueEffect::None => (),
+                    GlobalValueEffect::Deleted => {
+                        let struct_tag = match self.loader.type_to_type_tag(ty)? {
+                            TypeTag::Struct(struct_tag) => struct_tag,
+                            _ => return Err(PartialVMError::new(StatusCode::INTERNAL_TYPE_ERROR)),
+                        };
+                        resources.insert(struct_tag, None);
+                    }
+                    GlobalValueEffect::Changed(val) => {
+                        let struct_tag = match self.loader.type_to_type_tag(ty)? {
+                            TypeTag::Struct(struct_tag) => struct_tag,
+                            _ => return Err(PartialVMError::new(StatusCode::INTERNAL_TYPE_ERROR)),
+                        };
+                        let resource_blob = val
+                            .simple_serialize(layout)
+                            .ok_or_else(|| PartialVMError::new(StatusCode::INTERNAL_TYPE_ERROR))?;
+                        resources.insert(struct_tag, Some(resource_blob));
+                    }
+                }
+            }
+            change_set.publish_or_overwrite_account_change_set(
+                *addr,
+                AccountChangeSet::from_modules_resources(modules, resources),
+            );
+        }
+
+        let mut events = vec![];
+        for (guid, seq_num, ty, ty_layout, val) in self.event_data.iter() {
+            let ty_tag = self.loader.type_to_type_tag(ty)?;
+            let blob = val
+                .simple_serialize(ty_layout)
+                .ok_or_else(|| PartialVMError::new(StatusCode::INTERNAL_TYPE_ERROR))?;
+            events.push((guid.clone(), *seq_num, ty_tag, blob))
+        }
+
+        Ok((change_set, events))
+    }
+
     pub(crate) fn num_mutated_accounts(&self, sender: &AccountAddress) -> u64 {
         // The sender's account will always be mutated.
         let mut total_mutated_accounts: u64 = 1;
@@ -128,6 +128,14 @@ impl ModuleCache {
         self.modules.get(id).map(|module| Arc::clone(module))
     }
 
+    //////// 0L ////////
+    // The ids of all loaded modules, in the order they were loaded
+    fn module_ids(&self) -> Vec<ModuleId> {
+        let mut ids: Vec<_> = self.modules.id_map.iter().collect();
+        ids.sort_by_key(|(_, idx)| **idx);
+        ids.into_iter().map(|(id, _)| id.clone()).collect()
+    }
+
     // Retrieve a function by index
     fn function_at(&self, idx: usize) -> Arc<Function> {
         Arc::clone(&self.functions[idx])
@@ -430,6 +438,12 @@ impl Loader {
         }
     }
 
+    //////// 0L ////////
+    // The ids of the modules currently in the code cache, in the order they were loaded.
+    pub(crate) fn loaded_module_ids(&self) -> Vec<ModuleId> {
+        self.module_cache.read().module_ids()
+    }
+
     //
     // Script verification and loading
     //
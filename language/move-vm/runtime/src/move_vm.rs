@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{data_cache::MoveStorage, runtime::VMRuntime, session::Session};
+use move_core_types::language_storage::ModuleId;
 
 pub struct MoveVM {
     runtime: VMRuntime,
@@ -31,4 +32,11 @@ impl MoveVM {
     pub fn new_session<'r, S: MoveStorage>(&self, remote: &'r S) -> Session<'r, '_, S> {
         self.runtime.new_session(remote)
     }
+
+    //////// 0L ////////
+    /// The ids of the modules currently in the loader's code cache, in the order they were
+    /// loaded. Meant for diagnosing module resolution failures.
+    pub fn loaded_module_ids(&self) -> Vec<ModuleId> {
+        self.runtime.loaded_module_ids()
+    }
 }
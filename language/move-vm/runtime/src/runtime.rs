@@ -47,6 +47,11 @@ impl VMRuntime {
         }
     }
 
+    //////// 0L ////////
+    pub(crate) fn loaded_module_ids(&self) -> Vec<ModuleId> {
+        self.loader.loaded_module_ids()
+    }
+
     pub fn new_session<'r, S: MoveStorage>(&self, remote: &'r S) -> Session<'r, '_, S> {
         Session {
             runtime: self,
@@ -199,4 +199,16 @@ impl<'r, 'l, S: MoveStorage> Session<'r, 'l, S> {
             .into_effects()
             .map_err(|e| e.finish(Location::Undefined))
     }
+
+    /// Returns the changes `finish` would produce so far, without ending the session.
+    ///
+    /// The underlying cache cannot lend out its effects, so every changed resource is copied
+    /// and serialized on each call. Use it for inspection, not on hot paths.
+    ///
+    /// This MUST NOT be called if there is a previous invocation that failed with an invariant violation.
+    pub fn preview_changes(&self) -> VMResult<(ChangeSet, Vec<Event>)> {
+        self.data_cache
+            .effects()
+            .map_err(|e| e.finish(Location::Undefined))
+    }
 }
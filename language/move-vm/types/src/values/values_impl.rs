@@ -2002,6 +2002,21 @@ impl GlobalValueImpl {
         })
     }
 
+    fn copy_effect(&self) -> PartialVMResult<GlobalValueEffect<ValueImpl>> {
+        let copy_fields = |fields: &Rc<RefCell<Vec<ValueImpl>>>| {
+            ValueImpl::Container(Container::Struct(Rc::clone(fields))).copy_value()
+        };
+        Ok(match self {
+            Self::None => GlobalValueEffect::None,
+            Self::Deleted => GlobalValueEffect::Deleted,
+            Self::Fresh { fields } => GlobalValueEffect::Changed(copy_fields(fields)?),
+            Self::Cached { fields, status } => match &*status.borrow() {
+                GlobalDataStatus::Dirty => GlobalValueEffect::Changed(copy_fields(fields)?),
+                GlobalDataStatus::Clean => GlobalValueEffect::None,
+            },
+        })
+    }
+
     fn is_mutated(&self) -> bool {
         match self {
             Self::None => false,
@@ -2051,6 +2066,16 @@ impl GlobalValue {
         })
     }
 
+    /// Like `into_effect`, but leaves the slot untouched. Changed values are deep copied,
+    /// so this costs as much as copying the resource.
+    pub fn copy_effect(&self) -> PartialVMResult<GlobalValueEffect<Value>> {
+        Ok(match self.0.copy_effect()? {
+            GlobalValueEffect::None => GlobalValueEffect::None,
+            GlobalValueEffect::Deleted => GlobalValueEffect::Deleted,
+            GlobalValueEffect::Changed(v) => GlobalValueEffect::Changed(Value(v)),
+        })
+    }
+
     pub fn is_mutated(&self) -> bool {
         self.0.is_mutated()
     }
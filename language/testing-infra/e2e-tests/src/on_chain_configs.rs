@@ -8,6 +8,7 @@ use diem_types::{
     transaction::{Script, TransactionArgument},
 };
 use diem_vm::DiemVM;
+use move_vm_runtime::logging::NoContextLog;
 
 pub fn set_diem_version(executor: &mut FakeExecutor, version: DiemVersion) {
     let account = Account::new_genesis_account(diem_types::on_chain_config::config_address());
@@ -29,5 +30,5 @@ pub fn set_diem_version(executor: &mut FakeExecutor, version: DiemVersion) {
     executor.execute_and_apply(txn);
 
     let new_vm = DiemVM::new(executor.get_state_view());
-    assert_eq!(new_vm.internals().diem_version().unwrap(), version);
+    assert_eq!(new_vm.internals().diem_version(&NoContextLog::new()).unwrap(), version);
 }
@@ -119,8 +119,11 @@ impl Configurable<AppCfg> for OlCliCmd {
     /// settings from command-line options.
     fn process_config(
         &self,
-        config: AppCfg,
+        mut config: AppCfg,
     ) -> Result<AppCfg, FrameworkError> {
+        if let Some(preference) = entrypoint::get_args().client_preference {
+            config.profile.client_preference = preference;
+        }
         match self {
             OlCliCmd::Init(cmd) => cmd.override_config(config),
             _ => Ok(config),
@@ -6,6 +6,7 @@ use crate::{
     prelude::app_config,
     node::query::QueryType,
     node::client,
+    node::diagnostics::{diagnose_connectivity, format_diagnostics},
     node::node::Node
 };
 use std::process::exit;
@@ -66,6 +67,12 @@ pub struct QueryCmd {
     
     #[options(help = "Get a validator's on-chain config")]
     val_config: bool,
+
+    #[options(help = "transaction size and gas limits")]
+    limits: bool,
+
+    #[options(help = "reachability, version, chain id and latency of every configured node")]
+    peers: bool,
 }
 
 impl Runnable for QueryCmd {
@@ -76,6 +83,17 @@ impl Runnable for QueryCmd {
         let account = 
             if args.account.is_some() { args.account.unwrap() }
             else { cfg.profile.account };
+
+        // diagnose without picking a client, since that is what may be failing
+        if self.peers {
+            let waypoint = cfg.get_waypoint(args.swarm_path.clone()).unwrap_or_else(|e| {
+                println!("ERROR: Cannot get a waypoint. Message: {}", e);
+                exit(1);
+            });
+            let report = diagnose_connectivity(&cfg, waypoint);
+            status_info!("PEERS", format!("\n{}", format_diagnostics(&report)));
+            return;
+        }
             
         let client = client::pick_client(
             args.swarm_path.clone(), &mut cfg
@@ -144,6 +162,10 @@ impl Runnable for QueryCmd {
               };
             display = "VALIDATOR CONFIGS";
         }
+        else if self.limits {
+            query_type = QueryType::Limits;
+            display = "LIMITS";
+        }
 
         match node.query(query_type) {
             Ok(info) => {
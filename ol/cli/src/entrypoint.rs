@@ -6,6 +6,7 @@ use abscissa_core::{
 };
 use diem_types::{account_address::AccountAddress, waypoint::Waypoint};
 use diem_global_constants::NODE_HOME;
+use ol_types::config::ClientPreference;
 use reqwest::Url;
 use std::path::PathBuf;
 
@@ -59,6 +60,10 @@ where
     /// Swarm persona - what fixtures to use
     #[options(help = "use the fixtures of a persona, e.g. alice, eve")]
     pub swarm_persona: Option<String>,
+
+    /// Override which node to connect to
+    #[options(no_short, help = "which node to use: auto, local-only or remote-only")]
+    pub client_preference: Option<ClientPreference>,
 }
 
 impl<Cmd> EntryPoint<Cmd>
@@ -0,0 +1,102 @@
+//! `caching_client` memoizes ledger metadata so one command does not refetch it per check
+
+use anyhow::Error;
+use cli::diem_client::DiemClient;
+use diem_json_rpc_types::views::MetadataView;
+use std::{
+    cell::RefCell,
+    ops::Deref,
+    time::{Duration, Instant},
+};
+
+/// A value fetched at most once per `ttl`.
+#[derive(Debug)]
+pub struct TtlCache<T> {
+    ttl: Duration,
+    entry: RefCell<Option<(Instant, T)>>,
+}
+
+impl<T: Clone> TtlCache<T> {
+    /// Empty cache whose entries are fresh for `ttl`.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entry: RefCell::new(None),
+        }
+    }
+
+    /// The cached value if it was fetched less than `ttl` before `now`, otherwise the result
+    /// of `fetch`. Errors are returned without being cached.
+    pub fn get_or_fetch(
+        &self,
+        now: Instant,
+        fetch: impl FnOnce() -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        if let Some((fetched_at, value)) = &*self.entry.borrow() {
+            if now.saturating_duration_since(*fetched_at) < self.ttl {
+                return Ok(value.clone());
+            }
+        }
+        let value = fetch()?;
+        *self.entry.borrow_mut() = Some((now, value.clone()));
+        Ok(value)
+    }
+}
+
+/// A `DiemClient` whose `get_metadata` is cached for a short TTL. Every other call goes
+/// straight to the wrapped client.
+pub struct CachingClient {
+    client: DiemClient,
+    metadata: TtlCache<MetadataView>,
+}
+
+impl CachingClient {
+    /// Wrap `client`, caching its metadata for `ttl`.
+    pub fn new(client: DiemClient, ttl: Duration) -> Self {
+        Self {
+            client,
+            metadata: TtlCache::new(ttl),
+        }
+    }
+
+    /// Ledger metadata, fetched from the node only if the cached copy is stale.
+    pub fn get_metadata(&self) -> Result<MetadataView, Error> {
+        self.metadata
+            .get_or_fetch(Instant::now(), || self.client.get_metadata())
+    }
+
+    /// The wrapped client.
+    pub fn into_inner(self) -> DiemClient {
+        self.client
+    }
+}
+
+impl Deref for CachingClient {
+    type Target = DiemClient;
+
+    fn deref(&self) -> &DiemClient {
+        &self.client
+    }
+}
+
+#[test]
+fn test_ttl_cache_refetches_when_stale() {
+    let cache = TtlCache::new(Duration::from_secs(2));
+    let start = Instant::now();
+    let mut fetches = 0;
+    let mut fetch = |at: Instant| {
+        cache.get_or_fetch(at, || {
+            fetches += 1;
+            Ok(fetches)
+        })
+    };
+    assert_eq!(fetch(start).unwrap(), 1);
+    assert_eq!(fetch(start + Duration::from_secs(1)).unwrap(), 1);
+    assert_eq!(fetch(start + Duration::from_secs(2)).unwrap(), 2);
+
+    // a failed fetch is not cached, so the next call fetches again
+    let later = start + Duration::from_secs(5);
+    let failed: Result<u32, Error> = cache.get_or_fetch(later, || Err(Error::msg("down")));
+    assert!(failed.is_err());
+    assert_eq!(cache.get_or_fetch(later, || Ok(99)).unwrap(), 99);
+}
@@ -0,0 +1,108 @@
+//! `circuit_breaker` remembers upstreams which keep failing, across CLI invocations
+
+use anyhow::{Error, Result};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// file in the node home where the breaker state is kept
+pub const CIRCUIT_BREAKER_FILE: &str = "upstream_breakers.json";
+/// consecutive failed probes before an upstream is skipped
+pub const FAILURE_THRESHOLD: u32 = 3;
+/// how long an upstream is skipped once its breaker opens
+pub const COOLDOWN: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct UpstreamState {
+    consecutive_failures: u32,
+    /// seconds since the unix epoch until which the upstream is skipped
+    open_until: Option<u64>,
+}
+
+/// Consecutive failures and cooldowns per upstream url.
+/// After `FAILURE_THRESHOLD` failures in a row the breaker opens and the upstream is
+/// skipped until `COOLDOWN` has passed; the next probe after that either closes it again
+/// or, on failure, reopens it for another cooldown.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CircuitBreaker {
+    upstreams: HashMap<String, UpstreamState>,
+}
+
+impl CircuitBreaker {
+    /// Read the breaker state from the node home. A missing or unreadable file
+    /// starts with every breaker closed.
+    pub fn load(node_home: &Path) -> Self {
+        fs::read_to_string(Self::path(node_home))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    /// Write the breaker state to the node home.
+    pub fn save(&self, node_home: &Path) -> Result<(), Error> {
+        fs::write(Self::path(node_home), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    fn path(node_home: &Path) -> PathBuf {
+        node_home.join(CIRCUIT_BREAKER_FILE)
+    }
+
+    /// Whether `url` should be skipped at `now` (time since the unix epoch).
+    pub fn is_open(&self, url: &Url, now: Duration) -> bool {
+        self.upstreams
+            .get(url.as_str())
+            .and_then(|s| s.open_until)
+            .map_or(false, |until| now.as_secs() < until)
+    }
+
+    /// The upstream answered: close its breaker.
+    pub fn record_success(&mut self, url: &Url) {
+        self.upstreams.remove(url.as_str());
+    }
+
+    /// The upstream failed a probe at `now`; open the breaker once the threshold is reached.
+    pub fn record_failure(&mut self, url: &Url, now: Duration) {
+        let state = self.upstreams.entry(url.to_string()).or_default();
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        if state.consecutive_failures >= FAILURE_THRESHOLD {
+            state.open_until = Some(now.as_secs() + COOLDOWN.as_secs());
+        }
+    }
+}
+
+#[test]
+fn test_breaker_opens_and_resets() {
+    let url = Url::parse("http://1.2.3.4:8080").unwrap();
+    let now = Duration::from_secs(1_000);
+    let mut breaker = CircuitBreaker::default();
+
+    for _ in 1..FAILURE_THRESHOLD {
+        breaker.record_failure(&url, now);
+    }
+    assert!(!breaker.is_open(&url, now));
+    breaker.record_failure(&url, now);
+    assert!(breaker.is_open(&url, now));
+    assert!(!breaker.is_open(&url, now + COOLDOWN));
+
+    // a failure after the cooldown reopens it straight away
+    breaker.record_failure(&url, now + COOLDOWN);
+    assert!(breaker.is_open(&url, now + COOLDOWN));
+
+    breaker.record_success(&url);
+    assert!(!breaker.is_open(&url, now + COOLDOWN));
+
+    let dir = std::env::temp_dir().join("ol_circuit_breaker_test");
+    fs::create_dir_all(&dir).unwrap();
+    for _ in 0..FAILURE_THRESHOLD {
+        breaker.record_failure(&url, now);
+    }
+    breaker.save(&dir).unwrap();
+    assert!(CircuitBreaker::load(&dir).is_open(&url, now));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,24 +1,298 @@
 //! `bal` subcommand
 
-use crate::{config::AppCfg, entrypoint, node::node::Node, prelude::app_config};
+use crate::{
+    config::AppCfg,
+    entrypoint,
+    node::{
+        caching_client::CachingClient,
+        circuit_breaker::CircuitBreaker,
+        counters::{record_probe, CLIENTS_MADE, CLIENT_SELECTION_SECONDS, UPSTREAM_SELECTIONS},
+        health::{check_min_diem_version, check_not_regressed, probe_node_health, NodeHealth},
+        node::Node,
+        waypoints::{
+            bootstrap_waypoint_from_upstream, trusted_waypoints, verify_with_any_waypoint,
+        },
+    },
+    prelude::app_config,
+};
 use anyhow::Error;
 use anyhow::Result;
 use cli::diem_client::DiemClient;
-use diem_types::waypoint::Waypoint;
+use diem_json_rpc_types::{errors::ServerCode, views::TransactionView};
+use ol_types::config::ClientPreference;
+use diem_types::{chain_id::ChainId, transaction::SignedTransaction, waypoint::Waypoint};
 use rand::prelude::SliceRandom;
-use rand::thread_rng;
+use rand::{thread_rng, Rng};
 use reqwest::Url;
-use std::path::PathBuf;
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 /// returns a DiemClient instance.
+/// If `chain_id` is set, the client fails on the first call to a node on another chain.
 // TODO: Use app config file for params
-pub fn make_client(url: Option<Url>, waypoint: Waypoint) -> Result<DiemClient, Error> {
-    match url {
-        Some(u) => DiemClient::new(u, waypoint),
-        None => DiemClient::new(
-            Url::parse("http://localhost:8080").expect("Couldn't create diem client"),
-            waypoint,
-        ),
+pub fn make_client(
+    url: Option<Url>,
+    waypoint: Waypoint,
+    chain_id: Option<ChainId>,
+) -> Result<DiemClient, Error> {
+    let url = url.unwrap_or_else(|| {
+        Url::parse("http://localhost:8080").expect("Couldn't create diem client")
+    });
+    CLIENTS_MADE.with_label_values(&[url.as_str()]).inc();
+    let client = DiemClient::new(url, waypoint)?;
+    Ok(match chain_id {
+        Some(id) => client.with_chain_id(id),
+        None => client,
+    })
+}
+
+/// the chain id set in 0L.toml, if it parses
+pub fn expected_chain_id(config: &AppCfg) -> Option<ChainId> {
+    ChainId::from_str(&config.chain_info.chain_id).ok()
+}
+
+/// Submit a transaction, rejecting it locally if it is above the configured gas ceiling.
+pub fn submit_transaction(
+    client: &DiemClient,
+    config: &AppCfg,
+    txn: &SignedTransaction,
+) -> Result<(), Error> {
+    check_gas_ceiling(config, txn.max_gas_amount())?;
+    submit_with_backoff(client, txn)
+}
+
+/// Check that the node accepts `currency` for gas, i.e. it is a currency registered on chain.
+pub fn check_gas_currency(client: &DiemClient, currency: &str) -> Result<(), Error> {
+    let accepted: Vec<String> = client
+        .get_currency_info()?
+        .into_iter()
+        .map(|info| info.code)
+        .collect();
+    check_currency_accepted(currency, &accepted)
+}
+
+/// `currency` is one of the `accepted` currency codes
+fn check_currency_accepted(currency: &str, accepted: &[String]) -> Result<(), Error> {
+    if accepted.iter().any(|code| code == currency) {
+        return Ok(());
+    }
+    Err(Error::msg(format!(
+        "gas currency {} is not accepted by the node, accepted currencies: {}",
+        currency,
+        accepted.join(", ")
+    )))
+}
+
+/// Whether resubmitting a rejected transaction can help
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Retriability {
+    /// the node is congested or rate limiting, the same transaction may go through later
+    Retriable,
+    /// the transaction itself was rejected, e.g. bad sequence number or insufficient balance
+    NotRetriable,
+}
+
+/// What a node answered when it rejected a submission
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SubmissionStatus {
+    /// an HTTP error status, e.g. 429 from a rate limiting proxy
+    Http(u16),
+    /// a JSON-RPC error code, see `ServerCode`
+    JsonRpc(i16),
+}
+
+impl SubmissionStatus {
+    /// the status behind a failed `DiemClient::submit_transaction`, if the node answered at all
+    pub fn of(error: &Error) -> Option<Self> {
+        let error = error.downcast_ref::<diem_client::Error>()?;
+        error
+            .http_status()
+            .map(SubmissionStatus::Http)
+            .or_else(|| error.json_rpc_error().map(|e| SubmissionStatus::JsonRpc(e.code)))
+    }
+}
+
+/// A full mempool, an account at its mempool capacity and HTTP 429 are worth retrying.
+/// Anything else is a verdict on the transaction itself.
+pub fn classify_submission_error(status: SubmissionStatus) -> Retriability {
+    const TOO_MANY_REQUESTS: u16 = 429;
+    match status {
+        SubmissionStatus::Http(TOO_MANY_REQUESTS) => Retriability::Retriable,
+        SubmissionStatus::JsonRpc(code)
+            if code == ServerCode::MempoolIsFull as i16
+                || code == ServerCode::MempoolTooManyTransactions as i16 =>
+        {
+            Retriability::Retriable
+        }
+        _ => Retriability::NotRetriable,
+    }
+}
+
+/// attempts at a submission the node keeps rejecting as retriable
+const SUBMIT_MAX_ATTEMPTS: u32 = 5;
+/// first wait before resubmitting, doubled up to `POLL_MAX_BACKOFF` after each rejection
+const SUBMIT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Submit a transaction, backing off and resubmitting while the node rejects it as
+/// congested. Any other error is returned at once.
+pub fn submit_with_backoff(client: &DiemClient, txn: &SignedTransaction) -> Result<(), Error> {
+    let mut backoff = SUBMIT_INITIAL_BACKOFF;
+    let mut attempt = 1;
+    loop {
+        let err = match client.submit_transaction(txn) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        let retriable = SubmissionStatus::of(&err).map(classify_submission_error)
+            == Some(Retriability::Retriable);
+        if !retriable || attempt >= SUBMIT_MAX_ATTEMPTS {
+            return Err(err);
+        }
+        println!(
+            "submission attempt {} of {} rejected: {}, retrying in {}ms",
+            attempt,
+            SUBMIT_MAX_ATTEMPTS,
+            err,
+            backoff.as_millis()
+        );
+        std::thread::sleep(backoff);
+        backoff = next_backoff(backoff);
+        attempt += 1;
+    }
+}
+
+/// Why a node is no longer fit to take a write
+#[derive(Clone, Debug, Eq, PartialEq, thiserror::Error)]
+pub enum NotReadyForWrite {
+    /// the node did not answer the health probe
+    #[error("node is unreachable: {0}")]
+    Unreachable(String),
+    /// the node has no ledger, or it is further behind than `max_upstream_lag_secs`
+    #[error("node is behind, version: {version}, lag: {lag_secs}s")]
+    Behind {
+        /// latest ledger version
+        version: u64,
+        /// seconds behind wall-clock
+        lag_secs: u64,
+    },
+    /// the node serves another chain than the one in 0L.toml
+    #[error("node serves chain {actual}, expected chain {expected}")]
+    WrongChain {
+        /// chain id in 0L.toml
+        expected: u8,
+        /// chain id the node serves
+        actual: u8,
+    },
+}
+
+/// Last check right before submitting a write: the node answers, is not stale, and serves
+/// the chain in 0L.toml. Catches a node which degraded after `pick_client` selected it.
+pub fn ensure_ready_for_write(client: &DiemClient, config: &AppCfg) -> Result<(), Error> {
+    let health =
+        probe_node_health(client).map_err(|e| NotReadyForWrite::Unreachable(e.to_string()))?;
+    check_ready_for_write(&health, config)?;
+    Ok(())
+}
+
+/// the checks behind `ensure_ready_for_write`, on an already probed node
+fn check_ready_for_write(health: &NodeHealth, config: &AppCfg) -> Result<(), NotReadyForWrite> {
+    if !health.is_acceptable(Duration::from_secs(config.profile.max_upstream_lag_secs)) {
+        return Err(NotReadyForWrite::Behind {
+            version: health.version,
+            lag_secs: health.lag_estimate.as_secs(),
+        });
+    }
+    match expected_chain_id(config) {
+        Some(expected) if expected.id() != health.chain_id => Err(NotReadyForWrite::WrongChain {
+            expected: expected.id(),
+            actual: health.chain_id,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// first wait between polls in `submit_and_wait`
+const POLL_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// longest wait between polls in `submit_and_wait`
+const POLL_MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Submit a transaction through `submit_transaction`, with its gas ceiling and backoff,
+/// then poll for it by sender and sequence number, backing off between polls, until it is
+/// committed or `timeout` passes.
+/// The RPC calls block, so each runs on the blocking thread pool rather than the runtime.
+pub async fn submit_and_wait(
+    client: Arc<DiemClient>,
+    config: &AppCfg,
+    signed_txn: &SignedTransaction,
+    timeout: Duration,
+) -> Result<TransactionView, Error> {
+    {
+        let (client, config, txn) = (client.clone(), config.clone(), signed_txn.clone());
+        tokio::task::spawn_blocking(move || submit_transaction(&client, &config, &txn)).await??;
+    }
+    let sender = signed_txn.sender();
+    let sequence_number = signed_txn.sequence_number();
+    let deadline = Instant::now() + timeout;
+    let mut backoff = POLL_INITIAL_BACKOFF;
+    loop {
+        let poll_client = client.clone();
+        let polled = tokio::task::spawn_blocking(move || {
+            poll_client.get_txn_by_acc_seq(&sender, sequence_number, false)
+        })
+        .await?;
+        match polled {
+            Ok(Some(txn_view)) => return Ok(txn_view),
+            Ok(None) => {}
+            Err(e) => println!("error polling for transaction: {:?}", e),
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(Error::msg(format!(
+                "timed out after {}s waiting for transaction {} from {}",
+                timeout.as_secs(),
+                sequence_number,
+                sender,
+            )));
+        }
+        tokio::time::sleep(backoff.min(deadline - now)).await;
+        backoff = next_backoff(backoff);
+    }
+}
+
+/// `submit_and_wait` for callers which are not running in an async runtime
+pub fn submit_and_wait_blocking(
+    client: Arc<DiemClient>,
+    config: &AppCfg,
+    signed_txn: &SignedTransaction,
+    timeout: Duration,
+) -> Result<TransactionView, Error> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(submit_and_wait(client, config, signed_txn, timeout))
+}
+
+fn next_backoff(backoff: Duration) -> Duration {
+    (backoff * 2).min(POLL_MAX_BACKOFF)
+}
+
+/// Check a transaction's max gas against `max_gas_ceiling` in 0L.toml.
+/// This is a safety rail for the user, separate from the protocol limit enforced by the VM.
+pub fn check_gas_ceiling(config: &AppCfg, max_gas_amount: u64) -> Result<(), Error> {
+    match config.tx_configs.max_gas_ceiling {
+        Some(ceiling) if max_gas_amount > ceiling => Err(Error::msg(format!(
+            "Transaction max gas {} exceeds the max_gas_ceiling of {} set in 0L.toml",
+            max_gas_amount, ceiling
+        ))),
+        _ => Ok(()),
     }
 }
 
@@ -32,109 +306,881 @@ pub fn get_client() -> Option<DiemClient> {
     //    return default_local_client()
     // else find and return connect-able upstream node
     let config = app_config();
-    let waypoint = config
-        .get_waypoint(entry_args.swarm_path)
-        .expect("could not get waypoint");
-    if let Some(vec_urs) = config.profile.upstream_nodes.as_ref() {
-      for url in vec_urs {
-          match DiemClient::new(url.clone(), waypoint){
-            Ok(client) => { 
-              // TODO: What's the better way to check we can connect to client?
+    let waypoint = match waypoint_or_bootstrap(&config, entry_args.swarm_path) {
+        Ok(w) => w,
+        Err(e) => {
+            println!("could not get waypoint: {}", e);
+            return None;
+        }
+    };
+    let upstreams = match upstream_list(config.profile.upstream_nodes.as_deref(), &[]) {
+        Ok(list) => list,
+        Err(e) => {
+            println!("{}", e);
+            return None;
+        }
+    };
+    for url in upstreams {
+        match DiemClient::new(url, waypoint) {
+            Ok(client) => {
+                // TODO: What's the better way to check we can connect to client?
                 let metadata = client.get_metadata();
                 if metadata.is_ok() {
                     // found a connect-able upstream node
                     return Some(client);
                 }
-            },
-            Err(_) => {},
+            }
+            Err(_) => {}
         };
-      }
     }
-
-
+    println!("{}", UpstreamError::AllUpstreamsUnreachable);
     None
 }
 
-/// get client type with defaults from toml for remote node
+/// The configured waypoint, or, if there is none and the user allowed it, one bootstrapped
+/// from the upstreams. See `node::waypoints` for what that trusts.
+pub fn waypoint_or_bootstrap(
+    config: &AppCfg,
+    swarm_path: Option<PathBuf>,
+) -> Result<Waypoint, Error> {
+    config.get_waypoint(swarm_path).or_else(|e| {
+        println!("no local waypoint ({}), trying to bootstrap one from upstream", e);
+        bootstrap_waypoint_from_upstream(config)
+    })
+}
+
+/// get client type with defaults from toml for remote node.
+/// Upstreams must verify from `waypoint` or one of the `trusted_waypoints` in 0L.toml,
+/// see `node::waypoints` for the security model.
 pub fn find_a_remote_jsonrpc(config: &AppCfg, waypoint: Waypoint) -> Result<DiemClient, Error> {
-    let mut rng = thread_rng();
-    if let Some(list) = &config.profile.upstream_nodes {
-        let len = list.len();
-        let url = list.choose_multiple(&mut rng, len)
-            .into_iter()
-            .find(|&remote_url| {
-                println!("trying upstream url: {}", &remote_url);
-                match make_client(Some(remote_url.to_owned()), waypoint) {
-                    Ok(c) => {
-                      match c.get_metadata(){
-                        Ok(m) => {
-                          if m.version > 0 { true }
-                          else { 
-                            println!("can make client but could not get blockchain height > 0");
-                            false
-                          }
-                        },
-                        Err(e) => {
-                          println!("can make client but could not get metadata {:?}", e);
-                          false
-                        },
+    find_a_remote_jsonrpc_excluding(config, waypoint, &HashSet::new())
+}
+
+/// Same as `find_a_remote_jsonrpc`, but skips upstreams in `exclude`, e.g. nodes which
+/// already failed validation in this session. Falls back to the full list if nothing is left.
+pub fn find_a_remote_jsonrpc_excluding(
+    config: &AppCfg,
+    waypoint: Waypoint,
+    exclude: &HashSet<Url>,
+) -> Result<DiemClient, Error> {
+    find_a_remote_jsonrpc_with_rng(
+        config,
+        waypoint,
+        exclude,
+        &mut thread_rng(),
+        &AtomicBool::new(false),
+    )
+}
+
+/// Same as `find_a_remote_jsonrpc`, but stops probing upstreams once `cancel` is set,
+/// e.g. from a Ctrl-C handler. A probe already in flight finishes first.
+pub fn find_a_remote_jsonrpc_cancellable(
+    config: &AppCfg,
+    waypoint: Waypoint,
+    cancel: &AtomicBool,
+) -> Result<DiemClient, Error> {
+    find_a_remote_jsonrpc_with_rng(config, waypoint, &HashSet::new(), &mut thread_rng(), cancel)
+}
+
+/// Same as `find_a_remote_jsonrpc_excluding`, with the rng used to shuffle upstreams
+/// passed in, so a seeded rng gives a reproducible probe order.
+pub fn find_a_remote_jsonrpc_with_rng<R: Rng + ?Sized>(
+    config: &AppCfg,
+    waypoint: Waypoint,
+    exclude: &HashSet<Url>,
+    rng: &mut R,
+    cancel: &AtomicBool,
+) -> Result<DiemClient, Error> {
+    let _timer = CLIENT_SELECTION_SECONDS
+        .with_label_values(&["remote"])
+        .start_timer();
+    let max_lag = Duration::from_secs(config.profile.max_upstream_lag_secs);
+    let min_version = config.profile.min_diem_version;
+    let waypoints = trusted_waypoints(config, waypoint);
+    let upstreams = upstream_list(
+        config.profile.upstream_nodes.as_deref(),
+        &config.profile.preferred_upstreams,
+    )?;
+    let node_home = &config.workspace.node_home;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    let mut breaker = CircuitBreaker::load(node_home);
+    let list = without_open_breakers(&without_excluded(&upstreams, exclude), &breaker, now);
+    let preferred = &config.profile.preferred_upstreams;
+    let client = select_upstream(&list, preferred, rng, cancel, |remote_url| {
+        println!("trying upstream url: {}", &remote_url);
+        let client = match verify_with_any_waypoint(&waypoints, |wp| {
+            connect_upstream(config, remote_url, wp)
+        }) {
+            Ok(c) => match probe_node_health(&c) {
+                Ok(h) => {
+                    if h.is_acceptable(max_lag) {
+                        match check_not_regressed(&h)
+                            .and_then(|_| check_min_diem_version(&h, min_version))
+                        {
+                            Ok(()) => Some(c),
+                            Err(e) => {
+                                println!("rejecting upstream: {}", e);
+                                None
+                            }
+                        }
+                    } else {
+                        println!(
+                            "upstream is unhealthy, version: {}, lag: {}s",
+                            h.version,
+                            h.lag_estimate.as_secs()
+                        );
+                        None
                     }
-                    },
-                    Err(e) => {
-                      println!("could not make client {:?}", e);
-                      false
-                    },
                 }
-            });
-            
-            if let Some(url_clean) = url {
-              return make_client(Some(url_clean.to_owned()), waypoint);
-            }; 
-            
+                Err(e) => {
+                    println!("can make client but could not get metadata {:?}", e);
+                    None
+                }
+            },
+            Err(e) => {
+                println!("could not make a verified client {:?}", e);
+                None
+            }
+        };
+        record_probe(remote_url, client.is_some());
+        match client {
+            Some(_) => {
+                breaker.record_success(remote_url);
+                UPSTREAM_SELECTIONS.with_label_values(&[remote_url.as_str()]).inc();
+            }
+            None => breaker.record_failure(remote_url, now),
+        }
+        client
+    });
+
+    if let Err(e) = breaker.save(node_home) {
+        println!("could not save upstream circuit breaker state: {:?}", e);
+    }
+    Ok(picked_or_unreachable(client, cancel)?)
+}
+
+/// the upstream `select_upstream` picked, or `AllUpstreamsUnreachable` if it found none
+fn picked_or_unreachable<T>(picked: Option<T>, cancel: &AtomicBool) -> Result<T, UpstreamError> {
+    if let Some(c) = picked {
+        return Ok(c);
+    };
+    if cancel.load(Ordering::SeqCst) {
+        println!("upstream probing cancelled");
+    }
+    Err(UpstreamError::AllUpstreamsUnreachable)
+}
+
+/// ledger versions at most this far apart count as agreeing in `find_agreeing_remotes`
+pub const QUORUM_VERSION_TOLERANCE: u64 = 100;
+
+/// Probe every upstream and return one only if at least `quorum` of them agree on the
+/// ledger version, within `QUORUM_VERSION_TOLERANCE`, so a single lying or lagging upstream
+/// cannot decide what the CLI reads. The most recent node of the largest agreeing group is
+/// returned. A quorum of 1 is the same as `find_a_remote_jsonrpc`.
+pub fn find_agreeing_remotes(
+    config: &AppCfg,
+    waypoint: Waypoint,
+    quorum: usize,
+) -> Result<DiemClient, Error> {
+    if quorum <= 1 {
+        return find_a_remote_jsonrpc(config, waypoint);
+    }
+    let waypoints = trusted_waypoints(config, waypoint);
+    let upstreams = upstream_list(
+        config.profile.upstream_nodes.as_deref(),
+        &config.profile.preferred_upstreams,
+    )?;
+    let mut probed: Vec<(DiemClient, u64)> = upstreams
+        .iter()
+        .filter_map(|url| {
+            let client =
+                verify_with_any_waypoint(&waypoints, |wp| connect_upstream(config, url, wp));
+            match client.and_then(|c| probe_node_health(&c).map(|h| (c, h.version))) {
+                Ok(probed) => Some(probed),
+                Err(e) => {
+                    println!("could not probe upstream {}: {:?}", url, e);
+                    None
+                }
+            }
+        })
+        .collect();
+    let versions: Vec<u64> = probed.iter().map(|(_, version)| *version).collect();
+    let group = largest_agreeing_group(&versions, QUORUM_VERSION_TOLERANCE);
+    if group.len() < quorum {
+        return Err(Error::msg(format!(
+            "only {} of {} upstreams agree on the ledger version, {} required",
+            group.len(),
+            upstreams.len(),
+            quorum
+        )));
+    }
+    let newest = *group.last().expect("a quorum is never empty");
+    Ok(probed.swap_remove(newest).0)
+}
+
+/// indices of the largest set of `versions` all within `tolerance` of each other,
+/// ordered by version
+fn largest_agreeing_group(versions: &[u64], tolerance: u64) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..versions.len()).collect();
+    order.sort_by_key(|i| versions[*i]);
+    let mut best = 0..0;
+    let mut start = 0;
+    for (end, &i) in order.iter().enumerate() {
+        while versions[i] - versions[order[start]] > tolerance {
+            start += 1;
+        }
+        if end + 1 - start > best.len() {
+            best = start..end + 1;
+        }
+    }
+    order[best].to_vec()
+}
+
+/// Why no upstream could be selected
+#[derive(Copy, Clone, Debug, Eq, PartialEq, thiserror::Error)]
+pub enum UpstreamError {
+    /// there is no upstream_nodes entry in 0L.toml
+    #[error("upstream_nodes is not set in 0L.toml")]
+    UpstreamsNotConfigured,
+    /// upstream_nodes is set in 0L.toml, but lists no nodes
+    #[error("upstream_nodes in 0L.toml is empty")]
+    UpstreamsEmpty,
+    /// every upstream was tried and none was usable
+    #[error("Cannot connect to any JSON RPC peers in the list of upstream_nodes in 0L.toml")]
+    AllUpstreamsUnreachable,
+}
+
+/// the upstreams to probe: `upstream_nodes` with any `preferred` upstreams added,
+/// or why there are none
+fn upstream_list(
+    upstream_nodes: Option<&[Url]>,
+    preferred: &[Url],
+) -> Result<Vec<Url>, UpstreamError> {
+    if upstream_nodes.is_none() && preferred.is_empty() {
+        return Err(UpstreamError::UpstreamsNotConfigured);
+    }
+    let list = with_preferred_upstreams(upstream_nodes.unwrap_or_default(), preferred);
+    if list.is_empty() {
+        return Err(UpstreamError::UpstreamsEmpty);
+    }
+    Ok(list)
+}
+
+/// return the first upstream `probe` accepts, trying those of the list which are `preferred`
+/// in that order, then the rest shuffled with `rng`.
+/// No more probes are started once `cancel` is set
+fn select_upstream<R: Rng + ?Sized, T>(
+    list: &[Url],
+    preferred: &[Url],
+    rng: &mut R,
+    cancel: &AtomicBool,
+    probe: impl FnMut(&Url) -> Option<T>,
+) -> Option<T> {
+    let rest: Vec<&Url> = list.iter().filter(|url| !preferred.contains(url)).collect();
+    preferred
+        .iter()
+        .filter(|url| list.contains(url))
+        .chain(rest.choose_multiple(rng, rest.len()).copied())
+        .take_while(|_| !cancel.load(Ordering::SeqCst))
+        .find_map(probe)
+}
+
+/// the upstream list with any preferred upstreams it is missing added
+fn with_preferred_upstreams(upstreams: &[Url], preferred: &[Url]) -> Vec<Url> {
+    let mut list = upstreams.to_vec();
+    for url in preferred {
+        if !list.contains(url) {
+            list.push(url.clone());
+        }
+    }
+    list
+}
+
+/// drop the excluded urls from the list, unless that would leave nothing to try
+fn without_excluded(list: &[Url], exclude: &HashSet<Url>) -> Vec<Url> {
+    let remaining: Vec<Url> = list
+        .iter()
+        .filter(|url| !exclude.contains(*url))
+        .cloned()
+        .collect();
+    if remaining.is_empty() {
+        list.to_vec()
+    } else {
+        remaining
+    }
+}
+
+/// drop upstreams whose circuit breaker is open, unless that would leave nothing to try
+fn without_open_breakers(list: &[Url], breaker: &CircuitBreaker, now: Duration) -> Vec<Url> {
+    let remaining: Vec<Url> = list
+        .iter()
+        .filter(|url| !breaker.is_open(url, now))
+        .cloned()
+        .collect();
+    if remaining.is_empty() {
+        list.to_vec()
+    } else {
+        remaining
+    }
+}
+
+/// make a client for an upstream, trying https before http if `probe_https_first` is set
+fn connect_upstream(config: &AppCfg, url: &Url, waypoint: Waypoint) -> Result<DiemClient, Error> {
+    if config.profile.probe_https_first {
+        normalize_and_probe(url.as_str(), waypoint, expected_chain_id(config))
+    } else {
+        make_client(Some(url.to_owned()), waypoint, expected_chain_id(config))
     }
-    Err(Error::msg(
-        "Cannot connect to any JSON RPC peers in the list of upstream_nodes in 0L.toml",
-    ))
 }
 
-/// get client type with defaults from toml for local node
+/// Given a host with or without a scheme, try to connect over https and then http.
+/// Returns the client for the first scheme which serves metadata.
+pub fn normalize_and_probe(
+    host: &str,
+    waypoint: Waypoint,
+    chain_id: Option<ChainId>,
+) -> Result<DiemClient, Error> {
+    let bare_host = host
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_end_matches('/');
+
+    for scheme in &["https", "http"] {
+        let url = match Url::parse(&format!("{}://{}", scheme, bare_host)) {
+            Ok(u) => u,
+            Err(e) => {
+                println!("could not parse upstream {} as {} url: {:?}", bare_host, scheme, e);
+                continue;
+            }
+        };
+        if let Ok(client) = make_client(Some(url), waypoint, chain_id) {
+            if client.get_metadata().is_ok() {
+                return Ok(client);
+            }
+        }
+        println!("could not reach upstream {} over {}", bare_host, scheme);
+    }
+
+    Err(Error::msg(format!(
+        "Cannot reach upstream {} over https or http",
+        host
+    )))
+}
+
+/// get client type with defaults from toml for local node.
+/// With `local_nodes` in 0L.toml, the first of them which is healthy, else `default_node`.
 pub fn default_local_client(config: &AppCfg, waypoint: Waypoint) -> Result<DiemClient, Error> {
-    let local_url = config
-        .profile
-        .default_node
-        .clone()
-        .expect("could not get url from configs");
+    let candidates = &config.profile.local_nodes;
+    if candidates.is_empty() {
+        let local_url = config
+            .profile
+            .default_node
+            .clone()
+            .ok_or_else(|| Error::msg("could not get default_node url from configs"))?;
 
-    make_client(Some(local_url.clone()), waypoint)
+        return make_client(Some(local_url.clone()), waypoint, expected_chain_id(config));
+    }
+
+    let max_lag = Duration::from_secs(config.profile.max_upstream_lag_secs);
+    let chain_id = expected_chain_id(config);
+    first_healthy_local(candidates, |local_url| {
+        println!("trying local node url: {}", local_url);
+        let client = match make_client(Some(local_url.clone()), waypoint, chain_id) {
+            Ok(c) => c,
+            Err(e) => {
+                println!("could not make a client {:?}", e);
+                return None;
+            }
+        };
+        match probe_node_health(&client) {
+            Ok(h) if h.is_acceptable(max_lag) => Some(client),
+            Ok(h) => {
+                println!(
+                    "local node is unhealthy, version: {}, lag: {}s",
+                    h.version,
+                    h.lag_estimate.as_secs()
+                );
+                None
+            }
+            Err(e) => {
+                println!("can make client but could not get metadata {:?}", e);
+                None
+            }
+        }
+    })
+    .ok_or_else(|| Error::msg("none of the local_nodes in 0L.toml is healthy"))
+}
+
+/// the first of the local `candidates`, in order, which `probe` accepts
+fn first_healthy_local<T>(candidates: &[Url], probe: impl FnMut(&Url) -> Option<T>) -> Option<T> {
+    candidates.iter().find_map(probe)
 }
 
 /// connect a swarm client
 pub fn swarm_test_client(config: &mut AppCfg, swarm_path: PathBuf) -> Result<DiemClient, Error> {
-    let (url, waypoint) = ol_types::config::get_swarm_rpc_url(swarm_path.clone());
+    let (url, waypoint) = ol_types::config::try_get_swarm_rpc_url(swarm_path.clone())
+        .map_err(|e| {
+            Error::msg(format!("swarm config not found at {:?}: {}", &swarm_path, e))
+        })?;
+    if url.port_or_known_default().unwrap_or(0) == 0 {
+        return Err(Error::msg(format!(
+            "swarm config at {:?} has no json-rpc port: {}",
+            &swarm_path, url
+        )));
+    }
     config.profile.default_node = Some(url.clone());
     config.profile.upstream_nodes = Some(vec![url.clone()]);
 
-    make_client(Some(url.clone()), waypoint)
+    make_client(Some(url.clone()), waypoint, None)
 }
 
-/// picks what URL to connect to based on sync state. Or returns the client for swarm.
+/// picks what URL to connect to based on sync state. Or returns the client for swarm, or for
+/// the node passed with `--url`, which skips the decision.
+/// The local/remote decision is made up to `pick_client_attempts` times, as set in 0L.toml.
 pub fn pick_client(swarm_path: Option<PathBuf>, config: &mut AppCfg) -> Result<DiemClient, Error> {
     let is_swarm = *&swarm_path.is_some();
     if let Some(path) = swarm_path {
         return swarm_test_client(config, path);
     };
-    let waypoint = config.get_waypoint(swarm_path)?;
+    let entry_args = entrypoint::get_args();
+    let explicit_waypoint = entry_args.waypoint;
+    let config = &*config;
+    explicit_or_picked(
+        entry_args.url,
+        |url| {
+            let waypoint = match explicit_waypoint {
+                Some(w) => w,
+                None => waypoint_or_bootstrap(config, None)?,
+            };
+            explicit_client(config, url, waypoint)
+        },
+        || {
+            let waypoint = waypoint_or_bootstrap(config, swarm_path)?;
+
+            let _timer = CLIENT_SELECTION_SECONDS
+                .with_label_values(&["pick"])
+                .start_timer();
+            let delay = Duration::from_millis(config.profile.pick_client_retry_delay_ms);
+            with_retries(config.profile.pick_client_attempts, delay, || {
+                pick_client_once(config, waypoint, is_swarm)
+            })
+        },
+    )
+}
+
+/// connects to `explicit`, the node passed with `--url`, if there is one. Only otherwise is
+/// `pick` called, so the configured local and upstream nodes are not consulted at all
+fn explicit_or_picked<T>(
+    explicit: Option<Url>,
+    connect: impl FnOnce(Url) -> Result<T, Error>,
+    pick: impl FnOnce() -> Result<T, Error>,
+) -> Result<T, Error> {
+    match explicit {
+        Some(url) => connect(url),
+        None => pick(),
+    }
+}
+
+/// client for a node the user named explicitly, used as is: no upstream is probed and no
+/// sync check is made. The node must still answer, and serve the chain in 0L.toml.
+pub fn explicit_client(config: &AppCfg, url: Url, waypoint: Waypoint) -> Result<DiemClient, Error> {
+    let client = make_client(Some(url.clone()), waypoint, expected_chain_id(config))?;
+    explicit_node_available(&url, probe_node_health(&client))?;
+    Ok(client)
+}
+
+/// names the node passed with `--url` in the error, if `probed` says it is unavailable
+fn explicit_node_available<T>(url: &Url, probed: Result<T, Error>) -> Result<T, Error> {
+    probed.map_err(|e| {
+        Error::msg(format!("the node passed with --url, {}, is unavailable: {}", url, e))
+    })
+}
+
+/// one pass of the local/remote decision, probing upstreams from scratch
+fn pick_client_once(
+    config: &AppCfg,
+    waypoint: Waypoint,
+    is_swarm: bool,
+) -> Result<DiemClient, Error> {
+    match config.profile.client_preference {
+        ClientPreference::LocalOnly => {
+            let local_client = default_local_client(config, waypoint)?;
+            return match probe_node_health(&local_client) {
+                Ok(h) => check_min_diem_version(&h, config.profile.min_diem_version)
+                    .map(|_| local_client),
+                Err(e) => Err(Error::msg(format!(
+                    "client_preference is local-only, but the local node is unavailable: {}",
+                    e
+                ))),
+            };
+        }
+        ClientPreference::RemoteOnly => {
+            return find_a_remote_jsonrpc(config, waypoint).map_err(|e| {
+                Error::msg(format!(
+                    "client_preference is remote-only, but no upstream is available: {}",
+                    e
+                ))
+            });
+        }
+        ClientPreference::Auto => {}
+    }
 
     // check if is in sync
     let local_client = default_local_client(config, waypoint.clone())?;
 
     let remote_client = find_a_remote_jsonrpc(config, waypoint.clone())?;
+    // the sync check reuses this upstream and its metadata instead of picking another
+    let ttl = Duration::from_millis(config.profile.metadata_cache_ttl_ms);
+    let remote_client = CachingClient::new(remote_client, ttl);
     // compares to an upstream random remote client. If it is synced, use the local client as the default
     let mut node = Node::new(local_client, config, is_swarm);
-    match node.check_sync()?
-    .is_synced {
-      true => Ok(node.client),
-      false => Ok(remote_client),
+    let synced = node.check_sync_against(&remote_client).map(|s| s.is_synced);
+    // a synced local node which is too old for the operation is not used either
+    let synced = match (synced, config.profile.min_diem_version) {
+        (Ok(true), Some(_)) => probe_node_health(&node.client)
+            .and_then(|h| check_min_diem_version(&h, config.profile.min_diem_version))
+            .map(|_| true),
+        (synced, _) => synced,
+    };
+    Ok(local_if_synced(node.client, remote_client.into_inner(), synced))
+}
+
+/// the local client if the sync check says it is synced, otherwise the remote one.
+/// A sync check which failed counts as not synced, since the remote is usable either way
+fn local_if_synced<T>(local: T, remote: T, synced: Result<bool, Error>) -> T {
+    match synced {
+        Ok(true) => local,
+        Ok(false) => remote,
+        Err(e) => {
+            println!("could not check if the local node is synced, using the upstream: {}", e);
+            remote
+        }
+    }
+}
 
+/// call `f` up to `attempts` times (at least once), sleeping `delay` between failures,
+/// and return the first success or the last error
+fn with_retries<T>(
+    attempts: u32,
+    delay: Duration,
+    mut f: impl FnMut() -> Result<T, Error>,
+) -> Result<T, Error> {
+    let attempts = attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Ok(t) => return Ok(t),
+            Err(e) if attempt >= attempts => return Err(e),
+            Err(e) => {
+                println!("attempt {} of {} failed: {}, retrying", attempt, attempts, e);
+                std::thread::sleep(delay);
+                attempt += 1;
+            }
+        }
     }
 }
+
+#[test]
+fn test_gas_ceiling_rejects_above_cap() {
+    let mut config = AppCfg::default();
+    assert!(check_gas_ceiling(&config, u64::MAX).is_ok());
+
+    config.tx_configs.max_gas_ceiling = Some(1_000);
+    assert!(check_gas_ceiling(&config, 1_000).is_ok());
+    let err = check_gas_ceiling(&config, 1_001).unwrap_err();
+    assert!(err.to_string().contains("1000"));
+}
+
+#[test]
+fn test_swarm_client_missing_config() {
+    let dir = std::env::temp_dir().join("ol_swarm_client_missing_config");
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut config = AppCfg::default();
+    let err = swarm_test_client(&mut config, dir.clone()).err().unwrap();
+    assert!(err.to_string().contains("swarm config not found at"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_select_upstream_is_reproducible_with_seed() {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    let list: Vec<Url> = (0..5)
+        .map(|i| Url::parse(&format!("http://10.0.0.{}:8080", i)).unwrap())
+        .collect();
+    let healthy = list[3].clone();
+
+    let probe_order = |seed: u64| {
+        let mut tried = vec![];
+        let never = AtomicBool::new(false);
+        let chosen = select_upstream(&list, &[], &mut StdRng::seed_from_u64(seed), &never, |url| {
+            tried.push(url.clone());
+            if *url == healthy { Some(url.clone()) } else { None }
+        });
+        (chosen, tried)
+    };
+
+    let (chosen, tried) = probe_order(7);
+    assert_eq!(chosen, Some(healthy.clone()));
+    assert_eq!(tried.last(), Some(&healthy));
+    assert_eq!(probe_order(7), (chosen, tried));
+}
+
+#[test]
+fn test_expected_chain_id_from_config() {
+    let mut config = AppCfg::default();
+    config.chain_info.chain_id = "1".to_string();
+    assert_eq!(expected_chain_id(&config), Some(ChainId::new(1)));
+    config.chain_info.chain_id = "not-a-chain".to_string();
+    assert_eq!(expected_chain_id(&config), None);
+}
+
+#[test]
+fn test_poll_backoff_is_capped() {
+    let mut backoff = POLL_INITIAL_BACKOFF;
+    for _ in 0..10 {
+        backoff = next_backoff(backoff);
+    }
+    assert_eq!(backoff, POLL_MAX_BACKOFF);
+    assert_eq!(next_backoff(POLL_INITIAL_BACKOFF), POLL_INITIAL_BACKOFF * 2);
+}
+
+#[test]
+fn test_select_upstream_stops_when_cancelled() {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    let list: Vec<Url> = (0..5)
+        .map(|i| Url::parse(&format!("http://10.0.0.{}:8080", i)).unwrap())
+        .collect();
+    let cancel = AtomicBool::new(false);
+    let mut probes = 0;
+    // every upstream fails, and the token is set during the second probe
+    let chosen: Option<Url> = select_upstream(&list, &[], &mut StdRng::seed_from_u64(1), &cancel, |_| {
+        probes += 1;
+        if probes == 2 {
+            cancel.store(true, Ordering::SeqCst);
+        }
+        None
+    });
+    assert_eq!(chosen, None);
+    assert_eq!(probes, 2);
+}
+
+#[test]
+fn test_with_retries_stops_at_first_success() {
+    let mut calls = 0;
+    let res = with_retries(3, Duration::from_millis(0), || {
+        calls += 1;
+        if calls < 2 { Err(Error::msg("unreachable")) } else { Ok(calls) }
+    });
+    assert_eq!(res.unwrap(), 2);
+
+    let mut calls = 0;
+    let res: Result<(), Error> = with_retries(3, Duration::from_millis(0), || {
+        calls += 1;
+        Err(Error::msg(format!("failure {}", calls)))
+    });
+    assert_eq!(res.unwrap_err().to_string(), "failure 3");
+
+    // zero attempts still makes the decision once
+    let mut calls = 0;
+    let _ = with_retries(0, Duration::from_millis(0), || -> Result<(), Error> {
+        calls += 1;
+        Err(Error::msg("down"))
+    });
+    assert_eq!(calls, 1);
+}
+
+#[test]
+fn test_preferred_upstreams_are_probed_first() {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    let url = |i: u8| Url::parse(&format!("http://10.0.0.{}:8080", i)).unwrap();
+    let upstreams: Vec<Url> = (0..5).map(url).collect();
+    // one preferred upstream is not in upstream_nodes, and is added
+    let preferred = vec![url(9), url(3)];
+    let list = with_preferred_upstreams(&upstreams, &preferred);
+    assert_eq!(list.len(), 6);
+
+    let never = AtomicBool::new(false);
+    for seed in 0..5 {
+        let mut tried = vec![];
+        let chosen: Option<Url> =
+            select_upstream(&list, &preferred, &mut StdRng::seed_from_u64(seed), &never, |u| {
+                tried.push(u.clone());
+                None
+            });
+        assert_eq!(chosen, None);
+        assert_eq!(tried.len(), 6);
+        assert_eq!(&tried[..2], &preferred[..]);
+    }
+}
+
+#[test]
+fn test_missing_empty_and_unreachable_upstreams_are_distinguished() {
+    let reason = |config: &AppCfg| {
+        let err = find_a_remote_jsonrpc(config, Waypoint::default()).err().unwrap();
+        *err.downcast_ref::<UpstreamError>().unwrap()
+    };
+    let mut config = AppCfg::default();
+
+    config.profile.upstream_nodes = None;
+    assert_eq!(reason(&config), UpstreamError::UpstreamsNotConfigured);
+
+    config.profile.upstream_nodes = Some(vec![]);
+    assert_eq!(reason(&config), UpstreamError::UpstreamsEmpty);
+
+    // every upstream is down
+    use rand::{rngs::StdRng, SeedableRng};
+    let list = vec![Url::parse("http://127.0.0.1:1").unwrap()];
+    let never = AtomicBool::new(false);
+    let picked = select_upstream(&list, &[], &mut StdRng::seed_from_u64(1), &never, |_| {
+        None::<Url>
+    });
+    assert_eq!(
+        picked_or_unreachable(picked, &never),
+        Err(UpstreamError::AllUpstreamsUnreachable)
+    );
+}
+
+#[test]
+fn test_failed_sync_check_falls_back_to_remote() {
+    assert_eq!(local_if_synced("local", "remote", Ok(true)), "local");
+    assert_eq!(local_if_synced("local", "remote", Ok(false)), "remote");
+    let unavailable = Err(Error::msg("sync status unavailable"));
+    assert_eq!(local_if_synced("local", "remote", unavailable), "remote");
+}
+
+#[test]
+fn test_node_which_degraded_after_selection_is_not_ready_for_write() {
+    let mut config = AppCfg::default();
+    config.chain_info.chain_id = "1".to_string();
+    config.profile.max_upstream_lag_secs = 60;
+    let selected = NodeHealth {
+        version: 42,
+        timestamp: 0,
+        lag_estimate: Duration::from_secs(10),
+        chain_id: 1,
+        diem_version: Some(3),
+    };
+    assert_eq!(check_ready_for_write(&selected, &config), Ok(()));
+
+    // by the time of the write it fell behind
+    let stale = NodeHealth {
+        lag_estimate: Duration::from_secs(61),
+        ..selected.clone()
+    };
+    assert_eq!(
+        check_ready_for_write(&stale, &config),
+        Err(NotReadyForWrite::Behind {
+            version: 42,
+            lag_secs: 61
+        })
+    );
+
+    let other_chain = NodeHealth {
+        chain_id: 2,
+        ..selected
+    };
+    assert_eq!(
+        check_ready_for_write(&other_chain, &config),
+        Err(NotReadyForWrite::WrongChain {
+            expected: 1,
+            actual: 2
+        })
+    );
+}
+
+#[test]
+fn test_largest_agreeing_group() {
+    assert!(largest_agreeing_group(&[], 10).is_empty());
+    // one upstream far behind, the rest close together
+    let versions = [1_000, 5, 1_004, 1_010, 2_000];
+    assert_eq!(largest_agreeing_group(&versions, 10), vec![0, 2, 3]);
+    assert_eq!(largest_agreeing_group(&versions, 0).len(), 1);
+    assert_eq!(largest_agreeing_group(&versions, u64::MAX).len(), 5);
+}
+
+#[test]
+fn test_classify_submission_error() {
+    let retriable = [
+        SubmissionStatus::Http(429),
+        SubmissionStatus::JsonRpc(ServerCode::MempoolIsFull as i16),
+        SubmissionStatus::JsonRpc(ServerCode::MempoolTooManyTransactions as i16),
+    ];
+    for status in retriable.iter() {
+        assert_eq!(classify_submission_error(*status), Retriability::Retriable);
+    }
+
+    let not_retriable = [
+        SubmissionStatus::Http(400),
+        SubmissionStatus::JsonRpc(ServerCode::MempoolInvalidSeqNumber as i16),
+        SubmissionStatus::JsonRpc(ServerCode::VmValidationError as i16),
+        SubmissionStatus::JsonRpc(ServerCode::MempoolVmError as i16),
+    ];
+    for status in not_retriable.iter() {
+        assert_eq!(classify_submission_error(*status), Retriability::NotRetriable);
+    }
+
+    // an error which did not come from the node has no status to go by
+    assert_eq!(SubmissionStatus::of(&Error::msg("not sent")), None);
+}
+
+#[test]
+fn test_check_currency_accepted() {
+    let accepted = vec!["GAS".to_owned(), "XUS".to_owned()];
+    assert!(check_currency_accepted("GAS", &accepted).is_ok());
+    assert!(check_currency_accepted("XUS", &accepted).is_ok());
+    let err = check_currency_accepted("XDX", &accepted).unwrap_err();
+    assert!(err.to_string().contains("gas currency XDX is not accepted"));
+    assert!(err.to_string().contains("GAS, XUS"));
+}
+
+#[test]
+fn test_first_healthy_local_skips_a_node_which_is_down() {
+    let full_node = Url::parse("http://127.0.0.1:8080").unwrap();
+    let validator = Url::parse("http://127.0.0.1:8081").unwrap();
+    let candidates = vec![full_node.clone(), validator.clone()];
+
+    let mut tried = vec![];
+    let chosen = first_healthy_local(&candidates, |url| {
+        tried.push(url.clone());
+        if *url == full_node { None } else { Some(url.clone()) }
+    });
+    assert_eq!(chosen, Some(validator.clone()));
+    assert_eq!(tried, candidates);
+
+    assert_eq!(first_healthy_local(&candidates, |_| None::<Url>), None);
+}
+
+#[test]
+fn test_explicit_url_overrides_upstreams() {
+    let explicit = Url::parse("http://127.0.0.1:3").unwrap();
+    let connected = explicit_or_picked(Some(explicit.clone()), Ok, || {
+        panic!("the configured nodes were consulted despite --url")
+    });
+    assert_eq!(connected.unwrap(), explicit);
+
+    // only the explicit node is tried, and its failure is not papered over by another node
+    let failed: Result<Url, Error> = explicit_or_picked(
+        Some(explicit),
+        |url| Err(Error::msg(format!("{} is down", url))),
+        || panic!("the configured nodes were consulted despite --url"),
+    );
+    assert!(failed.is_err());
+
+    // without --url the configured nodes are picked from
+    let upstream = Url::parse("http://127.0.0.1:1").unwrap();
+    let picked = explicit_or_picked(None, Ok, || Ok(upstream.clone()));
+    assert_eq!(picked.unwrap(), upstream);
+
+    // the explicit client names the node when it is down
+    let down = Url::parse("http://127.0.0.1:3").unwrap();
+    let probed: Result<(), Error> = Err(Error::msg("connection refused"));
+    let err = explicit_node_available(&down, probed)
+        .err()
+        .unwrap()
+        .to_string();
+    assert!(err.contains("--url"), "{}", err);
+    assert!(err.contains(down.as_str()), "{}", err);
+}
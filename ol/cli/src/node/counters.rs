@@ -0,0 +1,85 @@
+//! `counters` for the CLI's connections to upstream nodes, in the process-wide metrics registry
+
+use diem_metrics::{register_histogram_vec, register_int_counter_vec, HistogramVec, IntCounterVec};
+use once_cell::sync::Lazy;
+use reqwest::Url;
+
+/// clients made with `make_client`, by url
+pub static CLIENTS_MADE: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "ol_cli_clients_made",
+        "Number of clients made, by url",
+        &["url"]
+    )
+    .unwrap()
+});
+
+/// upstream probes started while selecting a remote, by url
+pub static UPSTREAM_PROBE_ATTEMPTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "ol_cli_upstream_probe_attempts",
+        "Number of upstream probes started, by url",
+        &["url"]
+    )
+    .unwrap()
+});
+
+/// upstream probes which did not give a usable client, by url
+pub static UPSTREAM_PROBE_FAILURES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "ol_cli_upstream_probe_failures",
+        "Number of upstream probes which failed, by url",
+        &["url"]
+    )
+    .unwrap()
+});
+
+/// upstreams selected, by url
+pub static UPSTREAM_SELECTIONS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "ol_cli_upstream_selections",
+        "Number of times an upstream was selected, by url",
+        &["url"]
+    )
+    .unwrap()
+});
+
+/// time to select a client, by how it was selected: `remote` for `find_a_remote_jsonrpc`,
+/// `pick` for the whole of `pick_client`
+pub static CLIENT_SELECTION_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "ol_cli_client_selection_seconds",
+        "Time to select a client, in seconds",
+        &["selection"]
+    )
+    .unwrap()
+});
+
+/// count a finished probe of `url`, and whether it gave a usable client
+pub fn record_probe(url: &Url, usable: bool) {
+    UPSTREAM_PROBE_ATTEMPTS.with_label_values(&[url.as_str()]).inc();
+    if !usable {
+        UPSTREAM_PROBE_FAILURES.with_label_values(&[url.as_str()]).inc();
+    }
+}
+
+/// `(attempts, failures)` of the probes of `url` in this process
+pub fn probe_counts(url: &Url) -> (u64, u64) {
+    (
+        UPSTREAM_PROBE_ATTEMPTS.with_label_values(&[url.as_str()]).get(),
+        UPSTREAM_PROBE_FAILURES.with_label_values(&[url.as_str()]).get(),
+    )
+}
+
+#[test]
+fn test_record_probe_counts_failures_per_url() {
+    let flaky = Url::parse("http://10.9.9.1:8080").unwrap();
+    let healthy = Url::parse("http://10.9.9.2:8080").unwrap();
+    for usable in [false, false, false, true].iter() {
+        record_probe(&flaky, *usable);
+    }
+    record_probe(&healthy, true);
+
+    assert_eq!(probe_counts(&flaky), (4, 3));
+    assert_eq!(probe_counts(&healthy), (1, 0));
+}
@@ -0,0 +1,126 @@
+//! `diagnostics` of every node the client could connect to, without selecting one
+
+use crate::{
+    config::AppCfg,
+    node::{
+        client::make_client,
+        health::{probe_node_health, NodeHealth},
+    },
+};
+use diem_types::waypoint::Waypoint;
+use reqwest::Url;
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// What probing one node found.
+#[derive(Clone, Debug)]
+pub struct NodeDiagnostic {
+    /// node url
+    pub url: Url,
+    /// whether this is the `default_node` rather than one of the `upstream_nodes`
+    pub is_local: bool,
+    /// the node's health, or why it could not be probed
+    pub health: Result<NodeHealth, String>,
+    /// time taken to connect and probe, whether or not it succeeded
+    pub latency: Duration,
+}
+
+/// Probe the local node and every upstream in 0L.toml in parallel, in config order.
+/// Nodes are not verified against the waypoint or the configured chain id, so the report
+/// shows what each node actually serves.
+pub fn diagnose_connectivity(config: &AppCfg, waypoint: Waypoint) -> Vec<NodeDiagnostic> {
+    let local = config.profile.default_node.iter().map(|url| (url.clone(), true));
+    let upstreams = config
+        .profile
+        .upstream_nodes
+        .iter()
+        .flatten()
+        .map(|url| (url.clone(), false));
+
+    let handles: Vec<_> = local
+        .chain(upstreams)
+        .map(|(url, is_local)| {
+            thread::spawn(move || {
+                let start = Instant::now();
+                let health = make_client(Some(url.clone()), waypoint, None)
+                    .and_then(|client| probe_node_health(&client))
+                    .map_err(|e| e.to_string());
+                NodeDiagnostic {
+                    url,
+                    is_local,
+                    health,
+                    latency: start.elapsed(),
+                }
+            })
+        })
+        .collect();
+
+    handles
+        .into_iter()
+        .filter_map(|handle| handle.join().ok())
+        .collect()
+}
+
+/// One row per node: url, reachability, version, chain id, lag and latency.
+pub fn format_diagnostics(report: &[NodeDiagnostic]) -> String {
+    let mut out = format!(
+        "{:<40} {:<6} {:<12} {:>12} {:>6} {:>8} {:>10}\n",
+        "URL", "LOCAL", "REACHABLE", "VERSION", "CHAIN", "LAG(s)", "LATENCY(ms)"
+    );
+    for d in report {
+        let row = match &d.health {
+            Ok(h) => format!(
+                "{:<40} {:<6} {:<12} {:>12} {:>6} {:>8} {:>10}\n",
+                d.url.as_str(),
+                d.is_local,
+                "yes",
+                h.version,
+                h.chain_id,
+                h.lag_estimate.as_secs(),
+                d.latency.as_millis()
+            ),
+            Err(e) => format!(
+                "{:<40} {:<6} {:<12} {:>12} {:>6} {:>8} {:>10}  {}\n",
+                d.url.as_str(),
+                d.is_local,
+                "no",
+                "-",
+                "-",
+                "-",
+                d.latency.as_millis(),
+                e
+            ),
+        };
+        out.push_str(&row);
+    }
+    out
+}
+
+#[test]
+fn test_format_diagnostics_marks_unreachable_nodes() {
+    let up = NodeDiagnostic {
+        url: Url::parse("http://1.2.3.4:8080").unwrap(),
+        is_local: false,
+        health: Ok(NodeHealth {
+            version: 42,
+            timestamp: 0,
+            lag_estimate: Duration::from_secs(3),
+            chain_id: 1,
+            diem_version: Some(3),
+        }),
+        latency: Duration::from_millis(120),
+    };
+    let down = NodeDiagnostic {
+        url: Url::parse("http://localhost:8080").unwrap(),
+        is_local: true,
+        health: Err("connection refused".to_string()),
+        latency: Duration::from_millis(5),
+    };
+    let table = format_diagnostics(&[down, up]);
+    let rows: Vec<&str> = table.lines().collect();
+    assert_eq!(rows.len(), 3);
+    assert!(rows[1].contains("no") && rows[1].ends_with("connection refused"));
+    assert!(rows[2].contains("yes") && rows[2].contains("42"));
+}
@@ -0,0 +1,169 @@
+//! `health` of an upstream node, beyond whether it answers at all
+
+use anyhow::{bail, Error, Result};
+use cli::diem_client::DiemClient;
+use once_cell::sync::Lazy;
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// What a node reports about its ledger, and how far behind wall-clock it appears to be.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeHealth {
+    /// latest ledger version
+    pub version: u64,
+    /// latest ledger timestamp, in microseconds
+    pub timestamp: u64,
+    /// approximate distance between the ledger timestamp and wall-clock
+    pub lag_estimate: Duration,
+    /// chain id the node serves
+    pub chain_id: u8,
+    /// `DiemVersion` the node reports, if any
+    pub diem_version: Option<u64>,
+}
+
+impl NodeHealth {
+    /// A node is usable if it has a ledger and is not further behind than `max_lag`.
+    pub fn is_acceptable(&self, max_lag: Duration) -> bool {
+        self.version > 0 && self.lag_estimate <= max_lag
+    }
+}
+
+/// The newest ledger any upstream has reported, so the view of state never moves backwards.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LedgerHighWater {
+    version: u64,
+    timestamp: u64,
+}
+
+impl LedgerHighWater {
+    /// Accept `health` unless its ledger is older than one already seen, then raise the mark.
+    pub fn observe(&mut self, health: &NodeHealth) -> Result<(), Error> {
+        if health.version < self.version || health.timestamp < self.timestamp {
+            bail!(
+                "ledger regressed: node reports version {} (timestamp {}), already saw version {} (timestamp {})",
+                health.version,
+                health.timestamp,
+                self.version,
+                self.timestamp
+            );
+        }
+        self.version = health.version;
+        self.timestamp = health.timestamp;
+        Ok(())
+    }
+}
+
+static SESSION_HIGH_WATER: Lazy<Mutex<LedgerHighWater>> =
+    Lazy::new(|| Mutex::new(LedgerHighWater::default()));
+
+/// Check `health` against the newest ledger seen from any upstream in this process.
+pub fn check_not_regressed(health: &NodeHealth) -> Result<(), Error> {
+    SESSION_HIGH_WATER
+        .lock()
+        .map_err(|_| Error::msg("ledger high-water mark lock poisoned"))?
+        .observe(health)
+}
+
+/// Fetch metadata from the node and estimate its lag against the local clock.
+pub fn probe_node_health(client: &DiemClient) -> Result<NodeHealth, Error> {
+    let metadata = client.get_metadata()?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    Ok(NodeHealth {
+        version: metadata.version,
+        timestamp: metadata.timestamp,
+        lag_estimate: lag_since(metadata.timestamp, now),
+        chain_id: metadata.chain_id,
+        diem_version: metadata.diem_version,
+    })
+}
+
+/// Check the node serves at least protocol version `min`, if there is a minimum.
+/// A node which does not report its version is rejected when there is one.
+pub fn check_min_diem_version(health: &NodeHealth, min: Option<u64>) -> Result<(), Error> {
+    match (min, health.diem_version) {
+        (None, _) => Ok(()),
+        (Some(min), Some(version)) if version >= min => Ok(()),
+        (Some(min), Some(version)) => {
+            bail!("node serves protocol version {}, requires protocol version >= {}", version, min)
+        }
+        (Some(min), None) => bail!(
+            "node does not report its protocol version, requires protocol version >= {}",
+            min
+        ),
+    }
+}
+
+/// Lag between a ledger timestamp (microseconds) and `now`. A ledger ahead of the local
+/// clock counts as no lag.
+pub fn lag_since(ledger_timestamp_usecs: u64, now: Duration) -> Duration {
+    now.checked_sub(Duration::from_micros(ledger_timestamp_usecs))
+        .unwrap_or_default()
+}
+
+#[test]
+fn test_lag_since_and_threshold() {
+    let now = Duration::from_secs(1_000);
+    let ten_secs_behind = 990 * 1_000_000;
+    assert_eq!(lag_since(ten_secs_behind, now), Duration::from_secs(10));
+    assert_eq!(lag_since(2_000 * 1_000_000, now), Duration::from_secs(0));
+
+    let health = NodeHealth {
+        version: 42,
+        timestamp: ten_secs_behind,
+        lag_estimate: lag_since(ten_secs_behind, now),
+        chain_id: 1,
+        diem_version: Some(3),
+    };
+    assert!(health.is_acceptable(Duration::from_secs(10)));
+    assert!(!health.is_acceptable(Duration::from_secs(9)));
+
+    let empty = NodeHealth { version: 0, ..health };
+    assert!(!empty.is_acceptable(Duration::from_secs(10)));
+}
+
+#[test]
+fn test_high_water_rejects_older_ledger() {
+    let newer = NodeHealth {
+        version: 100,
+        timestamp: 2_000,
+        lag_estimate: Duration::from_secs(0),
+        chain_id: 1,
+        diem_version: Some(3),
+    };
+    let older = NodeHealth {
+        version: 90,
+        timestamp: 1_000,
+        ..newer.clone()
+    };
+    let mut mark = LedgerHighWater::default();
+    mark.observe(&newer).unwrap();
+    assert!(mark.observe(&older).is_err());
+    // the same ledger again is not a regression
+    mark.observe(&newer).unwrap();
+}
+
+#[test]
+fn test_min_diem_version_skips_older_nodes() {
+    let node = |diem_version: Option<u64>| NodeHealth {
+        version: 100,
+        timestamp: 0,
+        lag_estimate: Duration::from_secs(0),
+        chain_id: 1,
+        diem_version,
+    };
+    let nodes = [node(Some(2)), node(Some(4)), node(None), node(Some(3))];
+
+    // no minimum accepts every node, as before
+    assert!(nodes.iter().all(|n| check_min_diem_version(n, None).is_ok()));
+
+    let usable: Vec<Option<u64>> = nodes
+        .iter()
+        .filter(|n| check_min_diem_version(n, Some(3)).is_ok())
+        .map(|n| n.diem_version)
+        .collect();
+    assert_eq!(usable, vec![Some(4), Some(3)]);
+    let err = check_min_diem_version(&nodes[0], Some(3)).unwrap_err();
+    assert!(err.to_string().contains("requires protocol version >= 3"));
+}
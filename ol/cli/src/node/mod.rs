@@ -1,11 +1,17 @@
 //! `node` state of the host, account, node, and miner
 pub mod node;
 pub mod account;
+pub mod caching_client;
+pub mod circuit_breaker;
 pub mod chain_view;
 pub mod query;
 pub mod sync;
 pub mod client;
+pub mod counters;
+pub mod diagnostics;
+pub mod health;
 pub mod states;
 pub mod dictionary;
 pub mod refresh_peers;
+pub mod waypoints;
 // mod transitions;
\ No newline at end of file
@@ -3,6 +3,7 @@ use std::collections::BTreeMap;
 
 use super::node::Node;
 use anyhow::Error;
+use diem_types::on_chain_config::{config_address, TransactionLimits, VMConfig};
 use diem_json_rpc_client::{
     views::{BytesView, EventView, TransactionView},
     AccountAddress,
@@ -72,7 +73,9 @@ pub enum QueryType {
     ValConfig { 
       /// the account of the validator
       account: AccountAddress 
-    }
+    },
+    /// Transaction size and gas limits enforced by the VM
+    Limits,
 }
 
 /// Get data from a client, with a query type. Will connect to local only if in sync.
@@ -240,6 +243,25 @@ impl Node {
                     Err(_) => format!("No validator configs cound at: {}", account),
                 }
             },
+            Limits => {
+                match self.get_account_state(config_address())?.get_config::<VMConfig>()? {
+                    Some(vm_config) => {
+                        let limits = TransactionLimits::from(&vm_config.gas_schedule.gas_constants);
+                        format!("\n
+                            max transaction size (bytes): {}\n
+                            max gas units: {}\n
+                            min gas unit price: {}\n
+                            max gas unit price: {}\n
+                            ",
+                          limits.max_transaction_size_in_bytes,
+                          limits.maximum_number_of_gas_units,
+                          limits.min_price_per_gas_unit,
+                          limits.max_price_per_gas_unit,
+                        )
+                    },
+                    None => "No VM config found on chain".to_owned(),
+                }
+            },
         };
         Ok(print)
     }
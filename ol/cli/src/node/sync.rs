@@ -1,10 +1,11 @@
 //! `sync` subcommand
 
 use super::node::Node;
-use crate::node::client::*;
+use crate::node::{caching_client::CachingClient, client::*};
 use anyhow::{bail, Error};
 use backup_cli::utils::backup_service_client::{BackupServiceClient, BackupServiceClientOpt};
 use diemdb::backup::backup_handler::DbState;
+use std::time::Duration;
 use tokio::runtime::Runtime;
 
 /// State of the node's sync
@@ -33,8 +34,6 @@ impl Default for SyncState {
 impl Node {
     /// check if node is synced
     pub fn check_sync(&mut self) -> Result<SyncState, Error> {
-        let mut s = SyncState::default();
-
         if !Node::node_running() { // this should not fail for `ol start` command. The node is usually off when the command and sync is checked.
             return Ok(SyncState {
                 is_synced: false,
@@ -50,6 +49,22 @@ impl Node {
             println!("cannot connect to upstream node");
             e
         })?;
+        let ttl = Duration::from_millis(self.app_conf.profile.metadata_cache_ttl_ms);
+        self.check_sync_against(&CachingClient::new(remote_client, ttl))
+    }
+
+    /// check if node is synced, comparing with an upstream that was already picked
+    pub fn check_sync_against(&mut self, remote_client: &CachingClient) -> Result<SyncState, Error> {
+        let mut s = SyncState::default();
+
+        if !Node::node_running() {
+            return Ok(SyncState {
+                is_synced: false,
+                sync_height: 0,
+                remote_height: 0,
+                sync_delay: 404,
+            });
+        }
 
         let local_db = self.get_db_state()?;
         s.remote_height = match remote_client.get_metadata() {
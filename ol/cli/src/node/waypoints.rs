@@ -0,0 +1,284 @@
+//! `waypoints` for verifying upstreams which may be on different epochs
+//!
+//! Security model: every waypoint in the trusted list is treated as a root of trust.
+//! An upstream is accepted only if it serves a state proof, i.e. a chain of epoch change
+//! ledger infos signed by each epoch's validator set, that verifies from one of those
+//! waypoints up to its latest ledger info. An upstream cannot forge history past a trusted
+//! waypoint without the signatures of that epoch's validators.
+//!
+//! Accepting older waypoints lets us use nodes which are behind the configured waypoint,
+//! but the guarantee is only as strong as the oldest waypoint in the list: if the validator
+//! keys of an old epoch were compromised, a node could present a fork starting there.
+//! Only add waypoints obtained from a source you trust, and prune old ones.
+//!
+//! With no waypoint at all, `bootstrap_waypoint_from_upstream` can take one from the
+//! upstreams. Nothing verifies it, so the upstreams themselves become the root of trust;
+//! that is only done if the user sets `allow_waypoint_bootstrap` in 0L.toml.
+
+use crate::config::AppCfg;
+use anyhow::{bail, Error, Result};
+use cli::diem_client::DiemClient;
+use diem_types::{
+    epoch_change::Verifier, epoch_state::EpochState, ledger_info::LedgerInfoWithSignatures,
+    waypoint::Waypoint,
+};
+use ol_types::config::bootstrap_waypoint_from_rpc;
+use reqwest::Url;
+
+/// The configured waypoint plus `trusted_waypoints` from 0L.toml, newest first,
+/// without duplicates.
+pub fn trusted_waypoints(config: &AppCfg, waypoint: Waypoint) -> Vec<Waypoint> {
+    let mut list = vec![waypoint];
+    list.extend(config.profile.trusted_waypoints.iter().cloned());
+    sort_newest_first(list)
+}
+
+fn sort_newest_first(mut list: Vec<Waypoint>) -> Vec<Waypoint> {
+    list.sort_by(|a, b| b.version().cmp(&a.version()));
+    list.dedup();
+    list
+}
+
+/// Try each trusted waypoint in order, returning the first client whose upstream
+/// state proof verifies from that waypoint.
+pub fn verify_with_any_waypoint(
+    waypoints: &[Waypoint],
+    mut connect: impl FnMut(Waypoint) -> Result<DiemClient, Error>,
+) -> Result<DiemClient, Error> {
+    let mut last_err = Error::msg("no trusted waypoints to verify the upstream against");
+    for waypoint in waypoints {
+        match connect(*waypoint).and_then(|mut client| {
+            client.update_and_verify_state_proof()?;
+            Ok(client)
+        }) {
+            Ok(client) => return Ok(client),
+            Err(e) => {
+                println!("upstream does not verify from waypoint {}: {:?}", waypoint, e);
+                last_err = e;
+            }
+        }
+    }
+    Err(last_err)
+}
+
+/// Take a waypoint from the `upstream_nodes` when none is configured locally.
+/// Requires `allow_waypoint_bootstrap`, and every upstream which answers must agree.
+pub fn bootstrap_waypoint_from_upstream(config: &AppCfg) -> Result<Waypoint, Error> {
+    if !config.profile.allow_waypoint_bootstrap {
+        bail!(
+            "no waypoint found in key_store.json or 0L.toml. \
+            To trust upstream_nodes for one, set allow_waypoint_bootstrap = true in 0L.toml"
+        );
+    }
+    let reported: Vec<(Url, Waypoint)> = config
+        .profile
+        .upstream_nodes
+        .iter()
+        .flatten()
+        .filter_map(|url| match bootstrap_waypoint_from_rpc(url.clone()) {
+            Ok(waypoint) => Some((url.clone(), waypoint)),
+            Err(e) => {
+                println!("could not get a waypoint from {}: {:?}", url, e);
+                None
+            }
+        })
+        .collect();
+    agree_on_waypoint(&reported)
+}
+
+fn agree_on_waypoint(reported: &[(Url, Waypoint)]) -> Result<Waypoint, Error> {
+    let (_, waypoint) = reported
+        .first()
+        .ok_or_else(|| Error::msg("no upstream reported a waypoint to bootstrap from"))?;
+    if let Some((url, other)) = reported.iter().find(|(_, w)| w != waypoint) {
+        bail!(
+            "upstreams disagree on the waypoint: {} reports {}, {} reports {}",
+            reported[0].0,
+            waypoint,
+            url,
+            other
+        );
+    }
+    println!(
+        "WARNING: trusting waypoint {} from {} upstream(s) without verification, \
+        because allow_waypoint_bootstrap is set",
+        waypoint,
+        reported.len()
+    );
+    Ok(*waypoint)
+}
+
+/// Why an epoch change chain failed to verify, at the first bad link.
+#[derive(Clone, Debug, Eq, PartialEq, thiserror::Error)]
+pub enum EpochChainError {
+    /// the node returned no epoch change ledger infos
+    #[error("node returned no epoch change ledger infos")]
+    Empty,
+    /// the first ledger info is not the one the genesis waypoint commits to
+    #[error("first ledger info does not match the genesis waypoint: {0}")]
+    NotGenesis(String),
+    /// the ledger info ending `epoch` is not signed by that epoch's validators
+    #[error("epoch {epoch} change is not signed by the epoch's validators: {reason}")]
+    BadTransition {
+        /// epoch the ledger info ends
+        epoch: u64,
+        /// the verifier error
+        reason: String,
+    },
+    /// the ledger info ending `epoch` does not name the next validator set
+    #[error("epoch {0} change ledger info does not carry the next validator set")]
+    NoNextValidatorSet(u64),
+}
+
+/// Fetch the epoch change ledger infos from genesis to the node's current epoch, and
+/// verify each one against the validator set the previous one handed over. The first
+/// must match `genesis_waypoint`. Returns the verified chain, oldest first.
+pub fn verify_epoch_chain(
+    client: &DiemClient,
+    genesis_waypoint: Waypoint,
+) -> Result<Vec<LedgerInfoWithSignatures>, Error> {
+    let mut chain: Vec<LedgerInfoWithSignatures> = vec![];
+    loop {
+        let (known_version, next_epoch) = match chain.last() {
+            Some(li) => (li.ledger_info().version(), li.ledger_info().epoch() + 1),
+            None => (genesis_waypoint.version(), 0),
+        };
+        let proof = client.get_epoch_change_proof(known_version)?;
+        let fetched = chain.len();
+        // a follow-up request repeats the epoch we asked from
+        chain.extend(
+            proof
+                .ledger_info_with_sigs
+                .into_iter()
+                .filter(|li| li.ledger_info().epoch() >= next_epoch),
+        );
+        if !proof.more || chain.len() == fetched {
+            break;
+        }
+    }
+    verify_epoch_transitions(&chain, genesis_waypoint)?;
+    Ok(chain)
+}
+
+fn verify_epoch_transitions(
+    chain: &[LedgerInfoWithSignatures],
+    genesis_waypoint: Waypoint,
+) -> Result<(), EpochChainError> {
+    let genesis = chain.first().ok_or(EpochChainError::Empty)?;
+    genesis_waypoint
+        .verify(genesis.ledger_info())
+        .map_err(|e| EpochChainError::NotGenesis(e.to_string()))?;
+    let mut validators = next_validators(genesis)?;
+    for li in &chain[1..] {
+        validators.verify(li).map_err(|e| EpochChainError::BadTransition {
+            epoch: li.ledger_info().epoch(),
+            reason: e.to_string(),
+        })?;
+        validators = next_validators(li)?;
+    }
+    Ok(())
+}
+
+fn next_validators(li: &LedgerInfoWithSignatures) -> Result<&EpochState, EpochChainError> {
+    li.ledger_info()
+        .next_epoch_state()
+        .ok_or_else(|| EpochChainError::NoNextValidatorSet(li.ledger_info().epoch()))
+}
+
+#[test]
+fn test_trusted_waypoints_newest_first() {
+    use diem_crypto::HashValue;
+    use std::str::FromStr;
+
+    let at = |version: u64| {
+        Waypoint::from_str(&format!("{}:{}", version, HashValue::zero().to_hex())).unwrap()
+    };
+    let sorted = sort_newest_first(vec![at(10), at(300), at(10), at(0)]);
+    assert_eq!(sorted, vec![at(300), at(10), at(0)]);
+}
+
+#[test]
+fn test_bootstrap_waypoint_needs_consent_and_agreement() {
+    use diem_crypto::HashValue;
+    use std::str::FromStr;
+
+    let mut config = AppCfg::default();
+    let err = bootstrap_waypoint_from_upstream(&config).unwrap_err();
+    assert!(err.to_string().contains("allow_waypoint_bootstrap"));
+
+    config.profile.allow_waypoint_bootstrap = true;
+    config.profile.upstream_nodes = None;
+    let err = bootstrap_waypoint_from_upstream(&config).unwrap_err();
+    assert!(err.to_string().contains("no upstream reported a waypoint"));
+
+    let at = |version: u64| {
+        Waypoint::from_str(&format!("{}:{}", version, HashValue::zero().to_hex())).unwrap()
+    };
+    let a = Url::parse("http://1.2.3.4:8080").unwrap();
+    let b = Url::parse("http://5.6.7.8:8080").unwrap();
+    assert_eq!(
+        agree_on_waypoint(&[(a.clone(), at(10)), (b.clone(), at(10))]).unwrap(),
+        at(10)
+    );
+    assert!(agree_on_waypoint(&[(a, at(10)), (b, at(20))]).is_err());
+}
+
+#[test]
+fn test_epoch_chain_rejects_tampered_transition() {
+    use diem_crypto::HashValue;
+    use diem_types::{
+        block_info::BlockInfo, ledger_info::LedgerInfo, validator_signer::ValidatorSigner,
+        validator_verifier::ValidatorVerifier,
+    };
+    use std::collections::BTreeMap;
+
+    let signers: Vec<ValidatorSigner> =
+        (1..=3u8).map(|i| ValidatorSigner::random([i; 32])).collect();
+    let validators = |epoch: u64| EpochState {
+        epoch,
+        verifier: ValidatorVerifier::new_single(
+            signers[epoch as usize - 1].author(),
+            signers[epoch as usize - 1].public_key(),
+        ),
+    };
+    // the ledger info ending `epoch`, signed by `signer`
+    let ending = |epoch: u64, signer: Option<&ValidatorSigner>| {
+        let ledger_info = LedgerInfo::new(
+            BlockInfo::new(
+                epoch,
+                0,
+                HashValue::zero(),
+                HashValue::zero(),
+                epoch * 10,
+                0,
+                Some(validators(epoch + 1)),
+            ),
+            HashValue::zero(),
+        );
+        let signatures: BTreeMap<_, _> = signer
+            .map(|s| (s.author(), s.sign(&ledger_info)))
+            .into_iter()
+            .collect();
+        LedgerInfoWithSignatures::new(ledger_info, signatures)
+    };
+
+    let genesis = ending(0, None);
+    let waypoint = Waypoint::new_epoch_boundary(genesis.ledger_info()).unwrap();
+    let chain = vec![genesis, ending(1, Some(&signers[0])), ending(2, Some(&signers[1]))];
+    assert_eq!(verify_epoch_transitions(&chain, waypoint), Ok(()));
+
+    // epoch 2 signed by the epoch 1 validator instead of its own
+    let mut tampered = chain.clone();
+    tampered[2] = ending(2, Some(&signers[0]));
+    match verify_epoch_transitions(&tampered, waypoint) {
+        Err(EpochChainError::BadTransition { epoch, .. }) => assert_eq!(epoch, 2),
+        other => panic!("expected a bad transition, got {:?}", other),
+    }
+
+    // a chain which does not start at the waypoint
+    assert!(matches!(
+        verify_epoch_transitions(&chain[1..], waypoint),
+        Err(EpochChainError::NotGenesis(_))
+    ));
+    assert_eq!(verify_epoch_transitions(&[], waypoint), Err(EpochChainError::Empty));
+}
@@ -75,6 +75,10 @@ where
     /// The operator is sending the transaction, used in miner.
     #[options(short = "o", help = "the operator is signing and sending the transaction")]
     pub is_operator: bool,
+
+    /// Currency to pay gas in
+    #[options(help = "currency to pay gas in, defaults to GAS")]
+    pub gas_currency: Option<String>,
 }
 
 impl<Cmd> EntryPoint<Cmd>
@@ -1,16 +1,18 @@
 //! `relay`
 
 #![forbid(unsafe_code)]
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
 
 use crate::{
+  prelude::app_config,
   save_tx,
-  submit_tx::{tx_params_wrapper, wait_for_tx, TxParams},
+  submit_tx::{tx_params_wrapper, TxParams, TX_TIMEOUT},
 };
 use anyhow::Error;
 use cli::diem_client::DiemClient;
 use diem_json_rpc_types::views::TransactionView;
 use diem_types::transaction::SignedTransaction;
+use ol::node::client::submit_and_wait_blocking;
 use ol_types::config::TxType;
 
 /// submit a previously signed tx, perhaps to be submitted by a different account than the signer account.
@@ -19,30 +21,8 @@ pub fn relay_tx(
   txn: SignedTransaction,
   // original_signer: AccountAddress,
 ) -> Result<TransactionView, Error> {
-  let mut client = DiemClient::new(tx_params.url.to_owned(), tx_params.waypoint).unwrap();
-
-  let original_signer = txn.sender();
-  // let chain_id = ChainId::new(client.get_metadata().unwrap().chain_id);
-  let account_state = client.get_account(&original_signer).unwrap();
-
-  let original_signer_sequence_number = match account_state {
-    Some(av) => av.sequence_number,
-    None => 0,
-  };
-  // Submit the transaction with diem_client
-  match client.submit_transaction(&txn) {
-    Ok(_) => {
-      match wait_for_tx(
-        original_signer,
-        original_signer_sequence_number,
-        &mut client,
-      ) {
-        Some(res) => Ok(res),
-        None => Err(Error::msg("No Transaction View returned")),
-      }
-    }
-    Err(err) => Err(err),
-  }
+  let client = DiemClient::new(tx_params.url.to_owned(), tx_params.waypoint)?;
+  submit_and_wait_blocking(Arc::new(client), &app_config(), &txn, TX_TIMEOUT)
 }
 
 /// submit transaction from a file with batch of signed transactions
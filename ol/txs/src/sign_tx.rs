@@ -23,7 +23,7 @@ pub fn sign_tx(
     sequence_number,
     tx_params.tx_cost.max_gas_unit_for_tx,
     tx_params.tx_cost.coin_price_per_unit,
-    "GAS".parse().unwrap(),
+    tx_params.gas_currency.clone(),
     tx_params.tx_cost.user_tx_timeout as i64, // for compatibility with UTC's timestamp.
     chain_id,
   )
@@ -16,11 +16,12 @@ use diem_crypto::{
 use diem_global_constants::OPERATOR_KEY;
 use diem_json_rpc_types::views::{TransactionView, VMStatusView};
 use diem_secure_storage::{CryptoStorage, Namespaced, OnDiskStorage, Storage};
-use diem_types::{account_address::AccountAddress, waypoint::Waypoint};
+use diem_types::{account_address::AccountAddress, account_config::GAS_NAME, waypoint::Waypoint};
 use diem_types::{
     chain_id::ChainId,
     transaction::{authenticator::AuthenticationKey, SignedTransaction, TransactionPayload},
 };
+use ol::node::client::{check_gas_currency, submit_and_wait_blocking};
 use ol_keys::{scheme::KeyScheme, wallet};
 
 use diem_wallet::WalletLibrary;
@@ -30,11 +31,7 @@ use ol_types::{
     fixtures,
 };
 use reqwest::Url;
-use std::{
-    io::{stdout, Write},
-    path::PathBuf,
-    thread, time,
-};
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
 /// All the parameters needed for a client transaction.
 #[derive(Debug)]
@@ -62,6 +59,8 @@ pub struct TxParams {
     // pub user_tx_timeout: u64, // for compatibility with UTC's timestamp.
     /// Chain id
     pub chain_id: ChainId,
+    /// Code of the currency the gas is paid in
+    pub gas_currency: String,
 }
 
 #[derive(Debug)]
@@ -170,6 +169,8 @@ fn stage(
 ) -> Result<(AccountData, SignedTransaction), TxError> {
     match client.get_metadata() {
         Ok(meta) => {
+            // the node would reject gas in a currency it does not know with a less clear error
+            check_gas_currency(client, &tx_params.gas_currency)?;
             if let Some(av) = client.get_account(&tx_params.signer_address)? {
                 let sequence_number = av.sequence_number;
                 // Sign the transaction script
@@ -201,20 +202,18 @@ fn stage(
     }
 }
 
-/// Submit a transaction to the network.
+/// How long to wait for a submitted transaction to be committed.
+pub const TX_TIMEOUT: Duration = Duration::from_secs(360);
+
+/// Submit a transaction to the network, and wait for it to be committed. A transaction above
+/// the user's gas ceiling does not reach the network, and one rejected while the node is
+/// congested is retried.
 pub fn submit_tx(
-    mut client: DiemClient,
+    client: DiemClient,
     txn: SignedTransaction,
     mut _signer_account_data: &mut AccountData,
 ) -> Result<TransactionView, Error> {
-    // Submit the transaction with diem_client
-    match client.submit_transaction(&txn) {
-        Ok(_) => match wait_for_tx(txn.sender(), txn.sequence_number(), &mut client) {
-            Some(res) => Ok(res),
-            None => Err(Error::msg("No Transaction View returned")),
-        },
-        Err(err) => Err(err),
-    }
+    submit_and_wait_blocking(Arc::new(client), &app_config(), &txn, TX_TIMEOUT)
 }
 
 /// Main get tx params logic based on the design in this URL:
@@ -227,10 +226,11 @@ pub fn tx_params_wrapper(tx_type: TxType) -> Result<TxParams, Error> {
         swarm_persona,
         is_operator,
         use_upstream_url,
+        gas_currency,
         ..
     } = entrypoint::get_args();
     let app_config = app_config().clone();
-    tx_params(
+    let mut params = tx_params(
         app_config,
         url,
         waypoint,
@@ -240,7 +240,11 @@ pub fn tx_params_wrapper(tx_type: TxType) -> Result<TxParams, Error> {
         is_operator,
         use_upstream_url,
         None,
-    )
+    )?;
+    if let Some(currency) = gas_currency {
+        params.gas_currency = currency;
+    }
+    Ok(params)
 }
 
 /// tx_parameters format
@@ -325,6 +329,7 @@ pub fn get_tx_params_from_swarm(
         },
 
         chain_id: ChainId::new(4),
+        gas_currency: GAS_NAME.to_owned(),
     };
 
     println!("Info: Got tx params from swarm");
@@ -369,6 +374,7 @@ pub fn get_oper_params(
         keypair,
         tx_cost,
         chain_id: ChainId::new(1),
+        gas_currency: GAS_NAME.to_owned(),
     })
 }
 
@@ -414,6 +420,7 @@ pub fn get_tx_params_from_toml(
         // coin_price_per_unit: config.tx_configs.management_txs.coin_price_per_unit, // in micro_gas
         // user_tx_timeout: config.tx_configs.management_txs.user_tx_timeout,
         chain_id,
+        gas_currency: GAS_NAME.to_owned(),
     };
 
     Ok(tx_params)
@@ -448,51 +455,12 @@ pub fn get_tx_params_from_keypair(
         keypair,
         tx_cost: config.tx_configs.get_cost(tx_type),
         chain_id,
+        gas_currency: GAS_NAME.to_owned(),
     };
 
     Ok(tx_params)
 }
 
-/// Wait for the response from the diem RPC.
-pub fn wait_for_tx(
-    signer_address: AccountAddress,
-    sequence_number: u64,
-    client: &mut DiemClient,
-) -> Option<TransactionView> {
-    println!(
-        "\nAwaiting tx status \nSubmitted from account: {} with sequence number: {}",
-        signer_address, sequence_number
-    );
-
-    const MAX_ITERATIONS: u8 = 120;
-
-    let mut iter = 0;
-    loop {
-        thread::sleep(time::Duration::from_millis(3_000));
-        // prevent all the logging the client does while
-        // it loops through the query.
-        stdout().flush().unwrap();
-
-        match &mut client.get_txn_by_acc_seq(&signer_address, sequence_number, false) {
-            Ok(Some(txn_view)) => {
-                return Some(txn_view.to_owned());
-            }
-            Err(e) => {
-                println!("Response with error: {:?}", e);
-            }
-            _ => {
-                print!(".");
-            }
-        }
-        iter += 1;
-
-        if iter == MAX_ITERATIONS {
-            println!("Timeout waiting for response");
-            return None;
-        }
-    }
-}
-
 /// Evaluate the response of a submitted txs transaction.
 pub fn eval_tx_status(result: TransactionView) -> Result<TransactionView, TxError> {
     match &result.vm_status {
@@ -555,6 +523,7 @@ impl TxParams {
             // coin_price_per_unit: 1, // in micro_gas
             // user_tx_timeout: 5_000,
             chain_id: ChainId::new(4), // swarm/testnet
+            gas_currency: GAS_NAME.to_owned(),
         }
     }
 }
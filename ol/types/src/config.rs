@@ -1,8 +1,9 @@
 //! Configs for all 0L apps.
 
 use anyhow::{bail, Error};
-use diem_config::config::NodeConfig;
+use diem_config::config::{NodeConfig, WaypointConfig};
 use diem_global_constants::{CONFIG_FILE, NODE_HOME};
+use diem_secure_storage::{KVStorage, Storage};
 use diem_types::{
     account_address::AccountAddress, transaction::authenticator::AuthenticationKey,
     waypoint::Waypoint,
@@ -413,11 +414,111 @@ pub struct Profile {
     /// Node URL and and port to submit transactions. Defaults to localhost:8080
     pub default_node: Option<Url>,
 
+    /// Local nodes, e.g. a full node and a validator on this host, tried in this order for
+    /// the first healthy one. Only `default_node` is used if empty.
+    #[serde(default)]
+    pub local_nodes: Vec<Url>,
+
     /// Other nodes to connect for fallback connections
     pub upstream_nodes: Option<Vec<Url>>,
 
+    /// Upstreams tried in this order before the shuffled `upstream_nodes`,
+    /// whether or not they are also listed there.
+    #[serde(default)]
+    pub preferred_upstreams: Vec<Url>,
+
     /// Link to another delay tower.
     pub tower_link: Option<String>,
+
+    /// Try upstream nodes over https first, falling back to http.
+    /// Leave off for plain-http deployments to skip the failed https attempt.
+    #[serde(default)]
+    pub probe_https_first: bool,
+
+    /// Upstreams whose latest ledger timestamp is further behind wall-clock than this
+    /// many seconds are considered stale and skipped.
+    #[serde(default = "default_max_upstream_lag_secs")]
+    pub max_upstream_lag_secs: u64,
+
+    /// Whether to use the local node, an upstream, or pick based on sync state.
+    #[serde(default)]
+    pub client_preference: ClientPreference,
+
+    /// Older waypoints, besides the configured one, which upstreams may verify from.
+    /// Lets nodes on an earlier epoch be used. Each entry is a root of trust.
+    #[serde(default)]
+    pub trusted_waypoints: Vec<Waypoint>,
+
+    /// With no waypoint in key_store.json or 0L.toml, take one from the `upstream_nodes`.
+    /// This trusts those nodes not to serve a fork, so it is off unless the user opts in.
+    #[serde(default)]
+    pub allow_waypoint_bootstrap: bool,
+
+    /// How many times to make the local/remote client decision before giving up.
+    /// Upstreams are probed afresh on every attempt.
+    #[serde(default = "default_pick_client_attempts")]
+    pub pick_client_attempts: u32,
+
+    /// Milliseconds to wait between client decision attempts.
+    #[serde(default = "default_pick_client_retry_delay_ms")]
+    pub pick_client_retry_delay_ms: u64,
+
+    /// Milliseconds ledger metadata fetched while picking a client is reused before
+    /// being fetched again.
+    #[serde(default = "default_metadata_cache_ttl_ms")]
+    pub metadata_cache_ttl_ms: u64,
+
+    /// Nodes whose metadata reports an older `DiemVersion` than this are not used.
+    /// No minimum if unset.
+    #[serde(default)]
+    pub min_diem_version: Option<u64>,
+}
+
+/// Which node the client may connect to.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClientPreference {
+    /// use the local node when it is in sync, otherwise an upstream
+    Auto,
+    /// only the local node, never query upstreams
+    LocalOnly,
+    /// only upstream nodes, never the local node
+    RemoteOnly,
+}
+
+impl Default for ClientPreference {
+    fn default() -> Self {
+        ClientPreference::Auto
+    }
+}
+
+impl FromStr for ClientPreference {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ClientPreference::Auto),
+            "local-only" => Ok(ClientPreference::LocalOnly),
+            "remote-only" => Ok(ClientPreference::RemoteOnly),
+            _ => bail!("unknown client preference {}, expected auto, local-only or remote-only", s),
+        }
+    }
+}
+
+fn default_max_upstream_lag_secs() -> u64 {
+    300
+}
+
+fn default_pick_client_attempts() -> u32 {
+    1
+}
+
+fn default_pick_client_retry_delay_ms() -> u64 {
+    2_000
+}
+
+fn default_metadata_cache_ttl_ms() -> u64 {
+    2_000
 }
 
 impl Default for Profile {
@@ -432,8 +533,19 @@ impl Default for Profile {
             ip: "0.0.0.0".parse().unwrap(),
             vfn_ip: "0.0.0.0".parse().ok(),
             default_node: Some("http://localhost:8080".parse().expect("parse url")),
+            local_nodes: vec![],
             upstream_nodes: Some(vec!["http://localhost:8080".parse().expect("parse url")]),
+            preferred_upstreams: vec![],
             tower_link: None,
+            probe_https_first: false,
+            max_upstream_lag_secs: default_max_upstream_lag_secs(),
+            client_preference: ClientPreference::default(),
+            trusted_waypoints: vec![],
+            allow_waypoint_bootstrap: false,
+            pick_client_attempts: default_pick_client_attempts(),
+            pick_client_retry_delay_ms: default_pick_client_retry_delay_ms(),
+            metadata_cache_ttl_ms: default_metadata_cache_ttl_ms(),
+            min_diem_version: None,
         }
     }
 }
@@ -469,6 +581,9 @@ pub struct TxConfigs {
     /// Cheap or test transation costs
     #[serde(default = "default_cheap_txs_cost")]
     pub cheap_txs_cost: Option<TxCost>,
+    /// Highest max gas units the client will submit, regardless of the protocol limit.
+    #[serde(default)]
+    pub max_gas_ceiling: Option<u64>,
 }
 
 impl TxConfigs {
@@ -518,6 +633,7 @@ impl Default for TxConfigs {
             management_txs_cost: default_management_txs_cost(),
             miner_txs_cost: default_miner_txs_cost(),
             cheap_txs_cost: default_cheap_txs_cost(),
+            max_gas_ceiling: None,
         }
     }
 }
@@ -539,16 +655,49 @@ fn default_cheap_txs_cost() -> Option<TxCost> {
 }
 
 /// Get swarm configs from swarm files, swarm must be running
-pub fn get_swarm_rpc_url(mut swarm_path: PathBuf) -> (Url, Waypoint) {
+pub fn get_swarm_rpc_url(swarm_path: PathBuf) -> (Url, Waypoint) {
+    try_get_swarm_rpc_url(swarm_path).unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// Like `get_swarm_rpc_url`, but returns an error instead of panicking when the
+/// swarm files are missing or unreadable.
+pub fn try_get_swarm_rpc_url(mut swarm_path: PathBuf) -> Result<(Url, Waypoint), Error> {
     swarm_path.push("0/node.yaml");
-    let config = NodeConfig::load(&swarm_path)
-        .unwrap_or_else(|_| panic!("Failed to load NodeConfig from file: {:?}", &swarm_path));
+    let config = NodeConfig::load(&swarm_path).map_err(|e| {
+        Error::msg(format!(
+            "Failed to load NodeConfig from file: {:?}, {}",
+            &swarm_path, e
+        ))
+    })?;
 
-    let url = Url::parse(format!("http://localhost:{}", config.json_rpc.address.port()).as_str())
-        .unwrap();
-    let waypoint = config.base.waypoint.waypoint();
+    let url = Url::parse(format!("http://localhost:{}", config.json_rpc.address.port()).as_str())?;
+    let waypoint = try_get_waypoint(&config.base.waypoint)?;
 
-    (url, waypoint)
+    Ok((url, waypoint))
+}
+
+/// Like `WaypointConfig::waypoint`, but returns an error instead of panicking when there is
+/// no waypoint or it cannot be read.
+fn try_get_waypoint(waypoint: &WaypointConfig) -> Result<Waypoint, Error> {
+    match waypoint {
+        WaypointConfig::FromConfig(waypoint) => Ok(*waypoint),
+        WaypointConfig::FromFile(path) => {
+            let content = fs::read_to_string(path).map_err(|e| {
+                Error::msg(format!("Failed to read waypoint file {}: {}", path.display(), e))
+            })?;
+            Waypoint::from_str(content.trim()).map_err(|e| {
+                Error::msg(format!("Failed to parse waypoint {}: {}", content.trim(), e))
+            })
+        }
+        WaypointConfig::FromStorage(backend) => {
+            let storage: Storage = backend.into();
+            storage
+                .get::<Waypoint>(diem_global_constants::WAYPOINT)
+                .map(|response| response.value)
+                .map_err(|e| Error::msg(format!("Unable to read waypoint from storage: {}", e)))
+        }
+        WaypointConfig::None => bail!("no waypoint is configured"),
+    }
 }
 
 /// Get swarm configs from swarm files, swarm must be running
@@ -611,3 +760,20 @@ pub fn bootstrap_waypoint_from_rpc(url: Url) -> Result<Waypoint, Error> {
     bail!("could not get waypoint from json-rpc, url: {:?} ", url)
 }
 
+
+#[test]
+fn test_parse_client_preference() {
+    assert_eq!(ClientPreference::default(), ClientPreference::Auto);
+    assert_eq!("local-only".parse::<ClientPreference>().unwrap(), ClientPreference::LocalOnly);
+    assert_eq!("remote-only".parse::<ClientPreference>().unwrap(), ClientPreference::RemoteOnly);
+    assert!("local".parse::<ClientPreference>().is_err());
+}
+
+#[test]
+fn test_missing_waypoint_is_an_error() {
+    assert!(try_get_waypoint(&WaypointConfig::None).is_err());
+    let missing = PathBuf::from("/nonexistent/waypoint.txt");
+    assert!(try_get_waypoint(&WaypointConfig::FromFile(missing)).is_err());
+    let waypoint = Waypoint::from_str(BASE_WAYPOINT).unwrap();
+    assert_eq!(try_get_waypoint(&WaypointConfig::FromConfig(waypoint)).unwrap(), waypoint);
+}
@@ -43,6 +43,15 @@ impl Error {
         self.inner.json_rpc_error.as_ref()
     }
 
+    //////// 0L ////////
+    /// The HTTP status the node answered with, if the request failed on one.
+    pub fn http_status(&self) -> Option<u16> {
+        match self.inner.kind {
+            Kind::HttpStatus(status) => Some(status),
+            _ => None,
+        }
+    }
+
     pub fn is_retriable(&self) -> bool {
         match self.inner.kind {
             // internal server errors are retriable
@@ -7,6 +7,7 @@ use diem_logger::prelude::info;
 use diem_types::{
     account_address::AccountAddress,
     account_state_blob::AccountStateBlob,
+    chain_id::ChainId,
     epoch_change::EpochChangeProof,
     event::EventKey,
     ledger_info::LedgerInfoWithSignatures,
@@ -15,7 +16,10 @@ use diem_types::{
     waypoint::Waypoint,
 };
 use reqwest::Url;
-use std::time::Duration;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 
 /// A client connection to an AdmissionControl (AC) service. `DiemClient` also
 /// handles verifying the server's responses, retrying on non-fatal failures, and
@@ -39,6 +43,11 @@ pub struct DiemClient {
     /// about our local [`Waypoint`] and have not yet ratcheted to the remote's
     /// latest state.
     latest_epoch_change_li: Option<LedgerInfoWithSignatures>,
+    //////// 0L ////////
+    /// If set, responses from a node on any other chain are rejected.
+    expected_chain_id: Option<ChainId>,
+    /// Whether the node was seen on `expected_chain_id`, so transactions can be sent to it.
+    chain_id_checked: AtomicBool,
 }
 
 impl DiemClient {
@@ -51,16 +60,51 @@ impl DiemClient {
             client,
             trusted_state: initial_trusted_state,
             latest_epoch_change_li: None,
+            expected_chain_id: None,
+            chain_id_checked: AtomicBool::new(false),
         })
     }
 
+    //////// 0L ////////
+    /// Bind the client to `chain_id`. Every call then fails if the node reports another chain.
+    /// `wait_for_transaction` is not checked, since it polls an already submitted transaction.
+    pub fn with_chain_id(mut self, chain_id: ChainId) -> Self {
+        self.expected_chain_id = Some(chain_id);
+        self
+    }
+
+    /// Unwraps a response, rejecting it if it comes from a chain other than the expected one.
+    fn check_chain_id<T>(&self, response: Response<T>) -> Result<T> {
+        if let Some(expected) = self.expected_chain_id {
+            let actual = response.state().chain_id;
+            ensure!(
+                actual == expected.id(),
+                "Node is on chain id {}, expected chain id {}",
+                actual,
+                expected.id(),
+            );
+            self.chain_id_checked.store(true, Ordering::Relaxed);
+        }
+        Ok(response.into_inner())
+    }
+
+    /// Fetches the metadata once, unless a response was already checked, so a node on another
+    /// chain is caught before anything is sent to it rather than after.
+    fn ensure_chain_id(&self) -> Result<()> {
+        if self.expected_chain_id.is_some() && !self.chain_id_checked.load(Ordering::Relaxed) {
+            self.get_metadata()?;
+        }
+        Ok(())
+    }
+
     /// Submits a transaction and bumps the sequence number for the sender, pass in `None` for
     /// sender_account if sender's address is not managed by the client.
     pub fn submit_transaction(&self, transaction: &SignedTransaction) -> Result<()> {
+        self.ensure_chain_id()?;
         self.client
             .submit(transaction)
             .map_err(Into::into)
-            .map(Response::into_inner)
+            .and_then(|response| self.check_chain_id(response))
     }
 
     /// Retrieves account information
@@ -69,7 +113,7 @@ impl DiemClient {
         self.client
             .get_account(*account)
             .map_err(Into::into)
-            .map(Response::into_inner)
+            .and_then(|response| self.check_chain_id(response))
     }
 
     ///////// 0L ////////
@@ -81,7 +125,7 @@ impl DiemClient {
         self.client
             .get_miner_state(*account)
             .map_err(Into::into)
-            .map(Response::into_inner)
+            .and_then(|response| self.check_chain_id(response))
     }
 
     ///////// 0L ////////
@@ -90,7 +134,7 @@ impl DiemClient {
         self.client
             .get_oracle_upgrade_state()
             .map_err(Into::into)
-            .map(Response::into_inner)
+            .and_then(|response| self.check_chain_id(response))
     }
 
       ///////// 0L ////////
@@ -99,17 +143,17 @@ impl DiemClient {
         self.client
             .get_waypoint()
             .map_err(Into::into)
-            .map(Response::into_inner)
+            .and_then(|response| self.check_chain_id(response))
     }
 
     pub fn get_account_state_blob(
         &self,
         account: &AccountAddress,
     ) -> Result<(Option<AccountStateBlob>, Version)> {
-        let ret = self
+        let response = self
             .client
-            .get_account_state_with_proof(*account, None, None)
-            .map(Response::into_inner)?;
+            .get_account_state_with_proof(*account, None, None)?;
+        let ret = self.check_chain_id(response)?;
         if let Some(blob) = ret.blob {
             Ok((Some(bcs::from_bytes(&blob)?), ret.version))
         } else {
@@ -126,7 +170,7 @@ impl DiemClient {
         self.client
             .get_events(event_key, start, limit)
             .map_err(Into::into)
-            .map(Response::into_inner)
+            .and_then(|response| self.check_chain_id(response))
     }
 
     pub fn wait_for_transaction(
@@ -144,7 +188,7 @@ impl DiemClient {
         self.client
             .get_metadata()
             .map_err(Into::into)
-            .map(Response::into_inner)
+            .and_then(|response| self.check_chain_id(response))
     }
 
     /// Gets the currency info stored on-chain
@@ -152,19 +196,28 @@ impl DiemClient {
         self.client
             .get_currencies()
             .map_err(Into::into)
-            .map(Response::into_inner)
+            .and_then(|response| self.check_chain_id(response))
     }
 
     /// Retrieves and checks the state proof
     pub fn update_and_verify_state_proof(&mut self) -> Result<()> {
-        let state_proof = self
+        let response = self
             .client
-            .get_state_proof(self.trusted_state().version())
-            .map(Response::into_inner)?;
+            .get_state_proof(self.trusted_state().version())?;
+        let state_proof = self.check_chain_id(response)?;
 
         self.verify_state_proof(state_proof)
     }
 
+    //////// 0L ////////
+    /// Epoch change ledger infos from the epoch of `known_version` to the node's current
+    /// epoch, unverified. `more` is set if the node truncated the list.
+    pub fn get_epoch_change_proof(&self, known_version: Version) -> Result<EpochChangeProof> {
+        let response = self.client.get_state_proof(known_version)?;
+        let state_proof = self.check_chain_id(response)?;
+        Ok(bcs::from_bytes(&state_proof.epoch_change_proof)?)
+    }
+
     fn verify_state_proof(&mut self, state_proof: views::StateProofView) -> Result<()> {
         let state = self.trusted_state();
 
@@ -248,7 +301,7 @@ impl DiemClient {
         self.client
             .get_account_transaction(*account, sequence_number, fetch_events)
             .map_err(Into::into)
-            .map(Response::into_inner)
+            .and_then(|response| self.check_chain_id(response))
     }
 
     // 0L todo: Not sure if it is possible to implement this fn with diem 1.3.0 code
@@ -265,7 +318,7 @@ impl DiemClient {
       self.client
             .get_account_transactions(account, start_height, num_txs_limit, fetch_events)
             .map_err(Into::into)
-            .map(Response::into_inner)
+            .and_then(|response| self.check_chain_id(response))
     }
 
     /// Get transactions in range (start_version..start_version + limit - 1) from validator.
@@ -278,6 +331,6 @@ impl DiemClient {
         self.client
             .get_transactions(start_version, limit, fetch_events)
             .map_err(Into::into)
-            .map(Response::into_inner)
+            .and_then(|response| self.check_chain_id(response))
     }
 }
@@ -0,0 +1,55 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//////// 0L ////////
+use anyhow::{bail, Result};
+use diem_crypto::HashValue;
+use serde::{Deserialize, Serialize};
+
+/// Layout version of `UpgradeEvent`, its first byte. Any change to the fields, or to their
+/// order, needs a new version, since every validator must emit byte-identical events.
+pub const UPGRADE_EVENT_VERSION: u8 = 1;
+
+/// Describes a stdlib upgrade applied by the VM. The VM does not emit it yet; whatever does
+/// must serialize it with `serialize_upgrade_event`, and read it with
+/// `deserialize_upgrade_event`, which pin the layout.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct UpgradeEvent {
+    /// always `UPGRADE_EVENT_VERSION` for events written by this code
+    pub version: u8,
+    /// round of the block which applied the upgrade
+    pub round: u64,
+    /// timestamp of that block, in microseconds
+    pub timestamp: u64,
+    /// number of modules published
+    pub module_count: u64,
+    /// sha3-256 of the upgrade payload
+    pub payload_hash: HashValue,
+}
+
+/// The canonical BCS bytes of an `UpgradeEvent`, at the current `UPGRADE_EVENT_VERSION`.
+pub fn serialize_upgrade_event(
+    round: u64,
+    timestamp: u64,
+    module_count: u64,
+    payload_hash: HashValue,
+) -> Vec<u8> {
+    let event = UpgradeEvent {
+        version: UPGRADE_EVENT_VERSION,
+        round,
+        timestamp,
+        module_count,
+        payload_hash,
+    };
+    bcs::to_bytes(&event).expect("an upgrade event always serializes")
+}
+
+/// Reads bytes written by `serialize_upgrade_event`. The version is checked before anything
+/// else is decoded, so a layout this code does not know is an error, not a misread.
+pub fn deserialize_upgrade_event(bytes: &[u8]) -> Result<UpgradeEvent> {
+    match bytes.first() {
+        Some(&UPGRADE_EVENT_VERSION) => Ok(bcs::from_bytes(bytes)?),
+        Some(version) => bail!("unknown upgrade event version {}", version),
+        None => bail!("empty upgrade event"),
+    }
+}
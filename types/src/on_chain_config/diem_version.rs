@@ -26,3 +26,35 @@ pub const DIEM_VERSION_3: DiemVersion = DiemVersion { major: 3 };
 
 // Maximum current known version
 pub const DIEM_MAX_KNOWN_VERSION: DiemVersion = DIEM_VERSION_3;
+
+//////// 0L ////////
+/// What a `DiemVersion` supports. Version-gated features are checked here, so the VM and
+/// tooling agree on what each version allows; add new gated features as they land.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VersionCapabilities {
+    major: u64,
+}
+
+impl VersionCapabilities {
+    pub fn new(version: &DiemVersion) -> Self {
+        Self {
+            major: version.major,
+        }
+    }
+
+    /// The `ScriptFunction` payload type, and remapping known scripts to script functions.
+    pub fn supports_script_functions(&self) -> bool {
+        self.major >= DIEM_VERSION_2.major
+    }
+
+    /// Multi-agent transactions, with secondary signers.
+    pub fn supports_multi_agent(&self) -> bool {
+        self.major >= DIEM_VERSION_3.major
+    }
+}
+
+impl From<&DiemVersion> for VersionCapabilities {
+    fn from(version: &DiemVersion) -> Self {
+        Self::new(version)
+    }
+}
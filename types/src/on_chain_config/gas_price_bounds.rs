@@ -0,0 +1,41 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//////// 0L ////////
+use crate::on_chain_config::OnChainConfig;
+use move_core_types::identifier::{IdentStr, Identifier};
+use serde::{Deserialize, Serialize};
+
+/// Gas unit price bounds for individual gas currencies. A currency without an entry is held
+/// to `min_price_per_gas_unit` and `max_price_per_gas_unit` of the gas schedule.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CurrencyGasPriceBounds {
+    bounds: Vec<GasPriceBound>,
+}
+
+/// The gas unit prices a transaction paying in `currency_code` may offer.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct GasPriceBound {
+    pub currency_code: Identifier,
+    pub min_price_per_gas_unit: u64,
+    pub max_price_per_gas_unit: u64,
+}
+
+impl CurrencyGasPriceBounds {
+    pub fn new(bounds: Vec<GasPriceBound>) -> Self {
+        Self { bounds }
+    }
+
+    /// The bounds configured for `currency_code`, if any.
+    pub fn bounds_for(&self, currency_code: &IdentStr) -> Option<&GasPriceBound> {
+        self.bounds
+            .iter()
+            .find(|bound| bound.currency_code.as_ident_str() == currency_code)
+    }
+}
+
+// Not in `ON_CHAIN_CONFIG_REGISTRY`: the config is optional, and a chain without it uses the
+// gas schedule's bounds for every currency.
+impl OnChainConfig for CurrencyGasPriceBounds {
+    const IDENTIFIER: &'static str = "GasPriceBounds";
+}
@@ -18,16 +18,22 @@ use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{collections::HashMap, fmt, sync::Arc};
 
 mod diem_version;
+mod gas_price_bounds;
 mod registered_currencies;
+mod upgrade_config;
 mod validator_set;
 mod vm_config;
 mod vm_publishing_option;
 
 pub use self::{
-    diem_version::{DiemVersion, DIEM_MAX_KNOWN_VERSION, DIEM_VERSION_2, DIEM_VERSION_3},
+    diem_version::{
+        DiemVersion, VersionCapabilities, DIEM_MAX_KNOWN_VERSION, DIEM_VERSION_2, DIEM_VERSION_3,
+    },
+    gas_price_bounds::{CurrencyGasPriceBounds, GasPriceBound},
     registered_currencies::RegisteredCurrencies,
+    upgrade_config::{UpgradeConfig, DEFAULT_ORACLE_TICK_ROUND, DEFAULT_UPGRADE_APPLY_ROUND},
     validator_set::ValidatorSet,
-    vm_config::VMConfig,
+    vm_config::{TransactionLimits, VMConfig},
     vm_publishing_option::VMPublishingOption,
 };
 
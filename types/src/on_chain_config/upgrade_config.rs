@@ -0,0 +1,44 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//////// 0L ////////
+use crate::on_chain_config::OnChainConfig;
+use serde::{Deserialize, Serialize};
+
+/// Block round at which the upgrade oracle records an elected payload, unless configured
+/// otherwise.
+pub const DEFAULT_ORACLE_TICK_ROUND: u64 = 2;
+/// Block round at which an elected stdlib upgrade is applied, unless configured otherwise.
+pub const DEFAULT_UPGRADE_APPLY_ROUND: u64 = 2;
+
+/// When and how the VM runs the upgrade oracle and applies an elected stdlib upgrade. These
+/// change block output, so they are read from chain for every validator to agree on.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct UpgradeConfig {
+    /// block round at which the upgrade oracle records an elected payload
+    pub oracle_tick_round: u64,
+    /// block round at which an elected payload is applied
+    pub upgrade_apply_round: u64,
+    /// gas units the oracle tick may use before it is skipped for the block, 0 for unmetered
+    pub oracle_tick_gas_budget: u64,
+    /// whether an upgrade may publish a framework identical to, or older than, the one on
+    /// chain, e.g. to roll back a broken upgrade
+    pub allow_upgrade_downgrade: bool,
+}
+
+impl Default for UpgradeConfig {
+    fn default() -> Self {
+        Self {
+            oracle_tick_round: DEFAULT_ORACLE_TICK_ROUND,
+            upgrade_apply_round: DEFAULT_UPGRADE_APPLY_ROUND,
+            oracle_tick_gas_budget: 0,
+            allow_upgrade_downgrade: false,
+        }
+    }
+}
+
+// Not in `ON_CHAIN_CONFIG_REGISTRY`: the config is optional, and a chain without it uses
+// `UpgradeConfig::default()`.
+impl OnChainConfig for UpgradeConfig {
+    const IDENTIFIER: &'static str = "UpgradeConfig";
+}
@@ -3,7 +3,7 @@
 
 use crate::on_chain_config::OnChainConfig;
 use anyhow::{format_err, Result};
-use move_core_types::gas_schedule::{CostTable, GasConstants};
+use move_core_types::gas_schedule::{CostTable, GasAlgebra, GasConstants};
 use serde::{Deserialize, Serialize};
 
 /// Defines all the on chain configuration data needed by VM.
@@ -50,3 +50,24 @@ impl OnChainConfig for VMConfig {
         Ok(VMConfig { gas_schedule })
     }
 }
+
+//////// 0L ////////
+/// The transaction size and gas bounds enforced by the VM, as plain numbers.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct TransactionLimits {
+    pub max_transaction_size_in_bytes: u64,
+    pub maximum_number_of_gas_units: u64,
+    pub min_price_per_gas_unit: u64,
+    pub max_price_per_gas_unit: u64,
+}
+
+impl From<&GasConstants> for TransactionLimits {
+    fn from(gas_constants: &GasConstants) -> Self {
+        Self {
+            max_transaction_size_in_bytes: gas_constants.max_transaction_size_in_bytes,
+            maximum_number_of_gas_units: gas_constants.maximum_number_of_gas_units.get(),
+            min_price_per_gas_unit: gas_constants.min_price_per_gas_unit.get(),
+            max_price_per_gas_unit: gas_constants.max_price_per_gas_unit.get(),
+        }
+    }
+}
@@ -0,0 +1,15 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::on_chain_config::{VersionCapabilities, DIEM_VERSION_2, DIEM_VERSION_3};
+
+#[test]
+fn test_version_capabilities() {
+    let v2 = VersionCapabilities::new(&DIEM_VERSION_2);
+    assert!(v2.supports_script_functions());
+    assert!(!v2.supports_multi_agent());
+
+    let v3 = VersionCapabilities::from(&DIEM_VERSION_3);
+    assert!(v3.supports_script_functions());
+    assert!(v3.supports_multi_agent());
+}
@@ -7,6 +7,8 @@ mod canonical_serialization_examples;
 mod code_debug_fmt_test;
 mod contract_event_test;
 mod currency_code_test;
+mod diem_version_test;
+mod ol_upgrade_event_test;
 mod transaction_test;
 mod trusted_state_test;
 mod validator_set_test;
@@ -0,0 +1,51 @@
+// Copyright (c) The Diem Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::ol_upgrade_event::{
+    deserialize_upgrade_event, serialize_upgrade_event, UpgradeEvent, UPGRADE_EVENT_VERSION,
+};
+use diem_crypto::HashValue;
+
+#[test]
+fn upgrade_event_golden_bytes() {
+    let bytes = serialize_upgrade_event(2, 1_000_000, 3, HashValue::new([0xAB; 32]));
+
+    let mut expected = vec![
+        0x01, // version
+        0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // round
+        0x40, 0x42, 0x0F, 0x00, 0x00, 0x00, 0x00, 0x00, // timestamp
+        0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // module_count
+        0x20, // payload_hash length
+    ];
+    expected.extend_from_slice(&[0xAB; 32]);
+    assert_eq!(bytes, expected);
+}
+
+#[test]
+fn upgrade_event_round_trips() {
+    let payload_hash = HashValue::sha3_256_of(b"payload");
+    let bytes = serialize_upgrade_event(7, u64::MAX, 0, payload_hash);
+    assert_eq!(
+        deserialize_upgrade_event(&bytes).unwrap(),
+        UpgradeEvent {
+            version: UPGRADE_EVENT_VERSION,
+            round: 7,
+            timestamp: u64::MAX,
+            module_count: 0,
+            payload_hash,
+        }
+    );
+}
+
+#[test]
+fn upgrade_event_of_unknown_version_is_rejected() {
+    let mut bytes = serialize_upgrade_event(2, 1, 1, HashValue::zero());
+    bytes[0] = UPGRADE_EVENT_VERSION + 1;
+    assert!(deserialize_upgrade_event(&bytes).is_err());
+    assert!(deserialize_upgrade_event(&[]).is_err());
+
+    // trailing bytes are not part of any version
+    let mut bytes = serialize_upgrade_event(2, 1, 1, HashValue::zero());
+    bytes.push(0);
+    assert!(deserialize_upgrade_event(&bytes).is_err());
+}